@@ -1,4 +1,3 @@
-mod encode;
 pub mod opcode;
 mod parser;
 mod tests;
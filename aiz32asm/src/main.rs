@@ -13,7 +13,7 @@ fn main() {
     let input = &args[1];
     let output = &args[2];
     let table = opcode_table();
-    let generate_raw = args.get(3).map_or(false, |s| s == "--raw");
+    let generate_raw = args.get(3).is_some_and(|s| s == "--raw");
 
     let content = fs::read_to_string(input).expect("No se pudo leer el archivo .asm");
     let lines: Vec<String> = content.lines().map(|l| l.to_string()).collect();
@@ -1,14 +1,36 @@
-use aiz32core::instruction::Opcode;
+use aiz32core::alu::{Condition, bit_field};
+use aiz32core::instruction::{AddrMode, Instruction, Opcode, REGLIST_HIGH, REGLIST_LR};
+use aiz32core::isa::Operands;
+use aiz32core::packed::PackedOp;
 use std::collections::HashMap;
 
-use crate::utils::{parse_imm, parse_port, parse_reg};
-use crate::{AssembleError, encode::*};
+use crate::AssembleError;
+use crate::utils::{parse_imm, parse_port, parse_reg, reg_index};
 
 pub struct Assembly {
+    /// Dirección de carga del programa, fijada con `.org`.
+    pub origin: u32,
     pub labels: HashMap<String, u32>,
     pub lines: Vec<(u32, Vec<String>)>,
 }
 
+impl Assembly {
+    /// Dirección en bytes de una etiqueta.
+    pub fn address_of(&self, label: &str) -> Option<u32> {
+        self.labels
+            .get(label)
+            .map(|&pc| self.origin.wrapping_add(pc * 4))
+    }
+
+    // Un inmediato puede ser un número o la dirección de una etiqueta
+    fn value(&self, token: &str) -> i64 {
+        match self.address_of(token) {
+            Some(addr) => addr as i64,
+            None => parse_imm(token),
+        }
+    }
+}
+
 pub fn tokenize_line(line: &str) -> Option<Vec<String>> {
     let line = line.split(';').next().unwrap_or("");
     let line = line.trim();
@@ -27,10 +49,93 @@ pub fn tokenize_line(line: &str) -> Option<Vec<String>> {
     }
 }
 
+// Literales de `LDW rd, =valor` pendientes de colocar en el siguiente pool
+#[derive(Default)]
+struct LiteralPool {
+    index: u32,
+    pending: Vec<String>,
+}
+
+impl LiteralPool {
+    // Etiqueta interna del literal en el pool actual
+    fn name(&self, literal: &str) -> String {
+        format!("{}@{}", literal, self.index)
+    }
+
+    // Cada pool guarda una sola copia de cada valor
+    fn label(&mut self, literal: &str) -> String {
+        if !self.pending.iter().any(|l| l == literal) {
+            self.pending.push(literal.to_string());
+        }
+        self.name(literal)
+    }
+
+    // Emite los literales pendientes como `.word` a partir de `pc`
+    fn flush(
+        &mut self,
+        pc: &mut u32,
+        labels: &mut HashMap<String, u32>,
+        lines: &mut Vec<(u32, Vec<String>)>,
+    ) {
+        for literal in std::mem::take(&mut self.pending) {
+            labels.insert(self.name(&literal), *pc);
+            let value = literal.trim_start_matches('=').to_string();
+            lines.push((*pc, vec![".WORD".to_string(), value]));
+            *pc += 1;
+        }
+        self.index += 1;
+    }
+}
+
+// `{R4-R11, LR}` como inmediato de PUSHM/POPM. Los espacios dentro de la
+// lista parten los tokens, así que se vuelven a pegar los rangos como
+// `R4 - R11`. Una instrucción solo cubre un banco (R1..R16 o R17..R31), así
+// que una lista con registros de los dos es un error
+fn parse_reglist(tokens: &[String]) -> u32 {
+    let list = tokens.join(",").replace(",-", "-").replace("-,", "-");
+    let inner = list
+        .strip_prefix('{')
+        .and_then(|l| l.strip_suffix('}'))
+        .unwrap_or_else(|| panic!("Invalid register list: {}", list));
+
+    // El bit n es Rn y el bit 0 es LR
+    let mut mask = 0u32;
+    for item in inner.split(',').filter(|i| !i.is_empty()) {
+        let (first, last) = match item {
+            "LR" => (0, 0),
+            _ => match item.split_once('-') {
+                Some((first, last)) => (parse_reg(first), parse_reg(last)),
+                None => (parse_reg(item), parse_reg(item)),
+            },
+        };
+        assert!(
+            item == "LR" || (first >= 1 && first <= last),
+            "Invalid register list: {}",
+            list
+        );
+        for reg in first..=last {
+            mask |= 1 << reg;
+        }
+    }
+    assert!(mask != 0, "Empty register list");
+
+    let lr = if mask & 1 != 0 { REGLIST_LR } else { 0 };
+    match ((mask >> 1) & 0xFFFF, mask >> 17) {
+        (low, 0) => low | lr,
+        (0, high) => high | REGLIST_HIGH | lr,
+        _ => panic!(
+            "Register list mixes R1-R16 and R17-R31, use one PUSHM/POPM per bank: {}",
+            list
+        ),
+    }
+}
+
 pub fn first_pass(lines: &[String]) -> Assembly {
+    let mut origin = 0;
     let mut labels = HashMap::new();
     let mut parsed_lines = Vec::new();
     let mut pc: u32 = 0;
+    let mut pool = LiteralPool::default();
 
     for line in lines {
         if let Some(mut tokens) = tokenize_line(line) {
@@ -43,227 +148,272 @@ pub fn first_pass(lines: &[String]) -> Assembly {
                 }
             }
 
-            parsed_lines.push((pc, tokens));
-            pc += 1;
+            // `LDW rd, =valor` carga el valor de un pool con LDPC
+            if tokens[0] == "LDW" && tokens.get(2).is_some_and(|t| t.starts_with('=')) {
+                let label = pool.label(&tokens[2]);
+                tokens = vec!["LDPC".to_string(), tokens[1].clone(), label];
+            }
+
+            match tokens[0].as_str() {
+                ".ORG" => {
+                    assert!(pc == 0, ".org must come before any instruction or data");
+                    origin = parse_imm(&tokens[1]) as u32;
+                    continue;
+                }
+                ".LTORG" => pool.flush(&mut pc, &mut labels, &mut parsed_lines),
+                // Una palabra por valor
+                ".WORD" => {
+                    let words = tokens.len() as u32 - 1;
+                    parsed_lines.push((pc, tokens));
+                    pc += words;
+                }
+                _ => {
+                    parsed_lines.push((pc, tokens));
+                    pc += 1;
+                }
+            }
         }
     }
 
+    // Los literales que quedan van al final del programa
+    pool.flush(&mut pc, &mut labels, &mut parsed_lines);
+
     Assembly {
+        origin,
         labels,
         lines: parsed_lines,
     }
 }
 
-pub fn second_pass(
-    asm: Assembly,
-    table: &HashMap<String, Opcode>,
-) -> Result<Vec<u32>, AssembleError> {
-    let mut result = Vec::new();
-
-    for (current_pc, tokens) in asm.lines {
-        let opcode_str = &tokens[0];
-        let opcode = table
-            .get(opcode_str)
-            .unwrap_or_else(|| panic!("Unknown opcode: {}", opcode_str));
-
-        let encoded = match std::panic::catch_unwind(|| match opcode {
-            Opcode::ADD
-            | Opcode::SUB
-            | Opcode::MUL
-            | Opcode::DIV
-            | Opcode::MOD
-            | Opcode::AND
-            | Opcode::OR
-            | Opcode::XOR
-            | Opcode::NAND
-            | Opcode::NOR
-            | Opcode::XNOR
-            | Opcode::NOT
-            | Opcode::SHL
-            | Opcode::SHR
-            | Opcode::SAR
-            | Opcode::ROL
-            | Opcode::ROR
-            | Opcode::SEXTB
-            | Opcode::ZEXTB
-            | Opcode::POPCNT
-            | Opcode::SETZ
-            | Opcode::SETNZ
-            | Opcode::PASS
-            | Opcode::SEXTH
-            | Opcode::ZEXTH => {
-                let rd = parse_reg(&tokens[1]);
-                let rs1 = parse_reg(&tokens[2]);
-                let rs2 = parse_reg(&tokens[3]);
-                encode_r(*opcode, rd, rs1, rs2)
-            }
-            Opcode::INC | Opcode::DEC | Opcode::NEG | Opcode::ABS => {
-                let rd = parse_reg(&tokens[1]);
-                let rs1 = parse_reg(&tokens[2]);
-                encode_r(*opcode, rd, rs1, 0)
-            }
-            Opcode::CMP | Opcode::UCMP => {
-                let rd = parse_reg(&tokens[1]);
-                let rs1 = parse_reg(&tokens[2]);
-                encode_r(*opcode, rd, rs1, 0)
-            }
-
-            Opcode::NOP => encode_r(*opcode, 0, 0, 0),
-
-            Opcode::ADDI
-            | Opcode::SUBI
-            | Opcode::MULI
-            | Opcode::DIVI
-            | Opcode::MODI
-            | Opcode::ANDI
-            | Opcode::ORI
-            | Opcode::XORI
-            | Opcode::NANDI
-            | Opcode::NORI
-            | Opcode::XNORI
-            | Opcode::NOTI
-            | Opcode::SHLI
-            | Opcode::SHRI
-            | Opcode::SARI
-            | Opcode::ROLI
-            | Opcode::RORI
-            | Opcode::SEXTBI
-            | Opcode::ZEXTBI
-            | Opcode::POPCNTI
-            | Opcode::SETZI
-            | Opcode::SETNZI
-            | Opcode::PASSI
-            | Opcode::SEXTHI
-            | Opcode::ZEXTHI => {
-                let rd = parse_reg(&tokens[1]);
-                let rs1 = parse_reg(&tokens[2]);
-                let imm = parse_imm(&tokens[3], 14);
-                encode_i(*opcode, rd, rs1, imm)
-            }
-            Opcode::CMPI | Opcode::UCMPI => {
-                let rd = parse_reg(&tokens[1]);
-                let imm = parse_imm(&tokens[2], 14);
-                encode_i(*opcode, rd, 0, imm)
-            }
+// Operando de memoria con modo de direccionamiento: `rs1 + rs2*scale`,
+// `rs1+` o `-rs1` una vez quitados los corchetes. `None` si es la forma
+// con desplazamiento de `mem_offset`: solo es indexado si tras la base
+// viene un registro.
+fn parse_address_mode(tokens: &[String]) -> Option<(u8, u8, AddrMode)> {
+    let operand = tokens.concat();
+    if let Some(base) = operand.strip_prefix('-').and_then(reg_index) {
+        return Some((base, 0, AddrMode::PreDecrement));
+    }
+    if let Some(base) = operand.strip_suffix('+').and_then(reg_index) {
+        return Some((base, 0, AddrMode::PostIncrement));
+    }
+    let (base, index) = operand.split_once('+')?;
+    let (index, scale) = index.split_once('*').unwrap_or((index, "1"));
+    let index = reg_index(index)?;
+    let scale = match scale {
+        "1" => 0,
+        "2" => 1,
+        "4" => 2,
+        "8" => 3,
+        _ => panic!("Invalid index scale: {}", scale),
+    };
+    Some((parse_reg(base), index, AddrMode::Indexed { scale }))
+}
 
-            Opcode::INCI | Opcode::DECI | Opcode::NEGI | Opcode::ABSI => {
-                let rd = parse_reg(&tokens[1]);
-                let imm = parse_imm(&tokens[2], 14);
-                encode_i(*opcode, rd, 0, imm)
-            }
+// Base y desplazamiento de `[rs1, offset]`, `[rs1]` o `[rs1 + offset]`,
+// con o sin espacios alrededor del `+`
+fn mem_offset(base: &str, rest: &[String]) -> (String, Option<String>) {
+    let operand = base.to_string() + &rest.concat();
+    match operand.split_once('+') {
+        Some((base, offset)) if reg_index(base).is_some() => {
+            (base.to_string(), Some(offset.to_string()))
+        }
+        _ => (base.to_string(), rest.first().cloned()),
+    }
+}
 
-            Opcode::NOPI => encode_i(*opcode, 0, 0, 0),
-
-            Opcode::LDB
-            | Opcode::LDBU
-            | Opcode::LDH
-            | Opcode::LDHU
-            | Opcode::LDW
-            | Opcode::STB
-            | Opcode::STH
-            | Opcode::STW => {
-                let rd = parse_reg(&tokens[1]);
-                let rs1 = parse_reg(&tokens[2]);
-                let imm = parse_imm(&tokens[3], 14);
-                encode_mem(*opcode, rd, rs1, imm)
-            }
+// Variante de `Format::MemX` de una carga o almacenamiento
+fn indexed_form(opcode: Opcode) -> Option<Opcode> {
+    match opcode {
+        Opcode::LDB => Some(Opcode::LDBX),
+        Opcode::LDBU => Some(Opcode::LDBUX),
+        Opcode::LDH => Some(Opcode::LDHX),
+        Opcode::LDHU => Some(Opcode::LDHUX),
+        Opcode::LDW => Some(Opcode::LDWX),
+        Opcode::STB => Some(Opcode::STBX),
+        Opcode::STH => Some(Opcode::STHX),
+        Opcode::STW => Some(Opcode::STWX),
+        _ => None,
+    }
+}
 
-            Opcode::LDLR | Opcode::STLR => {
-                let rd = parse_reg(&tokens[1]);
-                let rs1 = parse_reg(&tokens[2]);
-                encode_mem(*opcode, rd, rs1, 0)
-            }
+/// Ensambla una línea a partir de los operandos que declara su opcode en la
+/// tabla del ISA. Entra en pánico con un mensaje si la línea no es válida.
+fn encode_line(opcode: Opcode, tokens: &[String], asm: &Assembly, current_pc: u32) -> u32 {
+    let operand = |idx: usize| -> &str {
+        tokens
+            .get(idx)
+            .unwrap_or_else(|| panic!("Missing operand {} for {}", idx, opcode.mnemonic()))
+    };
 
-            Opcode::PUSH | Opcode::POP => {
-                let rd = parse_reg(&tokens[1]);
-                encode_mem(*opcode, rd, 0, 0)
-            }
+    // `LDW R1, [R2 + R3*4]` y los demás modos usan la variante indexada
+    let opcode = match opcode.operands() {
+        Operands::Mem if parse_address_mode(tokens.get(2..).unwrap_or_default()).is_some() => {
+            indexed_form(opcode)
+                .unwrap_or_else(|| panic!("Addressing mode not supported by {}", opcode.mnemonic()))
+        }
+        _ => opcode,
+    };
 
-            Opcode::JMP
-            | Opcode::JZ
-            | Opcode::JNZ
-            | Opcode::JEQ
-            | Opcode::JNE
-            | Opcode::JLT
-            | Opcode::JGT
-            | Opcode::JLE
-            | Opcode::JGE
-            | Opcode::JC
-            | Opcode::JO
-            | Opcode::CALL => {
-                let label = &tokens[1];
-                let target_address = *asm
-                    .labels
-                    .get(label)
-                    .unwrap_or_else(|| panic!("Unknown label: {}", label));
-                let offset = (target_address as i32).wrapping_sub(current_pc as i32) as u32;
-                encode_j(*opcode, offset)
-            }
+    // Desplazamiento hasta la etiqueta, en instrucciones
+    let label_offset = |label: &str| -> i64 {
+        let target_address = *asm
+            .labels
+            .get(label)
+            .unwrap_or_else(|| panic!("Unknown label: {}", label));
+        target_address as i64 - current_pc as i64
+    };
 
-            Opcode::RET | Opcode::HALT => encode_j(*opcode, 0),
-
-            Opcode::MOV
-            | Opcode::MOVPC
-            | Opcode::MTSR
-            | Opcode::MFSR
-            | Opcode::MOVSP
-            | Opcode::SETSP => {
-                let rd = parse_reg(&tokens[1]);
-                let rs1 = if tokens.len() > 2 {
-                    parse_reg(&tokens[2])
-                } else {
-                    0
-                };
-                encode_r(*opcode, rd, rs1, 0)
-            }
+    let (rd, rs1, rs2, imm) = match opcode.operands() {
+        Operands::None => (0, 0, 0, 0),
+        Operands::Rd => (parse_reg(operand(1)), 0, 0, 0),
+        Operands::RdOptRs => {
+            let rs = tokens.get(2).map_or(0, |tok| parse_reg(tok));
+            (parse_reg(operand(1)), rs, 0, 0)
+        }
+        Operands::RdRs1 => (parse_reg(operand(1)), parse_reg(operand(2)), 0, 0),
+        Operands::RdRs1Rs2 => (
+            parse_reg(operand(1)),
+            parse_reg(operand(2)),
+            parse_reg(operand(3)),
+            0,
+        ),
+        Operands::RdImm => (parse_reg(operand(1)), 0, 0, asm.value(operand(2))),
+        Operands::RdRs1Imm => (
+            parse_reg(operand(1)),
+            parse_reg(operand(2)),
+            0,
+            asm.value(operand(3)),
+        ),
+        Operands::RdRs1Field => {
+            let pos = parse_imm(operand(3));
+            let width = parse_imm(operand(4));
+            assert!(
+                (0..32).contains(&pos) && width >= 1 && pos + width <= 32,
+                "Invalid bit field for {}: pos {}, width {}",
+                opcode.mnemonic(),
+                pos,
+                width
+            );
+            (
+                parse_reg(operand(1)),
+                parse_reg(operand(2)),
+                0,
+                bit_field(pos as u32, width as u32) as i64,
+            )
+        }
+        Operands::RdRs1Rs2Cond => {
+            let suffix = operand(4);
+            let cond = Condition::ALL
+                .iter()
+                .position(|c| c.suffix() == suffix)
+                .unwrap_or_else(|| {
+                    panic!("Unknown condition for {}: {}", opcode.mnemonic(), suffix)
+                });
+            (
+                parse_reg(operand(1)),
+                parse_reg(operand(2)),
+                parse_reg(operand(3)),
+                cond as i64,
+            )
+        }
+        Operands::Mem => {
+            let (base, offset) = mem_offset(operand(2), tokens.get(3..).unwrap_or_default());
+            let offset = offset.map_or(0, |tok| asm.value(&tok));
+            (parse_reg(operand(1)), parse_reg(&base), 0, offset)
+        }
+        Operands::Packed => {
+            let (op, lanes) = PackedOp::from_mnemonic(&tokens[0])
+                .unwrap_or_else(|| panic!("Unknown packed operation: {}", tokens[0]));
+            (
+                parse_reg(operand(1)),
+                parse_reg(operand(2)),
+                parse_reg(operand(3)),
+                op.funct(lanes) as i64,
+            )
+        }
+        Operands::MemX => {
+            let (rs1, rs2, mode) = parse_address_mode(tokens.get(2..).unwrap_or_default())
+                .unwrap_or_else(|| {
+                    panic!(
+                        "Expected an indexed, post-increment or pre-decrement address for {}",
+                        opcode.mnemonic()
+                    )
+                });
+            (parse_reg(operand(1)), rs1, rs2, mode.bits() as i64)
+        }
+        Operands::Target => {
+            let offset = tokens.get(2).map_or(0, |tok| asm.value(tok));
+            (0, parse_reg(operand(1)), 0, offset)
+        }
+        Operands::Label => (0, 0, 0, label_offset(operand(1))),
+        Operands::RdLabel => (parse_reg(operand(1)), 0, 0, label_offset(operand(2))),
+        Operands::RsRsLabel => (
+            0,
+            parse_reg(operand(1)),
+            parse_reg(operand(2)),
+            label_offset(operand(3)),
+        ),
+        Operands::RsLabel => (0, parse_reg(operand(1)), 0, label_offset(operand(2))),
+        Operands::Imm => (0, 0, 0, asm.value(operand(1))),
+        Operands::RegList if operand(1).starts_with('{') => {
+            (0, 0, 0, parse_reglist(&tokens[1..]) as i64)
+        }
+        Operands::RegList => (0, 0, 0, parse_imm(operand(1))),
+        Operands::RdPort => (parse_reg(operand(1)), 0, 0, parse_port(operand(2)) as i64),
+    };
 
-            Opcode::LI | Opcode::LUI => {
-                let rd = parse_reg(&tokens[1]);
-                let imm = parse_imm(&tokens[2], 19);
-                encode_sys(*opcode, rd, imm)
-            }
+    if let Some(field) = opcode.format().layout().imm
+        && !opcode.imm().fits(imm, field.bits)
+    {
+        panic!(
+            "Immediate out of range for {}: {} ({:?}, {} bits)",
+            opcode.mnemonic(),
+            imm,
+            opcode.imm(),
+            field.bits
+        );
+    }
 
-            Opcode::IN | Opcode::OUT => {
-                let rd = parse_reg(&tokens[1]);
-                let port = parse_port(&tokens[2]);
-                encode_io(*opcode, rd, port)
-            }
+    Instruction::from_parts(opcode, rd, rs1, rs2, imm as u32).encode()
+}
 
-            Opcode::FADD
-            | Opcode::FSUB
-            | Opcode::FMUL
-            | Opcode::FDIV
-            | Opcode::FCMP
-            | Opcode::FEQ
-            | Opcode::FLT
-            | Opcode::FGT
-            | Opcode::FTOI
-            | Opcode::ITOF
-            | Opcode::FMOV => {
-                let rd = parse_reg(&tokens[1]);
-                let rs1 = parse_reg(&tokens[2]);
-                let rs2 = parse_reg(&tokens[3]);
-                encode_fp(*opcode, rd, rs1, rs2)
-            }
+pub fn second_pass(
+    asm: Assembly,
+    table: &HashMap<String, Opcode>,
+) -> Result<Vec<u32>, AssembleError> {
+    let mut result = Vec::new();
 
-            Opcode::FLD | Opcode::FST => {
-                let rd = parse_reg(&tokens[1]);
-                let rs1 = parse_reg(&tokens[2]);
-                let imm = parse_imm(&tokens[3], 14);
-                encode_mem(*opcode, rd, rs1, imm)
+    for (current_pc, tokens) in &asm.lines {
+        let encoded = match std::panic::catch_unwind(|| {
+            if tokens[0] == ".WORD" {
+                return tokens[1..]
+                    .iter()
+                    .map(|tok| asm.value(tok) as u32)
+                    .collect();
             }
+            let opcode_str = &tokens[0];
+            let opcode = *table
+                .get(opcode_str)
+                .unwrap_or_else(|| panic!("Unknown opcode: {}", opcode_str));
+            vec![encode_line(opcode, tokens, &asm, *current_pc)]
         }) {
             Ok(enc) => enc,
-            Err(_) => {
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_else(|| "Pánico al ensamblar la instrucción".to_string());
                 return Err(AssembleError {
-                    line_number: current_pc as usize,
+                    line_number: *current_pc as usize,
                     line_content: tokens.join(" "),
-                    message: "Pánico al ensamblar la instrucción".to_string(),
+                    message,
                 });
             }
         };
 
-        result.push(encoded);
+        result.extend(encoded);
     }
 
     Ok(result)
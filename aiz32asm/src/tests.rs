@@ -1,138 +1,27 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
+    use crate::AssembleError;
     use crate::assemble_from_vec;
-    use aiz32core::instruction::Opcode;
-    use std::collections::HashMap;
-
-    fn opcode_table() -> HashMap<String, Opcode> {
-        use Opcode::*;
-        let mut table = HashMap::new();
-        // ALU (R-type)
-        table.insert("NOP".into(), NOP);
-        table.insert("ADD".into(), ADD);
-        table.insert("SUB".into(), SUB);
-        table.insert("MUL".into(), MUL);
-        table.insert("DIV".into(), DIV);
-        table.insert("MOD".into(), MOD);
-        table.insert("INC".into(), INC);
-        table.insert("DEC".into(), DEC);
-        table.insert("NEG".into(), NEG);
-        table.insert("ABS".into(), ABS);
-        table.insert("AND".into(), AND);
-        table.insert("OR".into(), OR);
-        table.insert("XOR".into(), XOR);
-        table.insert("NAND".into(), NAND);
-        table.insert("NOR".into(), NOR);
-        table.insert("XNOR".into(), XNOR);
-        table.insert("NOT".into(), NOT);
-        table.insert("SHL".into(), SHL);
-        table.insert("SHR".into(), SHR);
-        table.insert("SAR".into(), SAR);
-        table.insert("ROL".into(), ROL);
-        table.insert("ROR".into(), ROR);
-        table.insert("SEXTB".into(), SEXTB);
-        table.insert("ZEXTB".into(), ZEXTB);
-        table.insert("POPCNT".into(), POPCNT);
-        table.insert("CMP".into(), CMP);
-        table.insert("UCMP".into(), UCMP);
-        table.insert("SETZ".into(), SETZ);
-        table.insert("SETNZ".into(), SETNZ);
-        table.insert("PASS".into(), PASS);
-        table.insert("SEXTH".into(), SEXTH);
-        table.insert("ZEXTH".into(), ZEXTH);
-        // ALU (I-type)
-        table.insert("NOPI".into(), NOPI);
-        table.insert("ADDI".into(), ADDI);
-        table.insert("SUBI".into(), SUBI);
-        table.insert("MULI".into(), MULI);
-        table.insert("DIVI".into(), DIVI);
-        table.insert("MODI".into(), MODI);
-        table.insert("INCI".into(), INCI);
-        table.insert("DECI".into(), DECI);
-        table.insert("NEGI".into(), NEGI);
-        table.insert("ABSI".into(), ABSI);
-        table.insert("ANDI".into(), ANDI);
-        table.insert("ORI".into(), ORI);
-        table.insert("XORI".into(), XORI);
-        table.insert("NANDI".into(), NANDI);
-        table.insert("NORI".into(), NORI);
-        table.insert("XNORI".into(), XNORI);
-        table.insert("NOTI".into(), NOTI);
-        table.insert("SHLI".into(), SHLI);
-        table.insert("SHRI".into(), SHRI);
-        table.insert("SARI".into(), SARI);
-        table.insert("ROLI".into(), ROLI);
-        table.insert("RORI".into(), RORI);
-        table.insert("SEXTBI".into(), SEXTBI);
-        table.insert("ZEXTBI".into(), ZEXTBI);
-        table.insert("POPCNTI".into(), POPCNTI);
-        table.insert("CMPI".into(), CMPI);
-        table.insert("UCMPI".into(), UCMPI);
-        table.insert("SETZI".into(), SETZI);
-        table.insert("SETNZI".into(), SETNZI);
-        table.insert("PASSI".into(), PASSI);
-        table.insert("SEXTHI".into(), SEXTHI);
-        table.insert("ZEXTHI".into(), ZEXTHI);
-        // Memory
-        table.insert("LDB".into(), LDB);
-        table.insert("LDBU".into(), LDBU);
-        table.insert("LDH".into(), LDH);
-        table.insert("LDHU".into(), LDHU);
-        table.insert("LDW".into(), LDW);
-        table.insert("STB".into(), STB);
-        table.insert("STH".into(), STH);
-        table.insert("STW".into(), STW);
-        table.insert("LDLR".into(), LDLR);
-        table.insert("STLR".into(), STLR);
-        // Jumps & Branch
-        table.insert("JMP".into(), JMP);
-        table.insert("JZ".into(), JZ);
-        table.insert("JNZ".into(), JNZ);
-        table.insert("JEQ".into(), JEQ);
-        table.insert("JNE".into(), JNE);
-        table.insert("JLT".into(), JLT);
-        table.insert("JGT".into(), JGT);
-        table.insert("JLE".into(), JLE);
-        table.insert("JGE".into(), JGE);
-        table.insert("JC".into(), JC);
-        table.insert("JO".into(), JO);
-        table.insert("CALL".into(), CALL);
-        table.insert("RET".into(), RET);
-        table.insert("HALT".into(), HALT);
-        // Move & System
-        table.insert("MOV".into(), MOV);
-        table.insert("LI".into(), LI);
-        table.insert("LUI".into(), LUI);
-        table.insert("MOVPC".into(), MOVPC);
-        table.insert("MTSR".into(), MTSR);
-        table.insert("MFSR".into(), MFSR);
-        table.insert("MOVSP".into(), MOVSP);
-        table.insert("SETSP".into(), SETSP);
-        // Floating Point
-        table.insert("FADD".into(), FADD);
-        table.insert("FSUB".into(), FSUB);
-        table.insert("FMUL".into(), FMUL);
-        table.insert("FDIV".into(), FDIV);
-        table.insert("FCMP".into(), FCMP);
-        table.insert("FEQ".into(), FEQ);
-        table.insert("FLT".into(), FLT);
-        table.insert("FGT".into(), FGT);
-        table.insert("FTOI".into(), FTOI);
-        table.insert("ITOF".into(), ITOF);
-        table.insert("FMOV".into(), FMOV);
-        table.insert("FLD".into(), FLD);
-        table.insert("FST".into(), FST);
-        // IO
-        table.insert("IN".into(), IN);
-        table.insert("OUT".into(), OUT);
-        table
-    }
+    use crate::opcode::opcode_table;
+    use crate::parser::{first_pass, second_pass};
+    use aiz32core::instruction::{AddrMode, Instruction, Opcode, REGLIST_HIGH, REGLIST_LR};
+    use aiz32core::isa::ISA;
+    use aiz32core::packed::PackedOp;
 
     fn run(lines: Vec<&str>) -> Vec<u32> {
         let lines: Vec<String> = lines.into_iter().map(|s| s.to_string()).collect();
         assemble_from_vec(lines, &opcode_table())
     }
 
+    // El error que devuelve el ensamblado, sin pasar por el `unwrap` de
+    // `assemble_from_vec`
+    fn assemble_error(lines: Vec<&str>) -> AssembleError {
+        let lines: Vec<String> = lines.into_iter().map(|s| s.to_string()).collect();
+        second_pass(first_pass(&lines), &opcode_table())
+            .expect_err("se esperaba un error de ensamblado")
+    }
+
     #[test]
     fn test_r_type() {
         let out = run(vec!["ADD r1, r2, r3", "SUB r4, r5, r6"]);
@@ -198,6 +87,53 @@ mod tests {
         assert_eq!(addi & 0x3FFF, (-5i32 as u32) & 0x3FFF); // imm = -5 (14-bit two’s complement)
     }
 
+    #[test]
+    fn test_unsigned_divide_and_high_multiply() {
+        let out = run(vec![
+            "DIVU r1, r2, r3",
+            "MULHSU r4, r5, r6",
+            "MODUI r7, r8, #16000",
+        ]);
+        assert_eq!(out[0] >> 24, Opcode::DIVU as u32);
+        assert_eq!((out[0] >> 9) & 0x1F, 3); // rs2 = r3
+        assert_eq!(out[1] >> 24, Opcode::MULHSU as u32);
+        assert_eq!((out[1] >> 19) & 0x1F, 4); // rd = r4
+        assert_eq!(out[2] >> 24, Opcode::MODUI as u32);
+        assert_eq!(out[2] & 0x3FFF, 16000);
+    }
+
+    #[test]
+    fn test_add_with_carry() {
+        let out = run(vec!["ADC r1, r2, r3", "SBCI r4, r5, #-1"]);
+        assert_eq!(out[0] >> 24, Opcode::ADC as u32);
+        assert_eq!((out[0] >> 14) & 0x1F, 2); // rs1 = r2
+        assert_eq!(out[1] >> 24, Opcode::SBCI as u32);
+        assert_eq!(out[1] & 0x3FFF, 0x3FFF); // imm = -1
+    }
+
+    #[test]
+    fn test_bit_manipulation() {
+        let out = run(vec![
+            "CLZ r1, r2",
+            "BSETI r3, r4, #31",
+            "BFEXT r5, r6, #11, #5",
+            "BFINS r7, r8, 0, 32",
+        ]);
+        assert_eq!(out[0] >> 24, Opcode::CLZ as u32);
+        assert_eq!((out[0] >> 14) & 0x1F, 2); // rs1 = r2
+        assert_eq!(out[1] >> 24, Opcode::BSETI as u32);
+        assert_eq!(out[1] & 0x3FFF, 31);
+        assert_eq!(out[2] >> 24, Opcode::BFEXT as u32);
+        assert_eq!(out[2] & 0x3FFF, 11 | (4 << 5)); // pos 11, width - 1 = 4
+        assert_eq!(out[3] & 0x3FFF, 31 << 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid bit field for BFEXT")]
+    fn test_bit_field_out_of_range() {
+        run(vec!["BFEXT r1, r2, #28, #8"]);
+    }
+
     #[test]
     fn test_i_type_unary() {
         let out = run(vec!["INCI r1, #5", "DECI r2, 0x0A"]);
@@ -298,6 +234,302 @@ mod tests {
         assert_eq!(halt & 0xFFFFFF, 0); // offset = 0
     }
 
+    #[test]
+    fn test_indirect_jumps() {
+        let out = run(vec!["JR r5", "CALLR r6, 8", "JR [r7, -4]"]);
+        assert_eq!(out[0] >> 24, Opcode::JR as u32);
+        assert_eq!((out[0] >> 14) & 0x1F, 5); // rs1 = r5
+        assert_eq!(out[0] & 0x3FFF, 0);
+        assert_eq!(out[1] >> 24, Opcode::CALLR as u32);
+        assert_eq!((out[1] >> 14) & 0x1F, 6); // rs1 = r6
+        assert_eq!(out[1] & 0x3FFF, 8);
+        assert_eq!((out[2] >> 14) & 0x1F, 7); // rs1 = r7
+        assert_eq!(out[2] & 0x3FFF, (-4i32 as u32) & 0x3FFF);
+    }
+
+    #[test]
+    fn test_jump_table_with_label_addresses() {
+        let out = run(vec![
+            ".org 0x100",
+            "LI r1, TABLE",
+            "LDW r2, [r1, 4]",
+            "JR r2",
+            "CASE0: HALT",
+            "CASE1: HALT",
+            "TABLE: .word CASE0, CASE1, 0x1234",
+            "LDW r3, [r0, TABLE]",
+        ]);
+        assert_eq!(out.len(), 9);
+        // las etiquetas valen origen + 4 * índice de palabra
+        assert_eq!(out[0] & 0x7FFFF, 0x114);
+        assert_eq!(&out[5..8], &[0x10C, 0x110, 0x1234]);
+        assert_eq!(out[8] & 0x3FFF, 0x114);
+    }
+
+    #[test]
+    #[should_panic(expected = ".org must come before")]
+    fn test_org_after_code() {
+        run(vec!["NOP", ".org 0x100"]);
+    }
+
+    #[test]
+    fn test_compare_and_branch() {
+        let out = run(vec![
+            "LOOP: ADDI r1, r1, 1",
+            "BLTU r1, r2, LOOP",
+            "BEQ r3, r4, DONE",
+            "DBNZ r5, LOOP",
+            "DONE: HALT",
+        ]);
+        assert_eq!(out[1] >> 24, Opcode::BLTU as u32);
+        assert_eq!((out[1] >> 19) & 0x1F, 1); // rs1 = r1
+        assert_eq!((out[1] >> 14) & 0x1F, 2); // rs2 = r2
+        assert_eq!(out[1] & 0x3FFF, (-1i32 as u32) & 0x3FFF);
+        assert_eq!(out[2] >> 24, Opcode::BEQ as u32);
+        assert_eq!(out[2] & 0x3FFF, 2);
+        assert_eq!(out[3] >> 24, Opcode::DBNZ as u32);
+        assert_eq!((out[3] >> 19) & 0x1F, 5); // rs = r5
+        assert_eq!(out[3] & 0x3FFF, (-3i32 as u32) & 0x3FFF);
+    }
+
+    #[test]
+    fn test_conditional_move_and_select() {
+        let out = run(vec!["CMOVLT r1, r2", "SEL r3, r4, r5, GE", "CMOVO r6, r7"]);
+        assert_eq!(out[0] >> 24, Opcode::CMOVLT as u32);
+        assert_eq!((out[0] >> 19) & 0x1F, 1); // rd = r1
+        assert_eq!((out[0] >> 14) & 0x1F, 2); // rs = r2
+        assert_eq!(out[1] >> 24, Opcode::SEL as u32);
+        assert_eq!((out[1] >> 9) & 0x1F, 5); // rs2 = r5
+        assert_eq!(out[1] & 0xF, 7); // cond = GE
+        assert_eq!(out[2] >> 24, Opcode::CMOVO as u32);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown condition for SEL")]
+    fn test_select_unknown_condition() {
+        run(vec!["SEL r1, r2, r3, XX"]);
+    }
+
+    #[test]
+    fn test_addressing_modes() {
+        let out = run(vec![
+            "LDW r1, [r2 + r3*4]",
+            "STB r1, [r2+r3]",
+            "LDB r1, [r2]+",
+            "STH r4, -[r5]",
+            "LDHUX r6, [r7 + r8*8]",
+            "LDW r1, [r2, -4]",
+        ]);
+        assert_eq!(out[0] >> 24, Opcode::LDWX as u32);
+        assert_eq!((out[0] >> 14) & 0x1F, 2); // rs1 = r2
+        assert_eq!((out[0] >> 9) & 0x1F, 3); // rs2 = r3
+        assert_eq!(out[0] & 0xF, 2); // escala 4
+        assert_eq!(out[1] >> 24, Opcode::STBX as u32);
+        assert_eq!(out[1] & 0xF, 0);
+        assert_eq!(out[2] >> 24, Opcode::LDBX as u32);
+        assert_eq!(out[2] & 0xF, AddrMode::PostIncrement.bits() as u32);
+        assert_eq!(out[3] >> 24, Opcode::STHX as u32);
+        assert_eq!((out[3] >> 19) & 0x1F, 4); // rd = r4
+        assert_eq!((out[3] >> 14) & 0x1F, 5); // rs1 = r5
+        assert_eq!(out[3] & 0xF, AddrMode::PreDecrement.bits() as u32);
+        assert_eq!(out[4] >> 24, Opcode::LDHUX as u32);
+        assert_eq!(out[4] & 0xF, 3);
+        // el desplazamiento negativo sigue siendo la forma de siempre
+        assert_eq!(out[5] >> 24, Opcode::LDW as u32);
+    }
+
+    #[test]
+    fn test_positive_offset_is_not_indexed() {
+        // `+4` es un desplazamiento, no el registro R4
+        let out = run(vec![
+            "LDW r1, [r2, 4]",
+            "LDW r1, [r2, +4]",
+            "LDW r1, [r2, #+4]",
+        ]);
+        assert_eq!(out[0] >> 24, Opcode::LDW as u32);
+        assert_eq!((out[0] >> 14) & 0x1F, 2); // rs1 = r2
+        assert_eq!(out[0] & 0x3FFF, 4);
+        assert_eq!(out[1], out[0]);
+        assert_eq!(out[2], out[0]);
+    }
+
+    #[test]
+    fn test_base_plus_displacement() {
+        // `[base + imm]` es la forma con desplazamiento de siempre
+        let out = run(vec![
+            "LDW r1, [r2, 4]",
+            "LDW r1, [r2 + 4]",
+            "LDW r1, [r2+4]",
+            "LDW r1, [r2+ #4]",
+            "STW r3, [r0, -8]",
+            "STW r3, [r0 + -8]",
+        ]);
+        assert_eq!(out[1], out[0]);
+        assert_eq!(out[2], out[0]);
+        assert_eq!(out[3], out[0]);
+        assert_eq!(out[5], out[4]);
+    }
+
+    #[test]
+    fn test_bad_displacement_is_an_error() {
+        let err = assemble_error(vec!["NOP", "LDW r1, [r2 + foo]"]);
+        assert_eq!(err.line_number, 1);
+        assert!(err.message.contains("Invalid immediate: foo"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Addressing mode not supported by FLD")]
+    fn test_addressing_mode_unsupported() {
+        run(vec!["FLD f1, [r2]+"]);
+    }
+
+    #[test]
+    fn test_literal_pool() {
+        let out = run(vec![
+            "LDW r1, =0xDEADBEEF",
+            "LDW r2, =0xDEADBEEF",
+            "LDW r3, =DATA",
+            "HALT",
+            ".ltorg",
+            "DATA: .word 7",
+            "ADDPC r5, DATA",
+            "LDW r4, =0x10",
+        ]);
+        assert_eq!(out.len(), 10);
+        // el pool va en .ltorg y cada valor aparece una vez
+        assert_eq!(out[0] >> 24, Opcode::LDPC as u32);
+        assert_eq!((out[0] >> 19) & 0x1F, 1); // rd = r1
+        assert_eq!(out[0] & 0x7FFFF, 4);
+        assert_eq!(out[1] & 0x7FFFF, 3);
+        assert_eq!(out[2] & 0x7FFFF, 3);
+        assert_eq!(&out[4..7], &[0xDEAD_BEEF, 24, 7]);
+        assert_eq!(out[7] >> 24, Opcode::ADDPC as u32);
+        assert_eq!(out[7] & 0x7FFFF, (-1i32 as u32) & 0x7FFFF);
+        // los literales sin .ltorg van al final
+        assert_eq!(out[8] & 0x7FFFF, 1);
+        assert_eq!(out[9], 0x10);
+    }
+
+    #[test]
+    fn test_register_lists() {
+        let out = run(vec![
+            "PUSHM {R4-R11, LR}",
+            "PUSHM {R20-R21, R31, LR}",
+            "POPM {R1, R16}",
+            "ENTER 32",
+            "LEAVE",
+        ]);
+        assert_eq!(out.len(), 5);
+        assert_eq!(out[0] >> 24, Opcode::PUSHM as u32);
+        assert_eq!(out[0] & 0x7FFFF, REGLIST_LR | 0xFF << 3);
+        assert_eq!(
+            out[1] & 0x7FFFF,
+            REGLIST_HIGH | REGLIST_LR | 0b11 << 3 | 1 << 14
+        );
+        assert_eq!(out[2] >> 24, Opcode::POPM as u32);
+        assert_eq!(out[2] & 0x7FFFF, 1 | 1 << 15);
+        assert_eq!(out[3] >> 24, Opcode::ENTER as u32);
+        assert_eq!(out[3] & 0x7FFFF, 32);
+        assert_eq!(out[4] >> 24, Opcode::LEAVE as u32);
+    }
+
+    #[test]
+    fn test_register_lists_with_spaces() {
+        let out = run(vec![
+            "PUSHM { R4 - R11, LR }",
+            "PUSHM {R4-R11,LR}",
+            "POPM { R17 ,R20 -R21 }",
+            "POPM {R17, R20-R21}",
+        ]);
+        assert_eq!(out.len(), 4);
+        assert_eq!(out[0], out[1]);
+        assert_eq!(out[2], out[3]);
+    }
+
+    #[test]
+    fn test_register_list_across_banks() {
+        // una instrucción no puede mover R1..R16 y R17..R31 a la vez
+        let err = assemble_error(vec!["NOP", "PUSHM {R4-R20, LR}"]);
+        assert_eq!(err.line_number, 1);
+        assert!(err.message.contains("R1-R16 and R17-R31"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid register list")]
+    fn test_register_list_with_r0() {
+        run(vec!["PUSHM {R0-R3}"]);
+    }
+
+    #[test]
+    fn test_block_memory() {
+        let out = run(vec![
+            "MEMCPY r1, r2, r3",
+            "MEMSET r4, r0, r5",
+            "MEMCMP r6, r7, r8",
+        ]);
+        assert_eq!(out[0] >> 24, Opcode::MEMCPY as u32);
+        assert_eq!((out[0] >> 19) & 0x1F, 1); // rd = destino
+        assert_eq!((out[0] >> 14) & 0x1F, 2); // rs1 = origen
+        assert_eq!((out[0] >> 9) & 0x1F, 3); // rs2 = bytes
+        assert_eq!(out[1] >> 24, Opcode::MEMSET as u32);
+        assert_eq!((out[1] >> 14) & 0x1F, 0);
+        assert_eq!(out[2] >> 24, Opcode::MEMCMP as u32);
+        assert_eq!((out[2] >> 9) & 0x1F, 8);
+    }
+
+    #[test]
+    fn test_packed() {
+        let out = run(vec![
+            "PADDUSB r1, r2, r3",
+            "PMULHUH r4, r5, r6",
+            "PSHUFB r7, r8, r9",
+        ]);
+        // Todas son PACKED; la operación va en los bits bajos
+        for word in &out {
+            assert_eq!(word >> 24, Opcode::PACKED as u32);
+        }
+        assert_eq!((out[0] >> 19) & 0x1F, 1); // rd = r1
+        assert_eq!((out[0] >> 9) & 0x1F, 3); // rs2 = r3
+        assert_eq!(out[0] & 0x1F, 1);
+        assert_eq!(out[1] & 0x1F, 0x19);
+        assert_eq!(out[2] & 0x1F, 10);
+        assert_eq!((out[2] >> 14) & 0x1F, 8); // rs1 = r8
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown opcode: PACKED")]
+    fn test_packed_needs_its_mnemonic() {
+        run(vec!["PACKED r1, r2, r3"]);
+    }
+
+    #[test]
+    fn test_interrupt_control() {
+        let out = run(vec!["EI", "DI", "RETI"]);
+        assert_eq!(out[0], (Opcode::EI as u32) << 24);
+        assert_eq!(out[1], (Opcode::DI as u32) << 24);
+        assert_eq!(out[2], (Opcode::RETI as u32) << 24);
+    }
+
+    #[test]
+    fn test_privilege_instructions() {
+        let out = run(vec![
+            "SYSCALL #42",
+            "SYSCALL 0x7FFFF",
+            "MFUSP r3",
+            "MTUSP r4",
+        ]);
+        assert_eq!(out[0], ((Opcode::SYSCALL as u32) << 24) | 42);
+        assert_eq!(out[1], ((Opcode::SYSCALL as u32) << 24) | 0x7FFFF);
+        assert_eq!(out[2], ((Opcode::MFUSP as u32) << 24) | (3 << 19));
+        assert_eq!(out[3], ((Opcode::MTUSP as u32) << 24) | (4 << 19));
+    }
+
+    #[test]
+    #[should_panic(expected = "Immediate out of range for SYSCALL")]
+    fn test_syscall_out_of_range() {
+        run(vec!["SYSCALL 0x80000"]);
+    }
+
     #[test]
     fn test_move_system_r_type() {
         let out = run(vec!["MOV r1, r2", "MOVPC r3, r4"]);
@@ -315,20 +547,28 @@ mod tests {
 
     #[test]
     fn test_move_system_sys_type() {
-        let out = run(vec!["LI r1, #100", "SETSP r2, 0x200"]);
+        let out = run(vec!["LI r1, #100"]);
         let li = out[0];
-        let setsp = out[1];
         assert_eq!(li >> 24, Opcode::LI as u32);
         assert_eq!((li >> 19) & 0x1F, 1); // rd = r1
         assert_eq!(li & 0x7FFFF, 100); // imm = 100
-        assert_eq!(setsp >> 24, Opcode::SETSP as u32);
-        assert_eq!((setsp >> 19) & 0x1F, 2); // rd = r2
-        assert_eq!(setsp & 0x7FFFF, 512); // imm = 0x200
+
+        // SETSP no tiene forma con inmediato
+        let err = assemble_error(vec!["SETSP r2, 0x200"]);
+        assert_eq!(err.line_content, "SETSP R2 0X200");
+        assert!(err.message.contains("Invalid register: 0X200"));
+    }
+
+    #[test]
+    fn test_number_is_not_a_register() {
+        // Sin el prefijo R, `5` no se lee como R5
+        let err = assemble_error(vec!["MOV r1, 5"]);
+        assert!(err.message.contains("Invalid register: 5"));
     }
 
     #[test]
     fn test_fp_type() {
-        let out = run(vec!["FADD r1, r2, r3", "FMOV r4, r5, r6"]);
+        let out = run(vec!["FADD r1, r2, r3", "FMOV r4, r5"]);
         let fadd = out[0];
         let fmov = out[1];
         assert_eq!(fadd >> 24, Opcode::FADD as u32);
@@ -338,7 +578,7 @@ mod tests {
         assert_eq!(fmov >> 24, Opcode::FMOV as u32);
         assert_eq!((fmov >> 19) & 0x1F, 4); // rd = r4
         assert_eq!((fmov >> 14) & 0x1F, 5); // rs1 = r5
-        assert_eq!((fmov >> 9) & 0x1F, 6); // rs2 = r6
+        assert_eq!((fmov >> 9) & 0x1F, 0); // sin rs2
     }
 
     #[test]
@@ -356,17 +596,46 @@ mod tests {
         assert_eq!(fst & 0x3FFF, 32); // imm = 0x20
     }
 
+    #[test]
+    fn test_fp_extended() {
+        let out = run(vec![
+            "FSQRT f1, f2",
+            "FMA f3, f4, f5",
+            "MFFCSR r6",
+            "MTFCSR r7",
+        ]);
+        assert_eq!(out[0] >> 24, Opcode::FSQRT as u32);
+        assert_eq!((out[0] >> 14) & 0x1F, 2); // rs1 = f2
+        assert_eq!(out[1] >> 24, Opcode::FMA as u32);
+        assert_eq!((out[1] >> 9) & 0x1F, 5); // rs2 = f5
+        assert_eq!(out[2] >> 24, Opcode::MFFCSR as u32);
+        assert_eq!((out[2] >> 19) & 0x1F, 6); // rd = r6
+        assert_eq!(out[3] >> 24, Opcode::MTFCSR as u32);
+    }
+
+    #[test]
+    fn test_fp_double() {
+        let out = run(vec!["FADDD f0, f2, f4", "DTOF f1, f2", "FLDD f2, [r1, 8]"]);
+        assert_eq!(out[0] >> 24, Opcode::FADDD as u32);
+        assert_eq!((out[0] >> 9) & 0x1F, 4); // rs2 = f4
+        assert_eq!(out[1] >> 24, Opcode::DTOF as u32);
+        assert_eq!((out[1] >> 19) & 0x1F, 1); // rd = f1
+        assert_eq!(out[2] >> 24, Opcode::FLDD as u32);
+        assert_eq!((out[2] >> 19) & 0x1F, 2); // rd = f2
+        assert_eq!(out[2] & 0x3FFF, 8); // imm = 8
+    }
+
     #[test]
     fn test_io_type() {
         let out = run(vec!["IN r1, 0x10", "OUT r2, 0x20"]);
         let in_ = out[0];
         let out_ = out[1];
         assert_eq!(in_ >> 24, Opcode::IN as u32);
-        assert_eq!((in_ >> 3) & 0x1F, 1); // rd = r1
-        assert_eq!((in_ >> 8) & 0xFFFF, 16); // port = 0x10
+        assert_eq!((in_ >> 19) & 0x1F, 1); // rd = r1
+        assert_eq!((in_ >> 3) & 0xFFFF, 16); // port = 0x10
         assert_eq!(out_ >> 24, Opcode::OUT as u32);
-        assert_eq!((out_ >> 3) & 0x1F, 2); // rd = r2
-        assert_eq!((out_ >> 8) & 0xFFFF, 32); // port = 0x20
+        assert_eq!((out_ >> 19) & 0x1F, 2); // rd = r2
+        assert_eq!((out_ >> 3) & 0xFFFF, 32); // port = 0x20
     }
 
     #[test]
@@ -399,4 +668,115 @@ mod tests {
     fn test_invalid_label() {
         run(vec!["JMP NONEXISTENT"]);
     }
+
+    #[test]
+    fn test_table_covers_isa() {
+        let table = opcode_table();
+        // PACKED entra con un mnemónico por operación empaquetada
+        assert_eq!(table.len(), ISA.len() - 1 + PackedOp::variants().count());
+        for info in ISA.iter().filter(|info| info.opcode != Opcode::PACKED) {
+            assert_eq!(table[info.mnemonic], info.opcode);
+        }
+        for (op, lanes) in PackedOp::variants() {
+            assert_eq!(table[&op.mnemonic(lanes)], Opcode::PACKED);
+        }
+    }
+
+    #[test]
+    fn test_signed_immediates_decode() {
+        let out = run(vec![
+            "ADDI r1, r2, #-5",
+            "LDW r3, [r4, #-8]",
+            "CMPI r5, #-8192",
+            "TARGET: JMP TARGET",
+        ]);
+        assert_eq!(
+            Instruction::decode(out[0]).unwrap(),
+            Instruction::I {
+                opcode: Opcode::ADDI,
+                rd: 1,
+                rs1: 2,
+                imm: -5i32 as u32,
+            }
+        );
+        assert_eq!(
+            Instruction::decode(out[1]).unwrap(),
+            Instruction::Mem {
+                opcode: Opcode::LDW,
+                rd: 3,
+                rs1: 4,
+                imm: -8i32 as u32,
+            }
+        );
+        assert_eq!(
+            Instruction::decode(out[2]).unwrap(),
+            Instruction::I {
+                opcode: Opcode::CMPI,
+                rd: 5,
+                rs1: 0,
+                imm: -8192i32 as u32,
+            }
+        );
+        assert_eq!(
+            Instruction::decode(out[3]).unwrap(),
+            Instruction::J {
+                opcode: Opcode::JMP,
+                offset: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_li_negative_immediate() {
+        let out = run(vec!["LI r1, -1", "LUI r2, #-2"]);
+        assert_eq!(out[0] & 0x7FFFF, 0x7FFFF);
+        assert_eq!(
+            Instruction::decode(out[0]).unwrap(),
+            Instruction::Sys {
+                opcode: Opcode::LI,
+                rd: 1,
+                imm: -1i32 as u32,
+                rs: 0,
+            }
+        );
+        assert_eq!(
+            Instruction::decode(out[1]).unwrap(),
+            Instruction::Sys {
+                opcode: Opcode::LUI,
+                rd: 2,
+                imm: -2i32 as u32,
+                rs: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_mem_without_offset() {
+        let out = run(vec!["LDW r1, [r2]"]);
+        assert_eq!(out, run(vec!["LDW r1, [r2, #0]"]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Immediate out of range")]
+    fn test_signed_immediate_out_of_range() {
+        run(vec!["ADDI r1, r2, #8192"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Immediate out of range")]
+    fn test_unsigned_immediate_out_of_range() {
+        run(vec!["ANDI r1, r2, #-1"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Immediate out of range")]
+    fn test_li_out_of_range() {
+        run(vec!["LI r1, 0x80000"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid register")]
+    fn test_invalid_register() {
+        run(vec!["ADD r32, r1, r2"]);
+    }
 }
@@ -1,30 +1,42 @@
+/// Registro `Rn` o `Fn`. Un número sin prefijo no es un registro.
 pub fn parse_reg(reg: &str) -> u8 {
-    let r = reg.trim_start_matches('R').trim_start_matches('F');
-    r.parse::<u8>().unwrap()
+    reg_index(reg).unwrap_or_else(|| panic!("Invalid register: {}", reg))
 }
 
-pub fn parse_imm(imm: &str, bits: u32) -> u32 {
+/// Índice de `reg` si es un registro, sin entrar en pánico.
+pub fn reg_index(reg: &str) -> Option<u8> {
+    reg.strip_prefix('R')
+        .or_else(|| reg.strip_prefix('F'))
+        .and_then(|idx| idx.parse::<u8>().ok())
+        .filter(|&idx| idx < 32)
+}
+
+pub fn parse_imm(imm: &str) -> i64 {
     let imm = imm
         .trim()
         .trim_start_matches('[')
         .trim_end_matches(']')
         .trim_end_matches(',')
         .to_lowercase();
+    let imm = imm.strip_prefix('#').unwrap_or(&imm);
 
-    let value: i32 = if let Some(hex) = imm.strip_prefix("0x") {
-        i32::from_str_radix(hex, 16).unwrap()
-    } else if let Some(dec) = imm.strip_prefix('#') {
-        dec.parse::<i32>().unwrap()
-    } else {
-        imm.parse::<i32>().unwrap()
+    let (negative, digits) = match imm.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, imm),
     };
 
-    (value as u32) & ((1 << bits) - 1)
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => digits.parse::<i64>(),
+    }
+    .unwrap_or_else(|_| panic!("Invalid immediate: {}", imm));
+
+    if negative { -value } else { value }
 }
 
 pub fn parse_port(port: &str) -> u16 {
-    if port.starts_with("0X") {
-        u16::from_str_radix(&port[2..], 16).unwrap()
+    if let Some(hex) = port.strip_prefix("0X") {
+        u16::from_str_radix(hex, 16).unwrap()
     } else {
         port.parse::<u16>().unwrap()
     }
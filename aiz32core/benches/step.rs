@@ -0,0 +1,96 @@
+//! Velocidad de `CPU::step` con y sin la caché de decodificación.
+//!
+//! `cargo bench -p aiz32core` ejecuta desde ROM un bucle de aritmética,
+//! cargas y almacenamientos sobre el mapa del emulador (RAM, ROM y un
+//! framebuffer MMIO) e imprime millones de instrucciones por segundo.
+
+use std::{cell::RefCell, rc::Rc, time::Instant};
+
+use aiz32core::cpu::CPU;
+use aiz32core::instruction::{Instruction, Opcode};
+use aiz32core::memory::{MemoryBus, MemoryMap};
+use aiz32core::peripheral::Mmio;
+
+const RAM_SIZE: u32 = 0x10_0000;
+const ROM_BASE: u32 = 0x8000_0000;
+const FRAMEBUFFER_BASE: u32 = 0xA000_0000;
+const STEPS: u64 = 20_000_000;
+// La mejor de varias pasadas, para que el ruido de la máquina pese menos
+const RUNS: usize = 3;
+
+struct Framebuffer;
+
+impl Mmio for Framebuffer {
+    fn mmio_read(&self, _offset: u32, _size: u32) -> u32 {
+        0
+    }
+
+    fn mmio_write(&mut self, _offset: u32, _size: u32, _value: u32) {}
+}
+
+fn program() -> Vec<u8> {
+    let i = |opcode, rd, rs1, imm| Instruction::I {
+        opcode,
+        rd,
+        rs1,
+        imm,
+    };
+    let r = |opcode, rd, rs1, rs2| Instruction::R {
+        opcode,
+        rd,
+        rs1,
+        rs2,
+    };
+    let mem = |opcode, rd, rs1, imm| Instruction::Mem {
+        opcode,
+        rd,
+        rs1,
+        imm,
+    };
+    [
+        // R2 = puntero a RAM
+        i(Opcode::ADDI, 2, 0, 0x100),
+        // bucle:
+        i(Opcode::ADDI, 1, 1, 1),
+        r(Opcode::XOR, 3, 1, 2),
+        mem(Opcode::STW, 3, 2, 0),
+        mem(Opcode::LDW, 4, 2, 0),
+        r(Opcode::ADD, 5, 5, 4),
+        i(Opcode::ANDI, 2, 1, 0xFFC),
+        Instruction::J {
+            opcode: Opcode::JMP,
+            offset: (-6i32) as u32,
+        },
+    ]
+    .iter()
+    .flat_map(|instr| instr.encode().to_le_bytes())
+    .collect()
+}
+
+fn mips(icache: bool) -> f64 {
+    let mut map = MemoryMap::new(ROM_BASE);
+    map.add_ram(0, RAM_SIZE);
+    map.add_rom(ROM_BASE, program());
+    let mut mem = MemoryBus::from_map(map);
+    mem.map_mmio(
+        FRAMEBUFFER_BASE,
+        0x4_0000,
+        Rc::new(RefCell::new(Framebuffer)),
+    );
+    let mut cpu = CPU::with_memory(mem, RAM_SIZE);
+    cpu.icache.enabled = icache;
+
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        cpu.step().unwrap();
+    }
+    STEPS as f64 / start.elapsed().as_secs_f64() / 1e6
+}
+
+fn main() {
+    let best = |icache| (0..RUNS).map(|_| mips(icache)).fold(0.0, f64::max);
+    let off = best(false);
+    let on = best(true);
+    println!("sin caché: {:8.2} MIPS", off);
+    println!("con caché: {:8.2} MIPS ({:.2}x)", on, on / off);
+}
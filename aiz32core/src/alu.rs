@@ -6,9 +6,16 @@ pub enum ALUOp {
     Nop,
     Add,
     Sub,
+    Adc,
+    Sbc,
     Mul,
     Div,
     Mod,
+    DivU,
+    ModU,
+    MulH,
+    MulHU,
+    MulHSU,
     Inc,
     Dec,
     Neg,
@@ -37,6 +44,18 @@ pub enum ALUOp {
     Zexth,
     Popcnt,
 
+    // Manipulación de bits
+    Clz,
+    Ctz,
+    Bswap,
+    Brev,
+    Bset,
+    Bclr,
+    Btst,
+    Bfext,
+    Bfexts,
+    Bfins,
+
     // Comparaciones
     Cmp,
     Ucmp,
@@ -46,9 +65,10 @@ pub enum ALUOp {
 }
 
 impl ALUOp {
-    pub fn from_opcode(opcode: Opcode) -> Self {
+    /// Operación ALU de un opcode R o I; `None` si el opcode no pasa por la ALU.
+    pub fn from_opcode(opcode: Opcode) -> Option<Self> {
         use ALUOp::*;
-        match opcode {
+        let op = match opcode {
             // R-type
             Opcode::NOP => Nop,
             Opcode::ADD => Add,
@@ -84,6 +104,7 @@ impl ALUOp {
             Opcode::PASS => Pass,
 
             // I-type
+            Opcode::NOPI => Nop,
             Opcode::ADDI => Add,
             Opcode::SUBI => Sub,
             Opcode::MULI => Mul,
@@ -116,8 +137,30 @@ impl ALUOp {
             Opcode::SETNZI => Setnz,
             Opcode::PASSI => Pass,
 
-            _ => panic!("Opcode {:?} no es una operación ALU válida", opcode),
-        }
+            // ALU extendida
+            Opcode::DIVU | Opcode::DIVUI => DivU,
+            Opcode::MODU | Opcode::MODUI => ModU,
+            Opcode::MULH => MulH,
+            Opcode::MULHU => MulHU,
+            Opcode::MULHSU => MulHSU,
+            Opcode::ADC | Opcode::ADCI => Adc,
+            Opcode::SBC | Opcode::SBCI => Sbc,
+
+            // Manipulación de bits
+            Opcode::CLZ => Clz,
+            Opcode::CTZ => Ctz,
+            Opcode::BSWAP => Bswap,
+            Opcode::BREV => Brev,
+            Opcode::BSET | Opcode::BSETI => Bset,
+            Opcode::BCLR | Opcode::BCLRI => Bclr,
+            Opcode::BTST | Opcode::BTSTI => Btst,
+            Opcode::BFEXT => Bfext,
+            Opcode::BFEXTS => Bfexts,
+            Opcode::BFINS => Bfins,
+
+            _ => return None,
+        };
+        Some(op)
     }
 }
 
@@ -134,9 +177,14 @@ pub struct Flags {
     pub less: bool,
     pub greater_equal: bool,
     pub less_equal: bool,
+
+    pub interrupt_enable: bool,
+    /// Modo usuario; a 0 (el estado de reset) la CPU está en modo supervisor.
+    pub user_mode: bool,
 }
 
 impl Flags {
+    #[inline]
     pub fn from_u32(value: u32) -> Self {
         Self {
             zero: (value & 0x01) != 0,
@@ -149,9 +197,12 @@ impl Flags {
             less: (value & 0x80) != 0,
             greater_equal: (value & 0x100) != 0,
             less_equal: (value & 0x200) != 0,
+            interrupt_enable: (value & 0x400) != 0,
+            user_mode: (value & 0x800) != 0,
         }
     }
 
+    #[inline]
     pub fn to_u32(&self) -> u32 {
         (if self.zero { 0x01 } else { 0 })
             | (if self.carry { 0x02 } else { 0 })
@@ -163,7 +214,96 @@ impl Flags {
             | (if self.less { 0x80 } else { 0 })
             | (if self.greater_equal { 0x100 } else { 0 })
             | (if self.less_equal { 0x200 } else { 0 })
+            | (if self.interrupt_enable { 0x400 } else { 0 })
+            | (if self.user_mode { 0x800 } else { 0 })
     }
+
+    pub fn holds(&self, cond: Condition) -> bool {
+        match cond {
+            Condition::Zero => self.zero,
+            Condition::NotZero => !self.zero,
+            Condition::Equal => self.equal,
+            Condition::NotEqual => self.not_equal,
+            Condition::Less => self.less,
+            Condition::Greater => self.greater,
+            Condition::LessEqual => self.less_equal,
+            Condition::GreaterEqual => self.greater_equal,
+            Condition::Carry => self.carry,
+            Condition::Overflow => self.overflow,
+        }
+    }
+}
+
+/// Condición sobre FLAGS; son las mismas que comprueban los saltos JZ..JO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Zero,
+    NotZero,
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Carry,
+    Overflow,
+}
+
+impl Condition {
+    pub const ALL: [Condition; 10] = [
+        Condition::Zero,
+        Condition::NotZero,
+        Condition::Equal,
+        Condition::NotEqual,
+        Condition::Less,
+        Condition::Greater,
+        Condition::LessEqual,
+        Condition::GreaterEqual,
+        Condition::Carry,
+        Condition::Overflow,
+    ];
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        Self::ALL.get(value as usize).copied()
+    }
+
+    /// Sufijo del mnemónico, como en `JZ` o `CMOVZ`.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Condition::Zero => "Z",
+            Condition::NotZero => "NZ",
+            Condition::Equal => "EQ",
+            Condition::NotEqual => "NE",
+            Condition::Less => "LT",
+            Condition::Greater => "GT",
+            Condition::LessEqual => "LE",
+            Condition::GreaterEqual => "GE",
+            Condition::Carry => "C",
+            Condition::Overflow => "O",
+        }
+    }
+}
+
+/// Codifica la posición y el ancho (1..=32) de un campo de bits en el
+/// inmediato de BFEXT/BFEXTS/BFINS: `pos` en los bits 4..0 y `width - 1` en
+/// los bits 9..5.
+pub const fn bit_field(pos: u32, width: u32) -> u32 {
+    (pos & 31) | ((width.wrapping_sub(1) & 31) << 5)
+}
+
+/// Posición y máscara (sin desplazar) del campo codificado en `field`.
+#[inline]
+fn field_mask(field: u32) -> (u32, u32) {
+    let pos = field & 31;
+    let width = ((field >> 5) & 31) + 1;
+    (pos, u32::MAX >> (32 - width))
+}
+
+/// Copia los `width` bits bajos de `src` en el campo de `dst`.
+#[inline]
+pub fn insert_field(dst: u32, src: u32, field: u32) -> u32 {
+    let (pos, mask) = field_mask(field);
+    (dst & !(mask << pos)) | ((src & mask) << pos)
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -172,6 +312,7 @@ pub struct ALUResult {
     pub flags: Flags,
 }
 
+#[derive(Default)]
 pub struct ALU;
 
 impl ALU {
@@ -179,7 +320,13 @@ impl ALU {
         Self {}
     }
 
+    /// BFINS: necesita el valor previo de `rd` además de la fuente.
     #[inline]
+    pub fn insert(dst: u32, src: u32, field: u32, in_flags: Flags) -> ALUResult {
+        Self::execute(ALUOp::Pass, insert_field(dst, src, field), 0, in_flags)
+    }
+
+    #[inline(always)]
     pub fn execute(op: ALUOp, a: u32, b: u32, in_flags: Flags) -> ALUResult {
         use ALUOp::*;
         let mut f = Flags { ..in_flags };
@@ -200,6 +347,23 @@ impl ALU {
                 f.overflow = overflow_sub_i32(a, b, r);
                 (r, true)
             }
+            // El acarreo de entrada es `carry`; en SBC, como en SUB, `carry`
+            // indica préstamo
+            Adc => {
+                let c = in_flags.carry as u32;
+                let sum = a as u64 + b as u64 + c as u64;
+                let wide = a as i32 as i64 + b as i32 as i64 + c as i64;
+                f.carry = sum > u32::MAX as u64;
+                f.overflow = wide != wide as i32 as i64;
+                (sum as u32, true)
+            }
+            Sbc => {
+                let c = in_flags.carry as u32;
+                let wide = a as i32 as i64 - b as i32 as i64 - c as i64;
+                f.carry = (a as u64) < b as u64 + c as u64;
+                f.overflow = wide != wide as i32 as i64;
+                (a.wrapping_sub(b).wrapping_sub(c), true)
+            }
             Inc => {
                 let (r, c) = a.overflowing_add(1);
                 f.carry = c;
@@ -234,7 +398,7 @@ impl ALU {
                 let b64 = b as i64;
                 let p128 = (a64 as i128) * (b64 as i128);
                 let r = (a64.wrapping_mul(b64)) as u32;
-                let hi = (p128 >> 32) as i128;
+                let hi = p128 >> 32;
                 let sign_ext_ok = hi == ((r as i32 as i128) >> 32);
                 f.overflow = !sign_ext_ok;
                 f.carry = !sign_ext_ok;
@@ -243,11 +407,7 @@ impl ALU {
             Div => {
                 let ai = a as i32;
                 let bi = b as i32;
-                if bi == 0 {
-                    f.overflow = true;
-                    f.carry = false;
-                    (0, true)
-                } else if ai == i32::MIN && bi == -1 {
+                if bi == 0 || (ai == i32::MIN && bi == -1) {
                     f.overflow = true;
                     f.carry = false;
                     (0, true)
@@ -274,6 +434,29 @@ impl ALU {
                     ((ai % bi) as u32, true)
                 }
             }
+            DivU | ModU => {
+                f.carry = false;
+                f.overflow = b == 0;
+                if b == 0 {
+                    (0, true)
+                } else if op == DivU {
+                    (a / b, true)
+                } else {
+                    (a % b, true)
+                }
+            }
+            // Palabra alta del producto de 64 bits; MULHSU toma `a` con signo
+            // y `b` sin signo
+            MulH | MulHU | MulHSU => {
+                let p = match op {
+                    MulH => (a as i32 as i64).wrapping_mul(b as i32 as i64),
+                    MulHU => (a as u64 * b as u64) as i64,
+                    _ => (a as i32 as i64) * (b as i64),
+                };
+                f.carry = false;
+                f.overflow = false;
+                ((p >> 32) as u32, true)
+            }
 
             And => (a & b, true),
             Or => (a | b, true),
@@ -284,7 +467,7 @@ impl ALU {
             Not => (!a, true),
 
             Shl => {
-                let sh = (b & 31) as u32;
+                let sh = b & 31;
                 f.carry = if sh == 0 {
                     false
                 } else {
@@ -293,7 +476,7 @@ impl ALU {
                 (a.wrapping_shl(sh), true)
             }
             Shr => {
-                let sh = (b & 31) as u32;
+                let sh = b & 31;
                 f.carry = if sh == 0 {
                     false
                 } else {
@@ -302,7 +485,7 @@ impl ALU {
                 (a.wrapping_shr(sh), true)
             }
             Sar => {
-                let sh = (b & 31) as u32;
+                let sh = b & 31;
                 let ai = a as i32;
                 f.carry = if sh == 0 {
                     false
@@ -353,6 +536,29 @@ impl ALU {
                 (c, true)
             }
 
+            Clz => (a.leading_zeros(), true),
+            Ctz => (a.trailing_zeros(), true),
+            Bswap => (a.swap_bytes(), true),
+            Brev => (a.reverse_bits(), true),
+            Bset => (a | (1 << (b & 31)), true),
+            Bclr => (a & !(1 << (b & 31)), true),
+            // Deja el bit en el resultado: `zero` indica que estaba a 0
+            Btst => ((a >> (b & 31)) & 1, true),
+            Bfext => {
+                let (pos, mask) = field_mask(b);
+                ((a >> pos) & mask, true)
+            }
+            Bfexts => {
+                let (pos, mask) = field_mask(b);
+                let shift = mask.leading_zeros();
+                (
+                    ((((a >> pos) & mask) << shift) as i32 >> shift) as u32,
+                    true,
+                )
+            }
+            // Sin el `rd` previo, inserta en cero; la CPU usa `ALU::insert`
+            Bfins => (insert_field(0, a, b), true),
+
             Cmp => {
                 touch_rel = true;
                 let ai = a as i32;
@@ -422,12 +628,12 @@ fn overflow_sub_i32(a: u32, b: u32, r: u32) -> bool {
 
 #[inline]
 fn rol(x: u32, n: u32) -> u32 {
-    let s = (n & 31) as u32;
+    let s = n & 31;
     if s == 0 { x } else { x.rotate_left(s) }
 }
 
 #[inline]
 fn ror(x: u32, n: u32) -> u32 {
-    let s = (n & 31) as u32;
+    let s = n & 31;
     if s == 0 { x } else { x.rotate_right(s) }
 }
@@ -0,0 +1,190 @@
+//! Sistema de memoria y puertos que ve la CPU.
+//!
+//! `CPU` es genérica sobre `Bus`, así que una herramienta puede sustituir la
+//! memoria por la suya (un bus que registra los accesos, uno que simula una
+//! caché, un arnés de pruebas sobre un `HashMap`...) sin perder el despacho
+//! estático. `SystemBus`, el par `MemoryBus` + `IO`, es la implementación por
+//! defecto.
+//!
+//! Los métodos con implementación por defecto son ganchos opcionales: un bus
+//! que no los implementa funciona igual, solo que sin caché de decodificación,
+//! sin estados de espera y sin guardar su contenido en los estados.
+
+use crate::fault::FaultKind;
+use crate::memory::{AccessCounts, IO, MemoryBus};
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+
+pub trait Bus {
+    fn read8(&mut self, addr: u32) -> Result<u8, FaultKind>;
+    fn read16(&mut self, addr: u32) -> Result<u16, FaultKind>;
+    fn read32(&mut self, addr: u32) -> Result<u32, FaultKind>;
+    fn write8(&mut self, addr: u32, value: u8) -> Result<(), FaultKind>;
+    fn write16(&mut self, addr: u32, value: u16) -> Result<(), FaultKind>;
+    fn write32(&mut self, addr: u32, value: u32) -> Result<(), FaultKind>;
+
+    fn port_read(&mut self, port: u16) -> u32;
+    fn port_write(&mut self, port: u16, value: u32);
+
+    /// Copia `len` bytes de `src` a `dst` con el mismo resultado que una
+    /// secuencia de `read8` y `write8` en orden ascendente. Un bus puede
+    /// sobrescribirlo para copiar de golpe.
+    ///
+    /// Si un acceso falla devuelve el fallo junto con los bytes que ya copió:
+    /// con los rangos solapados repetirlos no daría lo mismo, así que la
+    /// copia tiene que seguir desde ahí.
+    fn copy_block(&mut self, dst: u32, src: u32, len: u32) -> Result<(), (u32, FaultKind)> {
+        for i in 0..len {
+            let value = self.read8(src.wrapping_add(i)).map_err(|e| (i, e))?;
+            self.write8(dst.wrapping_add(i), value)
+                .map_err(|e| (i, e))?;
+        }
+        Ok(())
+    }
+
+    /// Escribe `value` en los `len` bytes que empiezan en `dst`.
+    fn fill_block(&mut self, dst: u32, value: u8, len: u32) -> Result<(), FaultKind> {
+        for i in 0..len {
+            self.write8(dst.wrapping_add(i), value)?;
+        }
+        Ok(())
+    }
+
+    /// Compara `len` bytes a partir de `a` y de `b` y devuelve la posición de
+    /// la primera diferencia y los dos bytes.
+    fn compare_block(
+        &mut self,
+        a: u32,
+        b: u32,
+        len: u32,
+    ) -> Result<Option<(u32, u8, u8)>, FaultKind> {
+        for i in 0..len {
+            let x = self.read8(a.wrapping_add(i))?;
+            let y = self.read8(b.wrapping_add(i))?;
+            if x != y {
+                return Ok(Some((i, x, y)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Versión del código en `addr` para la caché de decodificación: debe
+    /// cambiar cada vez que cambia lo que hay en esa dirección. `None` si no
+    /// se puede cachear.
+    fn code_version(&self, _addr: u32) -> Option<u32> {
+        None
+    }
+
+    /// Cuenta una búsqueda de instrucción servida por la caché de
+    /// decodificación, comprobando la dirección como haría `read32`.
+    fn touch(&mut self, addr: u32) -> Result<(), FaultKind> {
+        self.read32(addr).map(|_| ())
+    }
+
+    /// Accesos acumulados desde la última llamada, para el modelo de tiempo.
+    fn take_accesses(&mut self) -> AccessCounts {
+        AccessCounts::default()
+    }
+
+    fn save_state(&self, _out: &mut StateWriter) {}
+
+    fn load_state(&mut self, _input: &mut StateReader) -> Result<(), SnapshotError> {
+        Ok(())
+    }
+}
+
+/// Bus por defecto: la memoria del `MemoryBus` y los puertos de `IO`.
+pub struct SystemBus {
+    pub mem: MemoryBus,
+    pub io: IO,
+}
+
+impl SystemBus {
+    pub fn new(mem: MemoryBus) -> Self {
+        Self { mem, io: IO::new() }
+    }
+}
+
+impl Bus for SystemBus {
+    #[inline]
+    fn read8(&mut self, addr: u32) -> Result<u8, FaultKind> {
+        self.mem.read8(addr)
+    }
+
+    #[inline]
+    fn read16(&mut self, addr: u32) -> Result<u16, FaultKind> {
+        self.mem.read16(addr)
+    }
+
+    #[inline]
+    fn read32(&mut self, addr: u32) -> Result<u32, FaultKind> {
+        self.mem.read32(addr)
+    }
+
+    #[inline]
+    fn write8(&mut self, addr: u32, value: u8) -> Result<(), FaultKind> {
+        self.mem.write8(addr, value)
+    }
+
+    #[inline]
+    fn write16(&mut self, addr: u32, value: u16) -> Result<(), FaultKind> {
+        self.mem.write16(addr, value)
+    }
+
+    #[inline]
+    fn write32(&mut self, addr: u32, value: u32) -> Result<(), FaultKind> {
+        self.mem.write32(addr, value)
+    }
+
+    fn copy_block(&mut self, dst: u32, src: u32, len: u32) -> Result<(), (u32, FaultKind)> {
+        self.mem.copy_block(dst, src, len)
+    }
+
+    fn fill_block(&mut self, dst: u32, value: u8, len: u32) -> Result<(), FaultKind> {
+        self.mem.fill_block(dst, value, len)
+    }
+
+    fn compare_block(
+        &mut self,
+        a: u32,
+        b: u32,
+        len: u32,
+    ) -> Result<Option<(u32, u8, u8)>, FaultKind> {
+        self.mem.compare_block(a, b, len)
+    }
+
+    fn port_read(&mut self, port: u16) -> u32 {
+        self.io.read(port)
+    }
+
+    fn port_write(&mut self, port: u16, value: u32) {
+        self.io.write(port, value)
+    }
+
+    #[inline]
+    fn code_version(&self, addr: u32) -> Option<u32> {
+        if self.mem.is_mmio(addr) {
+            None
+        } else {
+            Some(self.mem.code_version(addr))
+        }
+    }
+
+    #[inline]
+    fn touch(&mut self, addr: u32) -> Result<(), FaultKind> {
+        self.mem.touch(addr, 4)
+    }
+
+    fn take_accesses(&mut self) -> AccessCounts {
+        self.mem.take_accesses()
+    }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        self.mem.save_state(out);
+        self.io.save_state(out);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.mem.load_state(input)?;
+        self.io.load_state(input)
+    }
+}
@@ -1,7 +1,23 @@
-use crate::alu::{ALU, ALUOp, ALUResult, Flags};
-use crate::instruction::{Instruction, Opcode};
-use crate::memory::{IO, MemoryBus};
-use crate::registers::RegisterBank;
+use std::ops::{Deref, DerefMut};
+use std::{cell::RefCell, cmp::Ordering, fs, path::Path, rc::Rc};
+
+use crate::alu::{ALU, ALUOp, ALUResult, Condition, Flags};
+use crate::bus::{Bus, SystemBus};
+use crate::fault::{CpuFault, FaultKind, SYSCALL_CAUSE, SYSCALL_VECTOR, StepOutcome};
+use crate::fpu::{FCSR_INVALID, FCSR_MASK, Float, Fpu, Rounding};
+use crate::icache::DecodeCache;
+use crate::instruction::{
+    AddrMode, Instruction, Opcode, REGLIST_LR, reglist_len, reglist_registers,
+};
+use crate::interrupt::{InterruptController, IrqLine};
+use crate::isa::{Format, OPCODE};
+use crate::memory::MemoryBus;
+use crate::mmu::{Access, Mmu, PAGE_SIZE};
+use crate::packed::PackedOp;
+use crate::peripheral::Peripheral;
+use crate::registers::{FRAME_POINTER, RegisterBank};
+use crate::snapshot::{SNAPSHOT_MAGIC, SNAPSHOT_VERSION, SnapshotError, StateReader, StateWriter};
+use crate::timing::{Timing, VECTOR_ENTRY_CYCLES};
 
 fn sign_extend_24(offset: u32) -> i32 {
     if offset & 0x800000 != 0 {
@@ -11,57 +27,650 @@ fn sign_extend_24(offset: u32) -> i32 {
     }
 }
 
-pub struct CPU {
+/// Núcleo de la CPU sobre un sistema de memoria y puertos `B`.
+///
+/// El PIC y la MMU van dentro de la CPU: sus puertos se atienden antes de
+/// llegar al bus, así que funcionan con cualquier `Bus`.
+pub struct CPU<B: Bus = SystemBus> {
     pub regs: RegisterBank,
-    pub mem: MemoryBus,
+    pub bus: B,
     pub alu: ALU,
     pub cycle_count: u64,
     pub halted: bool,
-    pub io: IO,
+    pub pic: Rc<RefCell<InterruptController>>,
+    pub mmu: Rc<RefCell<Mmu>>,
+    /// DIV/MOD (y sus variantes sin signo) por cero producen `FaultKind::DivideByZero` en vez de activar overflow.
+    pub trap_div_zero: bool,
+    /// Los fallos se entregan al programa por su vector de excepción en vez de
+    /// devolverse a quien llama a `step`.
+    pub trap_faults: bool,
+    pub timing: Timing,
+    pub icache: DecodeCache,
 }
 
 impl CPU {
+    /// CPU con el mapa clásico: RAM desde 0 y ROM justo detrás.
     pub fn new(ram_size: usize, rom_contents: Vec<u8>, sp_dir: u32, pc_dir: u32) -> Self {
+        let mut cpu = Self::with_memory(MemoryBus::new(ram_size, rom_contents), sp_dir);
+        cpu.regs.set_pc(pc_dir);
+        cpu
+    }
+
+    /// CPU sobre una memoria ya construida; arranca en su vector de reset.
+    pub fn with_memory(mem: MemoryBus, sp_dir: u32) -> Self {
+        let pc_dir = mem.reset_vector();
+        Self::with_bus(SystemBus::new(mem), sp_dir, pc_dir)
+    }
+}
+
+/// Con el bus por defecto, `cpu.mem` y `cpu.io` siguen llegando a la memoria
+/// y a los puertos.
+impl Deref for CPU {
+    type Target = SystemBus;
+
+    fn deref(&self) -> &SystemBus {
+        &self.bus
+    }
+}
+
+impl DerefMut for CPU {
+    fn deref_mut(&mut self) -> &mut SystemBus {
+        &mut self.bus
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    pub fn with_bus(bus: B, sp_dir: u32, pc_dir: u32) -> Self {
         Self {
             regs: RegisterBank::new(pc_dir, sp_dir),
-            mem: MemoryBus::new(ram_size, rom_contents),
+            bus,
             alu: ALU::new(),
             cycle_count: 0,
             halted: false,
-            io: IO::new(),
+            pic: Rc::new(RefCell::new(InterruptController::new())),
+            mmu: Rc::new(RefCell::new(Mmu::new())),
+            trap_div_zero: false,
+            trap_faults: false,
+            timing: Timing::default(),
+            icache: DecodeCache::new(),
+        }
+    }
+
+    pub fn irq_line(&self, irq: u8) -> IrqLine {
+        IrqLine::new(self.pic.clone(), irq)
+    }
+
+    /// Lee un puerto como `IN`: primero el PIC y la MMU, luego el bus.
+    pub fn port_read(&mut self, port: u16) -> u32 {
+        if self.pic.borrow().handles_port(port) {
+            return self.pic.borrow().read(port);
+        }
+        if self.mmu.borrow().handles_port(port) {
+            return self.mmu.borrow().read(port);
+        }
+        self.bus.port_read(port)
+    }
+
+    /// Escribe un puerto como `OUT`.
+    pub fn port_write(&mut self, port: u16, value: u32) {
+        if self.pic.borrow().handles_port(port) {
+            self.pic.borrow_mut().write(port, value);
+        } else if self.mmu.borrow().handles_port(port) {
+            self.mmu.borrow_mut().write(port, value);
+        } else {
+            self.bus.port_write(port, value);
+        }
+    }
+
+    /// Serializa la máquina completa: registros, contador de ciclos, PIC, MMU
+    /// y lo que guarde el bus (con `SystemBus`, la RAM, los puertos y los
+    /// periféricos). La configuración (`timing`, `trap_*`) no se guarda.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = StateWriter::new();
+        for byte in SNAPSHOT_MAGIC {
+            out.write_u8(byte);
         }
+        out.write_u32(SNAPSHOT_VERSION);
+        self.regs.save_state(&mut out);
+        out.write_u64(self.cycle_count);
+        out.write_bool(self.halted);
+        self.pic.borrow().save_state(&mut out);
+        self.mmu.borrow().save_state(&mut out);
+        self.bus.save_state(&mut out);
+        out.into_bytes()
+    }
+
+    /// Restaura un estado de `save_state`. Si el estado no es válido la
+    /// máquina queda como estaba.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let backup = self.save_state();
+        self.restore(data).inspect_err(|_| {
+            self.restore(&backup)
+                .expect("no se pudo restaurar el estado previo");
+        })
     }
 
-    pub fn step(&mut self) {
+    fn restore(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        let mut input = StateReader::new(data);
+        for byte in SNAPSHOT_MAGIC {
+            if input.read_u8()? != byte {
+                return Err(SnapshotError::BadMagic);
+            }
+        }
+        let version = input.read_u32()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        self.regs.load_state(&mut input)?;
+        self.cycle_count = input.read_u64()?;
+        self.halted = input.read_bool()?;
+        self.pic.borrow_mut().load_state(&mut input)?;
+        self.mmu.borrow_mut().load_state(&mut input)?;
+        self.bus.load_state(&mut input)?;
+        if !input.is_empty() {
+            return Err(SnapshotError::Mismatch("trailing data"));
+        }
+        Ok(())
+    }
+
+    pub fn save_state_file(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        fs::write(path, self.save_state())?;
+        Ok(())
+    }
+
+    pub fn load_state_file(&mut self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let data = fs::read(path)?;
+        self.load_state(&data)
+    }
+
+    pub fn step(&mut self) -> Result<StepOutcome, CpuFault> {
         if self.halted {
-            return;
+            return Ok(StepOutcome::Halted);
         }
 
         let pc = self.regs.pc();
-        let raw_instr = self.mem.read32(pc);
+        // Los accesos hechos desde fuera de `step` no se cobran
+        self.bus.take_accesses();
+
+        if let Some(irq) = self.pending_interrupt() {
+            let result = self.enter_interrupt(irq);
+            self.charge(VECTOR_ENTRY_CYCLES);
+            result.map_err(|kind| CpuFault { pc, raw: 0, kind })?;
+            return Ok(StepOutcome::Interrupt(irq));
+        }
+
+        let mut raw_instr = 0;
+        let result = self.fetch_execute(pc, &mut raw_instr);
+        self.charge(self.instruction_cycles(raw_instr));
+
+        match result {
+            Ok(update_pc) => {
+                if !update_pc {
+                    self.regs.set_pc(pc.wrapping_add(4));
+                }
+                if OPCODE.extract(raw_instr) == Opcode::SYSCALL as u32 {
+                    return Ok(StepOutcome::Syscall(self.pic.borrow().fault_addr()));
+                }
+                Ok(StepOutcome::Executed)
+            }
+            Err(kind) => {
+                let fault = CpuFault {
+                    pc,
+                    raw: raw_instr,
+                    kind,
+                };
+                if !self.trap_faults {
+                    self.regs.set_pc(pc);
+                    return Err(fault);
+                }
+                // Un fallo al entrar en el manejador no se puede entregar al programa
+                let result = self.enter_exception(fault);
+                self.charge(VECTOR_ENTRY_CYCLES);
+                result.map_err(|kind| CpuFault { kind, ..fault })?;
+                Ok(StepOutcome::Exception(fault))
+            }
+        }
+    }
+
+    fn fetch_execute(&mut self, pc: u32, raw_instr: &mut u32) -> Result<bool, FaultKind> {
+        let instr = self.fetch(pc, raw_instr)?;
+        self.execute(instr)
+    }
+
+    // Busca y decodifica la instrucción en `pc`, pasando por la caché. Un
+    // acierto sigue contando como acceso a memoria para el modelo de tiempo.
+    // La caché va por dirección física: la misma dirección virtual puede ser
+    // otro código en otro espacio de direcciones. Lo que el bus no deja
+    // cachear (MMIO, por ejemplo) se lee siempre.
+    fn fetch(&mut self, pc: u32, raw_instr: &mut u32) -> Result<Instruction, FaultKind> {
+        let addr = self.translate(pc, Access::Execute)?;
+        let Some(version) = self.bus.code_version(addr) else {
+            *raw_instr = self.bus.read32(addr)?;
+            return Instruction::decode(*raw_instr);
+        };
+
+        if let Some((raw, instr)) = self.icache.lookup(addr, version) {
+            self.bus.touch(addr)?;
+            *raw_instr = raw;
+            return Ok(instr);
+        }
+
+        *raw_instr = self.bus.read32(addr)?;
+        let instr = Instruction::decode(*raw_instr)?;
+        self.icache.insert(addr, version, *raw_instr, instr);
+        Ok(instr)
+    }
+
+    // Traduce una dirección virtual con el modo de privilegio actual
+    fn translate(&mut self, addr: u32, access: Access) -> Result<u32, FaultKind> {
+        let user = Flags::from_u32(self.regs.flags()).user_mode;
+        self.translate_as(addr, access, user)
+    }
+
+    fn translate_as(&mut self, addr: u32, access: Access, user: bool) -> Result<u32, FaultKind> {
+        self.mmu
+            .borrow_mut()
+            .translate(&mut self.bus, addr, access, user)
+    }
+
+    fn read8(&mut self, addr: u32) -> Result<u8, FaultKind> {
+        let addr = self.translate(addr, Access::Read)?;
+        self.bus.read8(addr)
+    }
+
+    fn read16(&mut self, addr: u32) -> Result<u16, FaultKind> {
+        let addr = self.translate(addr, Access::Read)?;
+        self.bus.read16(addr)
+    }
+
+    fn read32(&mut self, addr: u32) -> Result<u32, FaultKind> {
+        let addr = self.translate(addr, Access::Read)?;
+        self.bus.read32(addr)
+    }
+
+    fn write8(&mut self, addr: u32, value: u8) -> Result<(), FaultKind> {
+        let addr = self.translate(addr, Access::Write)?;
+        self.bus.write8(addr, value)
+    }
+
+    fn write16(&mut self, addr: u32, value: u16) -> Result<(), FaultKind> {
+        let addr = self.translate(addr, Access::Write)?;
+        self.bus.write16(addr, value)
+    }
+
+    fn write32(&mut self, addr: u32, value: u32) -> Result<(), FaultKind> {
+        let addr = self.translate(addr, Access::Write)?;
+        self.bus.write32(addr, value)
+    }
+
+    fn instruction_cycles(&self, raw: u32) -> u32 {
+        match Opcode::from_u8(OPCODE.extract(raw) as u8) {
+            Some(opcode @ (Opcode::IN | Opcode::OUT)) => {
+                self.timing.base_cycles(opcode) + self.timing.io_wait
+            }
+            // Un ciclo más por registro de la lista
+            Some(opcode @ (Opcode::PUSHM | Opcode::POPM)) => {
+                let list = opcode.format().layout().imm.map_or(0, |f| f.extract(raw));
+                self.timing.base_cycles(opcode) + reglist_len(list)
+            }
+            Some(opcode @ Opcode::PACKED) => {
+                let funct = opcode.format().layout().imm.map_or(0, |f| f.extract(raw));
+                match PackedOp::from_funct(funct as u8) {
+                    // PMULHUB/PMULHUH multiplican como MULHU
+                    Some((PackedOp::MulHighU, _)) => self.timing.base_cycles(Opcode::MULHU),
+                    _ => self.timing.base_cycles(opcode),
+                }
+            }
+            Some(opcode) => self.timing.base_cycles(opcode),
+            None => 1,
+        }
+    }
+
+    // Suma el coste base y los estados de espera de los accesos a memoria hechos
+    fn charge(&mut self, base: u32) {
+        let accesses = self.bus.take_accesses();
+        self.cycle_count += base as u64 + self.timing.memory_cycles(accesses);
+    }
+
+    fn pending_interrupt(&self) -> Option<u8> {
+        if !Flags::from_u32(self.regs.flags()).interrupt_enable {
+            return None;
+        }
+        self.pic.borrow().next_pending()
+    }
+
+    // Guarda FLAGS y PC en la pila, deshabilita interrupciones y salta al vector
+    fn enter_interrupt(&mut self, irq: u8) -> Result<(), FaultKind> {
+        let vector_addr = {
+            let mut pic = self.pic.borrow_mut();
+            pic.clear(irq);
+            pic.vector_addr(irq)
+        };
+        self.enter_vector(vector_addr)
+    }
+
+    // Igual que una interrupción, pero el PC guardado es el de la instrucción que falló
+    fn enter_exception(&mut self, fault: CpuFault) -> Result<(), FaultKind> {
+        let vector_addr = {
+            let mut pic = self.pic.borrow_mut();
+            pic.record_fault(fault.kind.code(), fault.kind.addr().unwrap_or(fault.raw));
+            pic.vector_addr(fault.kind.vector())
+        };
+        self.regs.set_pc(fault.pc);
+        self.enter_vector(vector_addr)
+    }
+
+    // El marco siempre va a la pila de supervisor. Si algo falla, la CPU
+    // queda como estaba.
+    fn enter_vector(&mut self, vector_addr: u32) -> Result<(), FaultKind> {
+        let handler_addr = self.translate_as(vector_addr, Access::Read, false)?;
+        let handler = self.bus.read32(handler_addr)?;
+
+        let old_flags = self.regs.flags();
+        let mut flags = Flags::from_u32(old_flags);
+        let ssp = if flags.user_mode {
+            self.regs.banked_sp()
+        } else {
+            self.regs.sp()
+        };
+        let flags_addr = self.translate_as(ssp.wrapping_sub(4), Access::Write, false)?;
+        let pc_addr = self.translate_as(ssp.wrapping_sub(8), Access::Write, false)?;
+        self.bus.write32(flags_addr, old_flags)?;
+        self.bus.write32(pc_addr, self.regs.pc())?;
+
+        flags.interrupt_enable = false;
+        flags.user_mode = false;
+        self.set_status(flags.to_u32());
+        self.regs.set_sp(ssp.wrapping_sub(8));
+
+        self.regs.set_pc(handler);
+        Ok(())
+    }
+
+    // Escribe FLAGS e intercambia las pilas si cambia el modo de privilegio
+    fn set_status(&mut self, value: u32) {
+        let was_user = Flags::from_u32(self.regs.flags()).user_mode;
+        self.regs.set_flags(value);
+        if Flags::from_u32(value).user_mode != was_user {
+            self.regs.swap_sp();
+        }
+    }
+
+    fn require_supervisor(&self) -> Result<(), FaultKind> {
+        if Flags::from_u32(self.regs.flags()).user_mode {
+            Err(FaultKind::PrivilegeViolation)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Privilegiadas: E/S, escritura de FLAGS, control de interrupciones,
+    // vuelta de trampa, HALT y acceso a la pila de usuario
+    fn is_privileged(opcode: Opcode) -> bool {
+        matches!(
+            opcode,
+            Opcode::IN
+                | Opcode::OUT
+                | Opcode::MTSR
+                | Opcode::EI
+                | Opcode::DI
+                | Opcode::RETI
+                | Opcode::HALT
+                | Opcode::MFUSP
+                | Opcode::MTUSP
+        )
+    }
+
+    fn push(&mut self, value: u32) -> Result<(), FaultKind> {
+        let sp = self.regs.sp().wrapping_sub(4);
+        self.write32(sp, value)?;
+        self.regs.set_sp(sp);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<u32, FaultKind> {
+        let sp = self.regs.sp();
+        let value = self.read32(sp)?;
+        self.regs.set_sp(sp.wrapping_add(4));
+        Ok(value)
+    }
 
-        let instr = Instruction::decode(raw_instr);
+    // Una comparación desordenada (algún NaN) deja todas las relaciones a 0
+    // salvo not_equal y activa overflow
+    fn set_fp_compare_flags(&mut self, ord: Option<Ordering>) {
+        let mut flags = Flags::from_u32(self.regs.flags());
+        flags.equal = ord == Some(Ordering::Equal);
+        flags.zero = flags.equal;
+        flags.not_equal = !flags.equal;
+        flags.less = ord == Some(Ordering::Less);
+        flags.greater = ord == Some(Ordering::Greater);
+        flags.less_equal = flags.less || flags.equal;
+        flags.greater_equal = flags.greater || flags.equal;
+        flags.overflow = ord.is_none();
+        self.regs.set_flags(flags.to_u32());
+    }
 
-        let update_pc = self.execute(instr);
+    fn is_double(opcode: Opcode) -> bool {
+        opcode.format() == Format::FPD
+    }
 
-        if !update_pc {
-            self.regs.set_pc(pc.wrapping_add(4));
+    // Los pares de doble precisión empiezan en un registro par; con uno
+    // impar la instrucción es ilegal
+    fn check_dreg(idx: u8) -> Result<(), FaultKind> {
+        if idx.is_multiple_of(2) {
+            Ok(())
+        } else {
+            Err(FaultKind::IllegalOpcode)
         }
+    }
+
+    fn dreg(&self, idx: u8) -> Result<f64, FaultKind> {
+        Self::check_dreg(idx)?;
+        Ok(self.regs.dget(idx))
+    }
 
-        self.cycle_count += 1;
+    fn set_dreg(&mut self, idx: u8, value: f64) -> Result<(), FaultKind> {
+        Self::check_dreg(idx)?;
+        self.regs.dset(idx, value);
+        Ok(())
     }
 
-    pub fn execute(&mut self, instr: Instruction) -> bool {
+    fn execute_double(
+        &mut self,
+        fpu: &mut Fpu,
+        opcode: Opcode,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+    ) -> Result<(), FaultKind> {
+        match opcode {
+            Opcode::ITOD => return self.set_dreg(rd, fpu.from_i32(self.regs.get(rs1) as i32)),
+            Opcode::UTOD => return self.set_dreg(rd, fpu.from_u32(self.regs.get(rs1))),
+            // f32 -> f64 siempre es exacta; solo un NaN señalizador levanta
+            // invalid
+            Opcode::FTOD => {
+                let a = self.regs.fget(rs1);
+                let value = if a.is_nan() {
+                    if a.is_signaling() {
+                        fpu.flags |= FCSR_INVALID;
+                    }
+                    f64::CANONICAL_NAN
+                } else {
+                    a as f64
+                };
+                return self.set_dreg(rd, value);
+            }
+            _ => {}
+        }
+
+        let a = self.dreg(rs1)?;
+        match opcode {
+            Opcode::FSQRTD => self.set_dreg(rd, fpu.sqrt(a)),
+            Opcode::FABSD => self.set_dreg(rd, a.abs()),
+            Opcode::FNEGD => self.set_dreg(rd, -a),
+            Opcode::FMOVD => self.set_dreg(rd, a),
+            Opcode::DTOI => {
+                self.regs.set(rd, fpu.to_i32(a) as u32);
+                Ok(())
+            }
+            Opcode::DTOIU => {
+                self.regs.set(rd, fpu.to_u32(a));
+                Ok(())
+            }
+            Opcode::DTOF => {
+                self.regs.fset(rd, fpu.narrow(a));
+                Ok(())
+            }
+            _ => {
+                let b = self.dreg(rs2)?;
+                match opcode {
+                    Opcode::FADDD => self.set_dreg(rd, fpu.add(a, b)),
+                    Opcode::FSUBD => self.set_dreg(rd, fpu.sub(a, b)),
+                    Opcode::FMULD => self.set_dreg(rd, fpu.mul(a, b)),
+                    Opcode::FDIVD => self.set_dreg(rd, fpu.div(a, b)),
+                    Opcode::FMAD => {
+                        let c = self.dreg(rd)?;
+                        self.set_dreg(rd, fpu.fma(a, b, c))
+                    }
+                    Opcode::FMIND => self.set_dreg(rd, fpu.min_max(a, b, false)),
+                    Opcode::FMAXD => self.set_dreg(rd, fpu.min_max(a, b, true)),
+                    Opcode::FCMPD => {
+                        let ord = fpu.compare(a, b, true);
+                        self.set_fp_compare_flags(ord);
+                        Ok(())
+                    }
+                    Opcode::FEQD => {
+                        let ord = fpu.compare(a, b, false);
+                        self.regs.set(rd, (ord == Some(Ordering::Equal)) as u32);
+                        Ok(())
+                    }
+                    Opcode::FLTD => {
+                        let ord = fpu.compare(a, b, true);
+                        self.regs.set(rd, (ord == Some(Ordering::Less)) as u32);
+                        Ok(())
+                    }
+                    Opcode::FGTD => {
+                        let ord = fpu.compare(a, b, true);
+                        self.regs.set(rd, (ord == Some(Ordering::Greater)) as u32);
+                        Ok(())
+                    }
+                    _ => Err(FaultKind::IllegalOpcode),
+                }
+            }
+        }
+    }
+
+    fn check_div_zero(&self, alu_op: ALUOp, divisor: u32) -> Result<(), FaultKind> {
+        if self.trap_div_zero
+            && matches!(alu_op, ALUOp::Div | ALUOp::Mod | ALUOp::DivU | ALUOp::ModU)
+            && divisor == 0
+        {
+            Err(FaultKind::DivideByZero)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Un tramo de MEMCPY, MEMSET o MEMCMP, que acaba como mucho al final de
+    // la página del destino o del origen para traducir cada uno una sola vez.
+    // Los registros avanzan al terminar cada tramo: entre tramos se atienden
+    // interrupciones y tras un fallo la instrucción sigue por donde iba.
+    // Devuelve si quedan bytes, para volver a ejecutarla.
+    fn execute_block(
+        &mut self,
+        opcode: Opcode,
+        rd: u8,
+        rs1: u8,
+        rs2: u8,
+    ) -> Result<bool, FaultKind> {
+        // Cada registro lleva su propio progreso, también en MEMSET aunque no
+        // avance rs1
+        if rd == rs1 || rd == rs2 || rs1 == rs2 {
+            return Err(FaultKind::IllegalOpcode);
+        }
+        let dst = self.regs.get(rd);
+        let src = self.regs.get(rs1);
+        let len = self.regs.get(rs2);
+        let page_left = |addr: u32| PAGE_SIZE - addr % PAGE_SIZE;
+        let mut n = len.min(page_left(dst));
+        if opcode != Opcode::MEMSET {
+            n = n.min(page_left(src));
+        }
+
+        // Bytes hechos y bytes que se cobran: MEMCMP lee también el que difiere
+        let (done, charged, mismatch, fault) = match (opcode, n) {
+            (_, 0) => (0, 0, None, None),
+            (Opcode::MEMCPY, _) => {
+                let from = self.translate(src, Access::Read)?;
+                let to = self.translate(dst, Access::Write)?;
+                // Lo copiado antes de un fallo queda hecho: con el destino
+                // dentro del origen, repetirlo leería bytes ya sobrescritos
+                match self.bus.copy_block(to, from, n) {
+                    Ok(()) => (n, n, None, None),
+                    Err((copied, kind)) => (copied, copied, None, Some(kind)),
+                }
+            }
+            (Opcode::MEMSET, _) => {
+                let to = self.translate(dst, Access::Write)?;
+                self.bus.fill_block(to, src as u8, n)?;
+                (n, n, None, None)
+            }
+            _ => {
+                let a = self.translate(dst, Access::Read)?;
+                let b = self.translate(src, Access::Read)?;
+                match self.bus.compare_block(a, b, n)? {
+                    Some((i, x, y)) => (i, i + 1, Some((x, y)), None),
+                    None => (n, n, None, None),
+                }
+            }
+        };
+
+        self.cycle_count += charged as u64 * self.timing.block_byte_cycles as u64;
+        self.regs.set(rd, dst.wrapping_add(done));
+        if opcode != Opcode::MEMSET {
+            self.regs.set(rs1, src.wrapping_add(done));
+        }
+        self.regs.set(rs2, len - done);
+        if let Some(kind) = fault {
+            return Err(kind);
+        }
+
+        // MEMCMP deja los flags de UCMP entre los bytes que difieren, o los de
+        // igualdad si no hay diferencia
+        let finished = mismatch.is_some() || done == len;
+        if opcode == Opcode::MEMCMP && finished {
+            let (x, y) = mismatch.unwrap_or((0, 0));
+            let in_flags = Flags::from_u32(self.regs.flags());
+            let result = ALU::execute(ALUOp::Ucmp, x as u32, y as u32, in_flags);
+            self.regs.set_flags(result.flags.to_u32());
+        }
+        Ok(!finished)
+    }
+
+    pub fn execute(&mut self, instr: Instruction) -> Result<bool, FaultKind> {
+        if Self::is_privileged(instr.opcode()) {
+            self.require_supervisor()?;
+        }
+
         let mut update_pc = false;
         match instr {
             // R-type
+            Instruction::R {
+                opcode: opcode @ (Opcode::MEMCPY | Opcode::MEMSET | Opcode::MEMCMP),
+                rd,
+                rs1,
+                rs2,
+            } => {
+                update_pc = self.execute_block(opcode, rd, rs1, rs2)?;
+            }
+
             Instruction::R {
                 opcode,
                 rd,
                 rs1,
                 rs2,
             } => {
-                let alu_op = ALUOp::from_opcode(opcode);
+                let alu_op = ALUOp::from_opcode(opcode).ok_or(FaultKind::IllegalOpcode)?;
                 let in_flags = Flags::from_u32(self.regs.flags());
 
                 let result: ALUResult = if matches!(alu_op, ALUOp::Cmp | ALUOp::Ucmp) {
@@ -71,16 +680,47 @@ impl CPU {
                 } else {
                     let a = self.regs.get(rs1);
                     let b = self.regs.get(rs2);
+                    self.check_div_zero(alu_op, b)?;
                     ALU::execute(alu_op, a, b, in_flags)
                 };
 
-                if !matches!(alu_op, ALUOp::Cmp | ALUOp::Ucmp) {
+                if !matches!(alu_op, ALUOp::Nop | ALUOp::Cmp | ALUOp::Ucmp) {
                     self.regs.set(rd, result.value);
                 }
 
                 self.regs.set_flags(result.flags.to_u32());
             }
 
+            Instruction::RC {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+                cond,
+            } => {
+                let cond = Condition::from_u8(cond).ok_or(FaultKind::IllegalOpcode)?;
+                let flags = Flags::from_u32(self.regs.flags());
+                match opcode {
+                    Opcode::SEL => {
+                        let src = if flags.holds(cond) { rs1 } else { rs2 };
+                        self.regs.set(rd, self.regs.get(src));
+                    }
+                    _ => return Err(FaultKind::IllegalOpcode),
+                }
+            }
+
+            Instruction::RP {
+                rd,
+                rs1,
+                rs2,
+                funct,
+                ..
+            } => {
+                let (op, lanes) = PackedOp::from_funct(funct).ok_or(FaultKind::IllegalOpcode)?;
+                let value = op.execute(lanes, self.regs.get(rs1), self.regs.get(rs2));
+                self.regs.set(rd, value);
+            }
+
             // I-type
             Instruction::I {
                 opcode,
@@ -88,19 +728,22 @@ impl CPU {
                 rs1,
                 imm,
             } => {
-                let alu_op = ALUOp::from_opcode(opcode);
+                let alu_op = ALUOp::from_opcode(opcode).ok_or(FaultKind::IllegalOpcode)?;
                 let in_flags = Flags::from_u32(self.regs.flags());
 
                 let result: ALUResult = if matches!(alu_op, ALUOp::Cmp | ALUOp::Ucmp) {
                     // Para CMPI/UCMPI, usar rd y imm
                     let a = self.regs.get(rd);
                     ALU::execute(alu_op, a, imm, in_flags)
+                } else if alu_op == ALUOp::Bfins {
+                    ALU::insert(self.regs.get(rd), self.regs.get(rs1), imm, in_flags)
                 } else {
                     let a = self.regs.get(rs1);
+                    self.check_div_zero(alu_op, imm)?;
                     ALU::execute(alu_op, a, imm, in_flags)
                 };
 
-                if !matches!(alu_op, ALUOp::Cmp | ALUOp::Ucmp) {
+                if !matches!(alu_op, ALUOp::Nop | ALUOp::Cmp | ALUOp::Ucmp) {
                     self.regs.set(rd, result.value);
                 }
 
@@ -111,87 +754,26 @@ impl CPU {
             Instruction::J { opcode, offset } => {
                 let flags = Flags::from_u32(self.regs.flags());
                 let pc = self.regs.pc();
-                let offset = sign_extend_24(offset as u32) as i32;
+                let offset = sign_extend_24(offset);
 
                 let target = match opcode {
                     Opcode::JMP => {
                         update_pc = true;
                         pc.wrapping_add((offset * 4) as u32)
                     }
-                    Opcode::JZ => {
-                        if flags.zero {
-                            update_pc = true;
-                            pc.wrapping_add((offset * 4) as u32)
-                        } else {
-                            pc
-                        }
-                    }
-                    Opcode::JNZ => {
-                        if !flags.zero {
-                            update_pc = true;
-                            pc.wrapping_add((offset * 4) as u32)
-                        } else {
-                            pc
-                        }
-                    }
-                    Opcode::JEQ => {
-                        if flags.equal {
-                            update_pc = true;
-                            pc.wrapping_add((offset * 4) as u32)
-                        } else {
-                            pc
-                        }
-                    }
-                    Opcode::JNE => {
-                        if flags.not_equal {
-                            update_pc = true;
-                            pc.wrapping_add((offset * 4) as u32)
-                        } else {
-                            pc
-                        }
-                    }
-                    Opcode::JLT => {
-                        if flags.less {
-                            update_pc = true;
-                            pc.wrapping_add((offset * 4) as u32)
-                        } else {
-                            pc
-                        }
-                    }
-                    Opcode::JGT => {
-                        if flags.greater {
-                            update_pc = true;
-                            pc.wrapping_add((offset * 4) as u32)
-                        } else {
-                            pc
-                        }
-                    }
-                    Opcode::JLE => {
-                        if flags.less_equal {
-                            update_pc = true;
-                            pc.wrapping_add((offset * 4) as u32)
-                        } else {
-                            pc
-                        }
-                    }
-                    Opcode::JGE => {
-                        if flags.greater_equal {
-                            update_pc = true;
-                            pc.wrapping_add((offset * 4) as u32)
-                        } else {
-                            pc
-                        }
-                    }
-                    Opcode::JC => {
-                        if flags.carry {
-                            update_pc = true;
-                            pc.wrapping_add((offset * 4) as u32)
-                        } else {
-                            pc
-                        }
-                    }
-                    Opcode::JO => {
-                        if flags.overflow {
+                    Opcode::JZ
+                    | Opcode::JNZ
+                    | Opcode::JEQ
+                    | Opcode::JNE
+                    | Opcode::JLT
+                    | Opcode::JGT
+                    | Opcode::JLE
+                    | Opcode::JGE
+                    | Opcode::JC
+                    | Opcode::JO => {
+                        // Mismo orden que `Condition::ALL`
+                        let cond = Condition::ALL[(opcode as u8 - Opcode::JZ as u8) as usize];
+                        if flags.holds(cond) {
                             update_pc = true;
                             pc.wrapping_add((offset * 4) as u32)
                         } else {
@@ -202,22 +784,26 @@ impl CPU {
                         update_pc = true;
                         let ret_addr = pc.wrapping_add(4);
 
+                        self.push(ret_addr)?;
                         self.regs.set_lr(ret_addr);
 
-                        let sp = self.regs.sp().wrapping_sub(4);
-                        self.mem.write32(sp, ret_addr);
-                        self.regs.set_sp(sp);
-
                         pc.wrapping_add((offset * 4) as u32)
                     }
 
                     Opcode::RET => {
                         update_pc = true;
+                        self.pop()?
+                    }
 
+                    Opcode::RETI => {
+                        // Lee el marco entero antes de tocar SP para que un
+                        // fallo de página deje la instrucción repetible
+                        update_pc = true;
                         let sp = self.regs.sp();
-                        let ret_addr = self.mem.read32(sp);
-                        self.regs.set_sp(sp.wrapping_add(4));
-
+                        let ret_addr = self.read32(sp)?;
+                        let flags = self.read32(sp.wrapping_add(4))?;
+                        self.regs.set_sp(sp.wrapping_add(8));
+                        self.set_status(flags);
                         ret_addr
                     }
 
@@ -225,12 +811,43 @@ impl CPU {
                         self.halted = true;
                         pc
                     }
-                    _ => unimplemented!(),
+                    _ => return Err(FaultKind::IllegalOpcode),
                 };
 
                 self.regs.set_pc(target);
             }
 
+            Instruction::B {
+                opcode,
+                rs1,
+                rs2,
+                offset,
+            } => {
+                let a = self.regs.get(rs1);
+                let b = self.regs.get(rs2);
+
+                let taken = match opcode {
+                    Opcode::BEQ => a == b,
+                    Opcode::BNE => a != b,
+                    Opcode::BLT => (a as i32) < (b as i32),
+                    Opcode::BGE => (a as i32) >= (b as i32),
+                    Opcode::BLTU => a < b,
+                    Opcode::BGEU => a >= b,
+                    Opcode::DBNZ => {
+                        let count = a.wrapping_sub(1);
+                        self.regs.set(rs1, count);
+                        count != 0
+                    }
+                    _ => return Err(FaultKind::IllegalOpcode),
+                };
+
+                if taken {
+                    update_pc = true;
+                    let target = self.regs.pc().wrapping_add(offset.wrapping_mul(4));
+                    self.regs.set_pc(target);
+                }
+            }
+
             Instruction::Mem {
                 opcode,
                 rd,
@@ -241,45 +858,133 @@ impl CPU {
 
                 match opcode {
                     Opcode::LDB => {
-                        let value = self.mem.read8(addr) as i8 as i32 as u32;
+                        let value = self.read8(addr)? as i8 as i32 as u32;
                         self.regs.set(rd, value);
                     }
                     Opcode::LDBU => {
-                        let value = self.mem.read8(addr) as u32;
+                        let value = self.read8(addr)? as u32;
                         self.regs.set(rd, value);
                     }
                     Opcode::LDH => {
-                        let value = self.mem.read16(addr) as i16 as i32 as u32;
+                        let value = self.read16(addr)? as i16 as i32 as u32;
                         self.regs.set(rd, value);
                     }
                     Opcode::LDHU => {
-                        let value = self.mem.read16(addr) as u32;
+                        let value = self.read16(addr)? as u32;
                         self.regs.set(rd, value);
                     }
                     Opcode::LDW | Opcode::LDLR => {
-                        let value = self.mem.read32(addr);
+                        let value = self.read32(addr)?;
                         self.regs.set(rd, value);
                     }
 
                     // Store
-                    Opcode::STB => self.mem.write8(addr, self.regs.get(rd) as u8),
-                    Opcode::STH => self.mem.write16(addr, self.regs.get(rd) as u16),
-                    Opcode::STW | Opcode::STLR => self.mem.write32(addr, self.regs.get(rd)),
+                    Opcode::STB => self.write8(addr, self.regs.get(rd) as u8)?,
+                    Opcode::STH => self.write16(addr, self.regs.get(rd) as u16)?,
+                    Opcode::STW | Opcode::STLR => self.write32(addr, self.regs.get(rd))?,
 
-                    Opcode::PUSH => {
-                        let sp = self.regs.sp().wrapping_sub(4);
-                        self.mem.write32(sp, self.regs.get(rd));
-                        self.regs.set_sp(sp);
-                    }
+                    Opcode::PUSH => self.push(self.regs.get(rd))?,
 
                     Opcode::POP => {
-                        let sp = self.regs.sp();
-                        let value = self.mem.read32(sp);
+                        let value = self.pop()?;
                         self.regs.set(rd, value);
-                        self.regs.set_sp(sp.wrapping_add(4));
                     }
 
-                    _ => unimplemented!(),
+                    Opcode::FLD => {
+                        let value = self.read32(addr)?;
+                        self.regs.fset(rd, f32::from_bits(value));
+                    }
+                    Opcode::FST => self.write32(addr, self.regs.fget(rd).to_bits())?,
+                    Opcode::FLDD => {
+                        Self::check_dreg(rd)?;
+                        if !addr.is_multiple_of(8) {
+                            return Err(FaultKind::Misaligned { addr });
+                        }
+                        let lo = self.read32(addr)? as u64;
+                        let hi = self.read32(addr.wrapping_add(4))? as u64;
+                        self.regs.dset(rd, f64::from_bits(hi << 32 | lo));
+                    }
+                    Opcode::FSTD => {
+                        let bits = self.dreg(rd)?.to_bits();
+                        if !addr.is_multiple_of(8) {
+                            return Err(FaultKind::Misaligned { addr });
+                        }
+                        self.write32(addr, bits as u32)?;
+                        self.write32(addr.wrapping_add(4), (bits >> 32) as u32)?;
+                    }
+
+                    // Saltos indirectos: el destino es la dirección efectiva
+                    Opcode::JR => {
+                        update_pc = true;
+                        self.regs.set_pc(addr);
+                    }
+                    Opcode::CALLR => {
+                        let ret_addr = self.regs.pc().wrapping_add(4);
+                        self.push(ret_addr)?;
+                        self.regs.set_lr(ret_addr);
+                        update_pc = true;
+                        self.regs.set_pc(addr);
+                    }
+
+                    _ => return Err(FaultKind::IllegalOpcode),
+                }
+            }
+
+            Instruction::MemX {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+                mode,
+            } => {
+                let mode = AddrMode::from_bits(mode).ok_or(FaultKind::IllegalOpcode)?;
+                let size = match opcode {
+                    Opcode::LDBX | Opcode::LDBUX | Opcode::STBX => 1,
+                    Opcode::LDHX | Opcode::LDHUX | Opcode::STHX => 2,
+                    Opcode::LDWX | Opcode::STWX => 4,
+                    _ => return Err(FaultKind::IllegalOpcode),
+                };
+
+                let base = self.regs.get(rs1);
+                let (addr, writeback) = match mode {
+                    AddrMode::Indexed { scale } => {
+                        (base.wrapping_add(self.regs.get(rs2) << scale), None)
+                    }
+                    AddrMode::PostIncrement => (base, Some(base.wrapping_add(size))),
+                    AddrMode::PreDecrement => {
+                        let addr = base.wrapping_sub(size);
+                        (addr, Some(addr))
+                    }
+                };
+
+                // La base se actualiza solo si el acceso no falla, para que
+                // la instrucción se pueda repetir. Si rd es la base de una
+                // carga, gana el valor cargado
+                let loaded = match opcode {
+                    Opcode::LDBX => Some(self.read8(addr)? as i8 as i32 as u32),
+                    Opcode::LDBUX => Some(self.read8(addr)? as u32),
+                    Opcode::LDHX => Some(self.read16(addr)? as i16 as i32 as u32),
+                    Opcode::LDHUX => Some(self.read16(addr)? as u32),
+                    Opcode::LDWX => Some(self.read32(addr)?),
+                    Opcode::STBX => {
+                        self.write8(addr, self.regs.get(rd) as u8)?;
+                        None
+                    }
+                    Opcode::STHX => {
+                        self.write16(addr, self.regs.get(rd) as u16)?;
+                        None
+                    }
+                    _ => {
+                        self.write32(addr, self.regs.get(rd))?;
+                        None
+                    }
+                };
+
+                if let Some(base) = writeback {
+                    self.regs.set(rs1, base);
+                }
+                if let Some(value) = loaded {
+                    self.regs.set(rd, value);
                 }
             }
 
@@ -294,6 +999,22 @@ impl CPU {
                     let value = self.regs.get(rs);
                     self.regs.set(rd, value);
                 }
+                Opcode::CMOVZ
+                | Opcode::CMOVNZ
+                | Opcode::CMOVEQ
+                | Opcode::CMOVNE
+                | Opcode::CMOVLT
+                | Opcode::CMOVGT
+                | Opcode::CMOVLE
+                | Opcode::CMOVGE
+                | Opcode::CMOVC
+                | Opcode::CMOVO => {
+                    // Mismo orden que `Condition::ALL`
+                    let cond = Condition::ALL[(opcode as u8 - Opcode::CMOVZ as u8) as usize];
+                    if Flags::from_u32(self.regs.flags()).holds(cond) {
+                        self.regs.set(rd, self.regs.get(rs));
+                    }
+                }
                 Opcode::LUI => {
                     self.regs.set(rd, (imm & 0xFFFF) << 16);
                 }
@@ -304,8 +1025,61 @@ impl CPU {
                 Opcode::MOVPC => {
                     self.regs.set(rd, self.regs.pc());
                 }
+                Opcode::LDPC => {
+                    let addr = self.regs.pc().wrapping_add(imm.wrapping_mul(4));
+                    let value = self.read32(addr)?;
+                    self.regs.set(rd, value);
+                }
+                // La lista queda en orden ascendente desde el nuevo SP, con
+                // LR arriba del todo. SP solo cambia si todos los accesos
+                // salen bien
+                Opcode::PUSHM => {
+                    let sp = self.regs.sp().wrapping_sub(reglist_len(imm) * 4);
+                    let mut addr = sp;
+                    for reg in reglist_registers(imm) {
+                        self.write32(addr, self.regs.get(reg))?;
+                        addr = addr.wrapping_add(4);
+                    }
+                    if imm & REGLIST_LR != 0 {
+                        self.write32(addr, self.regs.lr())?;
+                    }
+                    self.regs.set_sp(sp);
+                }
+                Opcode::POPM => {
+                    let sp = self.regs.sp();
+                    let len = reglist_len(imm) as usize;
+                    let mut values = [0; 17];
+                    for (i, value) in values[..len].iter_mut().enumerate() {
+                        *value = self.read32(sp.wrapping_add(i as u32 * 4))?;
+                    }
+                    let mut values = values[..len].iter();
+                    for (reg, &value) in reglist_registers(imm).zip(&mut values) {
+                        self.regs.set(reg, value);
+                    }
+                    if let Some(&lr) = values.next() {
+                        self.regs.set_lr(lr);
+                    }
+                    self.regs.set_sp(sp.wrapping_add(len as u32 * 4));
+                }
+                // ENTER n: guarda FP, FP = SP y reserva n bytes de locales
+                Opcode::ENTER => {
+                    self.push(self.regs.get(FRAME_POINTER))?;
+                    let sp = self.regs.sp();
+                    self.regs.set(FRAME_POINTER, sp);
+                    self.regs.set_sp(sp.wrapping_sub(imm));
+                }
+                Opcode::LEAVE => {
+                    let fp = self.regs.get(FRAME_POINTER);
+                    let saved = self.read32(fp)?;
+                    self.regs.set_sp(fp.wrapping_add(4));
+                    self.regs.set(FRAME_POINTER, saved);
+                }
+                Opcode::ADDPC => {
+                    let addr = self.regs.pc().wrapping_add(imm.wrapping_mul(4));
+                    self.regs.set(rd, addr);
+                }
                 Opcode::MTSR => {
-                    self.regs.set_flags(self.regs.get(rd));
+                    self.set_status(self.regs.get(rd));
                 }
                 Opcode::MFSR => {
                     self.regs.set(rd, self.regs.flags());
@@ -316,7 +1090,36 @@ impl CPU {
                 Opcode::SETSP => {
                     self.regs.set_sp(self.regs.get(rd));
                 }
-                _ => unimplemented!(),
+                Opcode::EI => {
+                    let mut flags = Flags::from_u32(self.regs.flags());
+                    flags.interrupt_enable = true;
+                    self.regs.set_flags(flags.to_u32());
+                }
+                Opcode::DI => {
+                    let mut flags = Flags::from_u32(self.regs.flags());
+                    flags.interrupt_enable = false;
+                    self.regs.set_flags(flags.to_u32());
+                }
+                Opcode::SYSCALL => {
+                    // Vuelve a la instrucción siguiente
+                    let pc = self.regs.pc();
+                    let vector_addr = {
+                        let mut pic = self.pic.borrow_mut();
+                        pic.record_fault(SYSCALL_CAUSE, imm);
+                        pic.vector_addr(SYSCALL_VECTOR)
+                    };
+                    self.regs.set_pc(pc.wrapping_add(4));
+                    self.enter_vector(vector_addr)
+                        .inspect_err(|_| self.regs.set_pc(pc))?;
+                    return Ok(true);
+                }
+                Opcode::MFUSP => {
+                    self.regs.set(rd, self.regs.banked_sp());
+                }
+                Opcode::MTUSP => {
+                    self.regs.set_banked_sp(self.regs.get(rd));
+                }
+                _ => return Err(FaultKind::IllegalOpcode),
             },
 
             Instruction::FP {
@@ -324,79 +1127,80 @@ impl CPU {
                 rd,
                 rs1,
                 rs2,
-            } => match opcode {
-                Opcode::FADD => {
-                    let a = self.regs.fget(rs1);
-                    let b = self.regs.fget(rs2);
-                    self.regs.fset(rd, a + b);
-                }
-                Opcode::FSUB => {
-                    let a = self.regs.fget(rs1);
-                    let b = self.regs.fget(rs2);
-                    self.regs.fset(rd, a - b);
-                }
-                Opcode::FMUL => {
-                    let a = self.regs.fget(rs1);
-                    let b = self.regs.fget(rs2);
-                    self.regs.fset(rd, a * b);
-                }
-                Opcode::FDIV => {
+            } => {
+                let mut fpu = Fpu::new(Rounding::from_fcsr(self.regs.fcsr()));
+                if Self::is_double(opcode) {
+                    self.execute_double(&mut fpu, opcode, rd, rs1, rs2)?;
+                } else {
                     let a = self.regs.fget(rs1);
                     let b = self.regs.fget(rs2);
-                    self.regs.fset(rd, a / b);
-                }
 
-                Opcode::FCMP => {
-                    let a = self.regs.fget(rs1);
-                    let b = self.regs.fget(rs2);
-                    let mut flags = Flags::from_u32(self.regs.flags());
-                    flags.zero = a == b;
-                    flags.less = a < b;
-                    flags.greater = a > b;
-                    self.regs.set_flags(flags.to_u32());
-                }
+                    match opcode {
+                        Opcode::FADD => self.regs.fset(rd, fpu.add(a, b)),
+                        Opcode::FSUB => self.regs.fset(rd, fpu.sub(a, b)),
+                        Opcode::FMUL => self.regs.fset(rd, fpu.mul(a, b)),
+                        Opcode::FDIV => self.regs.fset(rd, fpu.div(a, b)),
+                        Opcode::FSQRT => self.regs.fset(rd, fpu.sqrt(a)),
+                        // rd = rs1 * rs2 + rd
+                        Opcode::FMA => {
+                            let c = self.regs.fget(rd);
+                            self.regs.fset(rd, fpu.fma(a, b, c));
+                        }
+                        Opcode::FMIN => self.regs.fset(rd, fpu.min_max(a, b, false)),
+                        Opcode::FMAX => self.regs.fset(rd, fpu.min_max(a, b, true)),
+
+                        // Solo cambian el signo: no levantan excepciones
+                        Opcode::FABS => self.regs.fset(rd, a.abs()),
+                        Opcode::FNEG => self.regs.fset(rd, -a),
+                        Opcode::FMOV => self.regs.fset(rd, a),
 
-                Opcode::FEQ => self
-                    .regs
-                    .set(rd, (self.regs.fget(rs1) == self.regs.fget(rs2)) as u32),
-                Opcode::FLT => self
-                    .regs
-                    .set(rd, (self.regs.fget(rs1) < self.regs.fget(rs2)) as u32),
-                Opcode::FGT => self
-                    .regs
-                    .set(rd, (self.regs.fget(rs1) > self.regs.fget(rs2)) as u32),
+                        Opcode::FCMP => {
+                            let ord = fpu.compare(a, b, true);
+                            self.set_fp_compare_flags(ord);
+                        }
 
-                Opcode::FTOI => self.regs.set(rd, self.regs.fget(rs1) as u32),
-                Opcode::ITOF => self.regs.fset(rd, self.regs.get(rs1) as f32),
+                        Opcode::FEQ => {
+                            let ord = fpu.compare(a, b, false);
+                            self.regs.set(rd, (ord == Some(Ordering::Equal)) as u32);
+                        }
+                        Opcode::FLT => {
+                            let ord = fpu.compare(a, b, true);
+                            self.regs.set(rd, (ord == Some(Ordering::Less)) as u32);
+                        }
+                        Opcode::FGT => {
+                            let ord = fpu.compare(a, b, true);
+                            self.regs.set(rd, (ord == Some(Ordering::Greater)) as u32);
+                        }
 
-                Opcode::FMOV => self.regs.fset(rd, self.regs.fget(rs1)),
-                Opcode::FLD => {
-                    let addr = self.regs.get(rs1);
-                    let bits = self.mem.read32(addr);
-                    self.regs.fset(rd, f32::from_bits(bits));
-                }
+                        Opcode::FTOI => self.regs.set(rd, fpu.to_i32(a) as u32),
+                        Opcode::FTOIU => self.regs.set(rd, fpu.to_u32(a)),
+                        Opcode::ITOF => self.regs.fset(rd, fpu.from_i32(self.regs.get(rs1) as i32)),
+                        Opcode::UTOF => self.regs.fset(rd, fpu.from_u32(self.regs.get(rs1))),
 
-                Opcode::FST => {
-                    let addr = self.regs.get(rs1);
-                    let bits = self.regs.fget(rd).to_bits();
-                    self.mem.write32(addr, bits);
+                        Opcode::MFFCSR => self.regs.set(rd, self.regs.fcsr()),
+                        Opcode::MTFCSR => self.regs.set_fcsr(self.regs.get(rd) & FCSR_MASK),
+
+                        _ => return Err(FaultKind::IllegalOpcode),
+                    }
                 }
 
-                _ => unimplemented!(),
-            },
+                if fpu.flags != 0 {
+                    self.regs.set_fcsr(self.regs.fcsr() | fpu.flags);
+                }
+            }
 
             Instruction::IO { opcode, rd, port } => match opcode {
                 Opcode::IN => {
-                    let value = self.io.read(port);
+                    let value = self.port_read(port);
                     self.regs.set(rd, value);
                 }
                 Opcode::OUT => {
                     let value = self.regs.get(rd);
-                    self.io.write(port, value);
+                    self.port_write(port, value);
                 }
-                _ => unimplemented!(),
+                _ => return Err(FaultKind::IllegalOpcode),
             },
         }
-        update_pc
+        Ok(update_pc)
     }
 }
@@ -0,0 +1,100 @@
+use std::fmt;
+
+use crate::interrupt::IRQ_LINES;
+
+/// Primer vector de excepción: van justo detrás de las líneas IRQ en la tabla.
+pub const EXCEPTION_VECTOR_BASE: u8 = IRQ_LINES;
+
+/// Vector de `SYSCALL`, tras el hueco reservado a los vectores de excepción.
+pub const SYSCALL_VECTOR: u8 = EXCEPTION_VECTOR_BASE + 16;
+
+/// Causa que ve el manejador de `SYSCALL` en `PIC_FAULT_CAUSE`; el número de
+/// la llamada queda en `PIC_FAULT_ADDR`.
+pub const SYSCALL_CAUSE: u32 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    IllegalOpcode,
+    BusError { addr: u32 },
+    RomWrite { addr: u32 },
+    Misaligned { addr: u32 },
+    DivideByZero,
+    PrivilegeViolation,
+    PageFault { addr: u32 },
+}
+
+impl FaultKind {
+    /// Código de causa que ve el programa en `PIC_FAULT_CAUSE`.
+    pub fn code(&self) -> u32 {
+        match self {
+            FaultKind::IllegalOpcode => 1,
+            FaultKind::BusError { .. } => 2,
+            FaultKind::RomWrite { .. } => 3,
+            FaultKind::Misaligned { .. } => 4,
+            FaultKind::DivideByZero => 5,
+            FaultKind::PrivilegeViolation => 6,
+            FaultKind::PageFault { .. } => 7,
+        }
+    }
+
+    pub fn vector(&self) -> u8 {
+        EXCEPTION_VECTOR_BASE + self.code() as u8 - 1
+    }
+
+    pub fn addr(&self) -> Option<u32> {
+        match *self {
+            FaultKind::BusError { addr }
+            | FaultKind::RomWrite { addr }
+            | FaultKind::Misaligned { addr }
+            | FaultKind::PageFault { addr } => Some(addr),
+            FaultKind::IllegalOpcode | FaultKind::DivideByZero | FaultKind::PrivilegeViolation => {
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultKind::IllegalOpcode => write!(f, "illegal opcode"),
+            FaultKind::BusError { addr } => write!(f, "bus error at {:#010X}", addr),
+            FaultKind::RomWrite { addr } => write!(f, "cannot write to ROM at {:#010X}", addr),
+            FaultKind::Misaligned { addr } => write!(f, "misaligned access at {:#010X}", addr),
+            FaultKind::DivideByZero => write!(f, "divide by zero"),
+            FaultKind::PrivilegeViolation => write!(f, "privileged instruction in user mode"),
+            FaultKind::PageFault { addr } => write!(f, "page fault at {:#010X}", addr),
+        }
+    }
+}
+
+/// Fallo de ejecución: dónde ocurrió, qué palabra se estaba ejecutando y por qué.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuFault {
+    pub pc: u32,
+    pub raw: u32,
+    pub kind: FaultKind,
+}
+
+impl fmt::Display for CpuFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (PC={:#010X}, instr={:#010X})",
+            self.kind, self.pc, self.raw
+        )
+    }
+}
+
+impl std::error::Error for CpuFault {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Executed,
+    Halted,
+    Interrupt(u8),
+    /// `SYSCALL` con su número; el programa ya está en el vector de la llamada.
+    Syscall(u32),
+    /// El fallo se entregó al programa a través de su vector de excepción.
+    Exception(CpuFault),
+}
@@ -0,0 +1,456 @@
+//! Unidad de coma flotante IEEE-754.
+//!
+//! El anfitrión solo redondea al par más cercano. Para los demás modos cada
+//! operación calcula ese resultado y el signo de su error respecto al valor
+//! exacto (con transformaciones sin error: TwoSum, FMA), y lo corrige un ulp
+//! cuando el modo lo pide. Las excepciones se acumulan en `FCSR` y nunca
+//! generan un fallo; el programa las consulta con `MFFCSR`.
+//!
+//! ```text
+//!  9   8 | 4   3   2   1   0
+//! |  RM  | NX  UF  OF  DZ  NV |
+//! ```
+
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+// Excepciones acumuladas de FCSR
+pub const FCSR_INVALID: u32 = 0x01;
+pub const FCSR_DIV_ZERO: u32 = 0x02;
+pub const FCSR_OVERFLOW: u32 = 0x04;
+pub const FCSR_UNDERFLOW: u32 = 0x08;
+pub const FCSR_INEXACT: u32 = 0x10;
+pub const FCSR_FLAGS: u32 = 0x1F;
+
+/// Campo del modo de redondeo de FCSR.
+pub const FCSR_RM_SHIFT: u32 = 8;
+pub const FCSR_RM_MASK: u32 = 0x3 << FCSR_RM_SHIFT;
+
+/// Bits de FCSR que se pueden escribir; el resto se lee a 0.
+pub const FCSR_MASK: u32 = FCSR_FLAGS | FCSR_RM_MASK;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    NearestEven = 0,
+    TowardZero = 1,
+    Down = 2,
+    Up = 3,
+}
+
+impl Rounding {
+    pub fn from_fcsr(fcsr: u32) -> Self {
+        match (fcsr & FCSR_RM_MASK) >> FCSR_RM_SHIFT {
+            0 => Rounding::NearestEven,
+            1 => Rounding::TowardZero,
+            2 => Rounding::Down,
+            _ => Rounding::Up,
+        }
+    }
+
+    pub fn to_fcsr(self) -> u32 {
+        (self as u32) << FCSR_RM_SHIFT
+    }
+}
+
+/// Formato binario IEEE-754 sobre el que opera la `Fpu`.
+pub trait Float:
+    Copy
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const MIN_POSITIVE: Self;
+    /// NaN silencioso que producen las operaciones inválidas.
+    const CANONICAL_NAN: Self;
+
+    fn is_nan(self) -> bool;
+    fn is_infinite(self) -> bool;
+    fn is_sign_negative(self) -> bool;
+    fn abs(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn mul_add(self, a: Self, b: Self) -> Self;
+    fn to_f64(self) -> f64;
+    fn from_i64(value: i64) -> Self;
+
+    /// NaN con el bit de silencioso a 0.
+    fn is_signaling(self) -> bool;
+
+    /// Siguiente valor representable hacia +inf (`up`) o hacia -inf.
+    fn next(self, up: bool) -> Self;
+
+    // Signo de `exacto - r` para cada operación, con `r` el resultado
+    // redondeado al par. Por defecto se usan los restos exactos que da el
+    // FMA, que dejan de serlo cuando el resto cae por debajo de los
+    // subnormales; f32 los calcula en f64, donde no hay ese problema.
+
+    fn mul_error(a: Self, b: Self, r: Self) -> Ordering {
+        sign(a.mul_add(b, -r))
+    }
+
+    fn div_error(a: Self, b: Self, r: Self) -> Ordering {
+        // El resto `a - r * b` tiene el signo del error si `b > 0`
+        let rem = sign((-r).mul_add(b, a));
+        if b.is_sign_negative() {
+            rem.reverse()
+        } else {
+            rem
+        }
+    }
+
+    fn sqrt_error(a: Self, r: Self) -> Ordering {
+        sign((-r).mul_add(r, a))
+    }
+
+    fn fma_error(a: Self, b: Self, c: Self, r: Self) -> Ordering {
+        // a * b = p + pe y p + c = s + se, ambos exactos
+        let p = a * b;
+        let pe = a.mul_add(b, -p);
+        let (s, se) = two_sum(p, c);
+        sign((s - r) + (se + pe))
+    }
+}
+
+macro_rules! impl_float {
+    ($float:ty, $bits:ty, $quiet:literal $(, $error:item)*) => {
+        impl Float for $float {
+            const ZERO: Self = 0.0;
+            const MIN_POSITIVE: Self = <$float>::MIN_POSITIVE;
+            const CANONICAL_NAN: Self = <$float>::from_bits(<$float>::INFINITY.to_bits() | $quiet);
+
+            #[inline]
+            fn is_nan(self) -> bool {
+                self.is_nan()
+            }
+
+            #[inline]
+            fn is_infinite(self) -> bool {
+                self.is_infinite()
+            }
+
+            #[inline]
+            fn is_sign_negative(self) -> bool {
+                self.is_sign_negative()
+            }
+
+            #[inline]
+            fn abs(self) -> Self {
+                self.abs()
+            }
+
+            #[inline]
+            fn sqrt(self) -> Self {
+                self.sqrt()
+            }
+
+            #[inline]
+            fn mul_add(self, a: Self, b: Self) -> Self {
+                self.mul_add(a, b)
+            }
+
+            #[inline]
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            #[inline]
+            fn from_i64(value: i64) -> Self {
+                value as $float
+            }
+
+            #[inline]
+            fn is_signaling(self) -> bool {
+                self.is_nan() && self.to_bits() & $quiet == 0
+            }
+
+            fn next(self, up: bool) -> Self {
+                let bits = self.to_bits();
+                let sign: $bits = 1 << (<$bits>::BITS - 1);
+                if self == 0.0 {
+                    return <$float>::from_bits(if up { 1 } else { sign | 1 });
+                }
+                if (self > 0.0) == up {
+                    <$float>::from_bits(bits + 1)
+                } else {
+                    <$float>::from_bits(bits - 1)
+                }
+            }
+
+            $($error)*
+        }
+    };
+}
+
+impl_float!(
+    f32,
+    u32,
+    0x0040_0000,
+    // En f64 el producto de dos f32 es exacto, y el cociente y la raíz
+    // conservan el signo del error
+    fn mul_error(a: f32, b: f32, r: f32) -> Ordering {
+        sign(a as f64 * b as f64 - r as f64)
+    },
+    fn div_error(a: f32, b: f32, r: f32) -> Ordering {
+        sign(a as f64 / b as f64 - r as f64)
+    },
+    fn sqrt_error(a: f32, r: f32) -> Ordering {
+        sign((a as f64).sqrt() - r as f64)
+    },
+    fn fma_error(a: f32, b: f32, c: f32, r: f32) -> Ordering {
+        let (sum, err) = two_sum(a as f64 * b as f64, c as f64);
+        match sign(sum - r as f64) {
+            Ordering::Equal => sign(err),
+            ord => ord,
+        }
+    }
+);
+
+impl_float!(f64, u64, 0x0008_0000_0000_0000);
+
+/// Suma y su error de redondeo exacto (TwoSum de Knuth).
+#[inline]
+fn two_sum<F: Float>(a: F, b: F) -> (F, F) {
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+#[inline]
+fn sign<F: Float>(value: F) -> Ordering {
+    value.partial_cmp(&F::ZERO).unwrap_or(Ordering::Equal)
+}
+
+/// Modo de redondeo y excepciones levantadas. La CPU crea una por
+/// instrucción a partir de FCSR y después acumula `flags` en él.
+#[derive(Debug, Clone, Copy)]
+pub struct Fpu {
+    pub rounding: Rounding,
+    pub flags: u32,
+}
+
+impl Fpu {
+    pub fn new(rounding: Rounding) -> Self {
+        Self { rounding, flags: 0 }
+    }
+
+    pub fn add<F: Float>(&mut self, a: F, b: F) -> F {
+        let r = a + b;
+        // Una suma que redondea a cero es exacta
+        let result = self.arith(&[a, b], r, || sign(two_sum(a, b).1));
+        self.exact_zero(result, a, b)
+    }
+
+    pub fn sub<F: Float>(&mut self, a: F, b: F) -> F {
+        self.add(a, -b)
+    }
+
+    pub fn mul<F: Float>(&mut self, a: F, b: F) -> F {
+        let r = a * b;
+        self.arith(&[a, b], r, || F::mul_error(a, b, r))
+    }
+
+    pub fn div<F: Float>(&mut self, a: F, b: F) -> F {
+        let r = a / b;
+        if b == F::ZERO && !a.is_nan() && !a.is_infinite() && a != F::ZERO {
+            self.flags |= FCSR_DIV_ZERO;
+            return r;
+        }
+        self.arith(&[a, b], r, || F::div_error(a, b, r))
+    }
+
+    pub fn sqrt<F: Float>(&mut self, a: F) -> F {
+        let r = a.sqrt();
+        self.arith(&[a], r, || F::sqrt_error(a, r))
+    }
+
+    /// `a * b + c` con un único redondeo.
+    pub fn fma<F: Float>(&mut self, a: F, b: F, c: F) -> F {
+        let r = a.mul_add(b, c);
+        let mut op = Fpu::new(self.rounding);
+        let result = op.arith(&[a, b, c], r, || F::fma_error(a, b, c, r));
+        self.flags |= op.flags;
+        if op.flags & FCSR_INEXACT == 0 {
+            self.exact_zero(result, a * b, c)
+        } else {
+            result
+        }
+    }
+
+    /// minNum/maxNum: un NaN silencioso pierde frente a un número y -0 < +0.
+    pub fn min_max<F: Float>(&mut self, a: F, b: F, max: bool) -> F {
+        if a.is_signaling() || b.is_signaling() {
+            self.flags |= FCSR_INVALID;
+        }
+        match (a.is_nan(), b.is_nan()) {
+            (true, true) => F::CANONICAL_NAN,
+            (true, false) => b,
+            (false, true) => a,
+            _ if a == b => {
+                if a.is_sign_negative() != max {
+                    a
+                } else {
+                    b
+                }
+            }
+            _ => {
+                if (a < b) != max {
+                    a
+                } else {
+                    b
+                }
+            }
+        }
+    }
+
+    /// Compara dos valores; `None` si no están ordenados (algún NaN). Las
+    /// comparaciones `signaling` levantan invalid con cualquier NaN; las
+    /// silenciosas solo con un NaN señalizador.
+    pub fn compare<F: Float>(&mut self, a: F, b: F, signaling: bool) -> Option<Ordering> {
+        if a.is_nan() || b.is_nan() {
+            if signaling || a.is_signaling() || b.is_signaling() {
+                self.flags |= FCSR_INVALID;
+            }
+            return None;
+        }
+        a.partial_cmp(&b)
+    }
+
+    /// Conversión a entero con truncamiento, como un cast de C. Fuera de
+    /// rango satura y levanta invalid; NaN da 0.
+    pub fn to_i32<F: Float>(&mut self, value: F) -> i32 {
+        self.truncate_int(value, i32::MIN as f64, i32::MAX as f64) as i32
+    }
+
+    pub fn to_u32<F: Float>(&mut self, value: F) -> u32 {
+        self.truncate_int(value, 0.0, u32::MAX as f64) as u32
+    }
+
+    pub fn from_i32<F: Float>(&mut self, value: i32) -> F {
+        self.round_int(value as i64)
+    }
+
+    pub fn from_u32<F: Float>(&mut self, value: u32) -> F {
+        self.round_int(value as i64)
+    }
+
+    /// Reduce un f64 a f32 con el modo de redondeo actual.
+    pub fn narrow(&mut self, value: f64) -> f32 {
+        if value.is_nan() {
+            if value.is_signaling() {
+                self.flags |= FCSR_INVALID;
+            }
+            return f32::CANONICAL_NAN;
+        }
+        let r = value as f32;
+        if value.is_infinite() {
+            return r;
+        }
+        let err = if r.is_infinite() {
+            if r > 0.0 {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        } else {
+            sign(value - r as f64)
+        };
+        self.round(r, err)
+    }
+
+    fn truncate_int<F: Float>(&mut self, value: F, min: f64, max: f64) -> i64 {
+        if value.is_nan() {
+            self.flags |= FCSR_INVALID;
+            return 0;
+        }
+        let value = value.to_f64();
+        let truncated = value.trunc();
+        if truncated < min || truncated > max {
+            self.flags |= FCSR_INVALID;
+            return truncated.clamp(min, max) as i64;
+        }
+        if truncated != value {
+            self.flags |= FCSR_INEXACT;
+        }
+        truncated as i64
+    }
+
+    fn round_int<F: Float>(&mut self, value: i64) -> F {
+        let r = F::from_i64(value);
+        // Los enteros de 32 bits son exactos en f64
+        let err = sign(value as f64 - r.to_f64());
+        self.round(r, err)
+    }
+
+    // Resultado de una operación aritmética: NaN y operandos infinitos dan
+    // un resultado exacto; si no, se redondea según el signo del error.
+    fn arith<F: Float>(&mut self, inputs: &[F], r: F, err: impl FnOnce() -> Ordering) -> F {
+        if inputs.iter().any(|x| x.is_nan()) {
+            if inputs.iter().any(|x| x.is_signaling()) {
+                self.flags |= FCSR_INVALID;
+            }
+            return F::CANONICAL_NAN;
+        }
+        if r.is_nan() {
+            self.flags |= FCSR_INVALID;
+            return F::CANONICAL_NAN;
+        }
+        if inputs.iter().any(|x| x.is_infinite()) {
+            return r;
+        }
+        // Con operandos finitos, un infinito es un desbordamiento
+        let err = if r.is_infinite() {
+            if r > F::ZERO {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        } else {
+            err()
+        };
+        self.round(r, err)
+    }
+
+    // `r` es el resultado redondeado al par y `err` el signo de
+    // `exacto - r`
+    fn round<F: Float>(&mut self, r: F, err: Ordering) -> F {
+        if err == Ordering::Equal {
+            return r;
+        }
+        self.flags |= FCSR_INEXACT;
+
+        let step = match self.rounding {
+            Rounding::NearestEven => false,
+            Rounding::TowardZero => (err == Ordering::Less) != r.is_sign_negative(),
+            Rounding::Down => err == Ordering::Less,
+            Rounding::Up => err == Ordering::Greater,
+        };
+        let result = if step {
+            r.next(err == Ordering::Greater)
+        } else {
+            r
+        };
+
+        if r.is_infinite() || result.is_infinite() {
+            self.flags |= FCSR_OVERFLOW;
+        }
+        if r.abs() < F::MIN_POSITIVE || result.abs() < F::MIN_POSITIVE {
+            self.flags |= FCSR_UNDERFLOW;
+        }
+        result
+    }
+
+    // Una suma exacta a cero de operandos de distinto signo es +0, salvo
+    // redondeando hacia -inf, donde es -0
+    fn exact_zero<F: Float>(&self, result: F, a: F, b: F) -> F {
+        let same_sign_zeros =
+            a == F::ZERO && b == F::ZERO && a.is_sign_negative() == b.is_sign_negative();
+        if result == F::ZERO && self.rounding == Rounding::Down && !same_sign_zeros {
+            -F::ZERO
+        } else {
+            result
+        }
+    }
+}
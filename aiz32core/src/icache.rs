@@ -0,0 +1,89 @@
+//! Caché de instrucciones predecodificadas.
+//!
+//! Correspondencia directa indexada por PC. Cada entrada guarda la versión de
+//! la página de código de la que salió (`MemoryBus::code_version`); escribir
+//! en esa página cambia la versión y la entrada deja de valer, así que el
+//! código automodificable sigue funcionando. La ROM nunca cambia de versión y
+//! sus entradas valen para siempre mientras no se expulsen.
+
+use crate::instruction::Instruction;
+
+pub const ICACHE_ENTRIES: usize = 4096;
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    pc: u32,
+    version: u32,
+    raw: u32,
+    instr: Instruction,
+}
+
+pub struct DecodeCache {
+    entries: Box<[Option<Entry>]>,
+    pub enabled: bool,
+    hits: u64,
+    misses: u64,
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecodeCache {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![None; ICACHE_ENTRIES].into_boxed_slice(),
+            enabled: true,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    #[inline]
+    fn index(pc: u32) -> usize {
+        (pc as usize >> 2) & (ICACHE_ENTRIES - 1)
+    }
+
+    #[inline]
+    pub fn lookup(&mut self, pc: u32, version: u32) -> Option<(u32, Instruction)> {
+        if !self.enabled {
+            return None;
+        }
+        match self.entries[Self::index(pc)] {
+            Some(e) if e.pc == pc && e.version == version => {
+                self.hits += 1;
+                Some((e.raw, e.instr))
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    #[inline]
+    pub fn insert(&mut self, pc: u32, version: u32, raw: u32, instr: Instruction) {
+        if self.enabled {
+            self.entries[Self::index(pc)] = Some(Entry {
+                pc,
+                version,
+                raw,
+                instr,
+            });
+        }
+    }
+
+    pub fn flush(&mut self) {
+        self.entries.fill(None);
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
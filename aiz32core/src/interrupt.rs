@@ -0,0 +1,169 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::peripheral::Peripheral;
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+
+pub const IRQ_LINES: u8 = 32;
+
+// Puertos del controlador de interrupciones
+pub const PIC_PENDING: u16 = 0x1000;
+pub const PIC_MASK: u16 = 0x1001;
+pub const PIC_VECTOR_BASE: u16 = 0x1002;
+pub const PIC_RAISE: u16 = 0x1003;
+pub const PIC_FAULT_CAUSE: u16 = 0x1004;
+pub const PIC_FAULT_ADDR: u16 = 0x1005;
+
+/// Controlador de interrupciones programable.
+///
+/// Cada línea IRQ queda enganchada en `pending` hasta que la CPU la atiende o
+/// el programa la limpia escribiendo un 1 en su bit de `PIC_PENDING`. La tabla
+/// de vectores vive en memoria: la entrada `n` está en `vector_base + n * 4`.
+/// Tras las líneas IRQ vienen los vectores de excepción (ver `fault`).
+pub struct InterruptController {
+    pending: u32,
+    mask: u32,
+    vector_base: u32,
+    fault_cause: u32,
+    fault_addr: u32,
+}
+
+impl Default for InterruptController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InterruptController {
+    pub fn new() -> Self {
+        Self {
+            pending: 0,
+            mask: 0,
+            vector_base: 0,
+            fault_cause: 0,
+            fault_addr: 0,
+        }
+    }
+
+    pub fn raise(&mut self, irq: u8) {
+        debug_assert!(irq < IRQ_LINES, "IRQ fuera de rango");
+        self.pending |= 1 << irq;
+    }
+
+    pub fn clear(&mut self, irq: u8) {
+        debug_assert!(irq < IRQ_LINES, "IRQ fuera de rango");
+        self.pending &= !(1 << irq);
+    }
+
+    pub fn pending(&self) -> u32 {
+        self.pending
+    }
+
+    pub fn mask(&self) -> u32 {
+        self.mask
+    }
+
+    pub fn set_mask(&mut self, mask: u32) {
+        self.mask = mask;
+    }
+
+    pub fn vector_base(&self) -> u32 {
+        self.vector_base
+    }
+
+    pub fn set_vector_base(&mut self, base: u32) {
+        self.vector_base = base;
+    }
+
+    pub fn vector_addr(&self, vector: u8) -> u32 {
+        self.vector_base.wrapping_add(vector as u32 * 4)
+    }
+
+    pub fn record_fault(&mut self, cause: u32, addr: u32) {
+        self.fault_cause = cause;
+        self.fault_addr = addr;
+    }
+
+    pub fn fault_cause(&self) -> u32 {
+        self.fault_cause
+    }
+
+    pub fn fault_addr(&self) -> u32 {
+        self.fault_addr
+    }
+
+    /// Línea habilitada de mayor prioridad (número más bajo) con petición pendiente.
+    pub fn next_pending(&self) -> Option<u8> {
+        let active = self.pending & self.mask;
+        if active == 0 {
+            None
+        } else {
+            Some(active.trailing_zeros() as u8)
+        }
+    }
+}
+
+impl Peripheral for InterruptController {
+    fn handles_port(&self, port: u16) -> bool {
+        (PIC_PENDING..=PIC_FAULT_ADDR).contains(&port)
+    }
+
+    fn read(&self, port: u16) -> u32 {
+        match port {
+            PIC_PENDING => self.pending,
+            PIC_MASK => self.mask,
+            PIC_VECTOR_BASE => self.vector_base,
+            PIC_FAULT_CAUSE => self.fault_cause,
+            PIC_FAULT_ADDR => self.fault_addr,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, port: u16, value: u32) {
+        match port {
+            PIC_PENDING => self.pending &= !value,
+            PIC_MASK => self.mask = value,
+            PIC_VECTOR_BASE => self.vector_base = value,
+            PIC_RAISE if value < IRQ_LINES as u32 => self.raise(value as u8),
+            _ => {}
+        }
+    }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_u32(self.pending);
+        out.write_u32(self.mask);
+        out.write_u32(self.vector_base);
+        out.write_u32(self.fault_cause);
+        out.write_u32(self.fault_addr);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.pending = input.read_u32()?;
+        self.mask = input.read_u32()?;
+        self.vector_base = input.read_u32()?;
+        self.fault_cause = input.read_u32()?;
+        self.fault_addr = input.read_u32()?;
+        Ok(())
+    }
+}
+
+/// Extremo de una línea IRQ que se entrega a un periférico para que pueda
+/// solicitar interrupciones sin conocer a la CPU.
+#[derive(Clone)]
+pub struct IrqLine {
+    pic: Rc<RefCell<InterruptController>>,
+    irq: u8,
+}
+
+impl IrqLine {
+    pub fn new(pic: Rc<RefCell<InterruptController>>, irq: u8) -> Self {
+        Self { pic, irq }
+    }
+
+    pub fn irq(&self) -> u8 {
+        self.irq
+    }
+
+    pub fn raise(&self) {
+        self.pic.borrow_mut().raise(self.irq);
+    }
+}
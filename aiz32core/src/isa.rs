@@ -0,0 +1,538 @@
+//! Definición única del juego de instrucciones.
+//!
+//! Cada opcode declara su formato de codificación, cómo se extiende su
+//! inmediato y qué operandos acepta en ensamblador. `Instruction::decode`,
+//! `Instruction::encode` y el ensamblador se generan a partir de esta tabla.
+
+/// Campo de bits dentro de la palabra de instrucción.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub shift: u32,
+    pub bits: u32,
+}
+
+impl Field {
+    const fn new(shift: u32, bits: u32) -> Self {
+        Self { shift, bits }
+    }
+
+    #[inline]
+    pub fn mask(self) -> u32 {
+        ((1u64 << self.bits) - 1) as u32
+    }
+
+    #[inline]
+    pub fn extract(self, raw: u32) -> u32 {
+        (raw >> self.shift) & self.mask()
+    }
+
+    #[inline]
+    pub fn insert(self, value: u32) -> u32 {
+        (value & self.mask()) << self.shift
+    }
+}
+
+pub const OPCODE: Field = Field::new(24, 8);
+const RD: Field = Field::new(19, 5);
+const RS1: Field = Field::new(14, 5);
+const RS2: Field = Field::new(9, 5);
+const IMM14: Field = Field::new(0, 14);
+const IMM19: Field = Field::new(0, 19);
+const OFFSET24: Field = Field::new(0, 24);
+const PORT16: Field = Field::new(3, 16);
+const COND4: Field = Field::new(0, 4);
+const MODE4: Field = Field::new(0, 4);
+const FUNCT5: Field = Field::new(0, 5);
+
+/// Disposición de campos de un formato. `rs1` es el `rs` de las Sys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub rd: Option<Field>,
+    pub rs1: Option<Field>,
+    pub rs2: Option<Field>,
+    pub imm: Option<Field>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// opcode(8) | rd(5) | rs1(5) | rs2(5) | unused(9)
+    R,
+    /// opcode(8) | rd(5) | rs1(5) | rs2(5) | unused(5) | cond(4)
+    RC,
+    /// opcode(8) | rd(5) | rs1(5) | rs2(5) | unused(4) | funct(5)
+    RP,
+    /// opcode(8) | rd(5) | rs1(5) | imm(14)
+    I,
+    /// opcode(8) | rd(5) | rs1(5) | offset(14)
+    Mem,
+    /// opcode(8) | rd(5) | rs1(5) | rs2(5) | unused(5) | mode(4)
+    MemX,
+    /// opcode(8) | offset(24)
+    J,
+    /// opcode(8) | rs1(5) | rs2(5) | offset(14)
+    B,
+    /// opcode(8) | rd(5) | rs(5) | unused(14)
+    SysReg,
+    /// opcode(8) | rd(5) | imm(19)
+    SysImm,
+    /// opcode(8) | rd(5) | rs1(5) | rs2(5) | unused(9)
+    FP,
+    /// Como `FP`, pero los registros de coma flotante son pares de doble
+    /// precisión
+    FPD,
+    /// opcode(8) | rd(5) | port(16) | unused(3)
+    IO,
+}
+
+impl Format {
+    pub const fn layout(self) -> Layout {
+        let none = Layout {
+            rd: None,
+            rs1: None,
+            rs2: None,
+            imm: None,
+        };
+        match self {
+            Format::R | Format::FP | Format::FPD => Layout {
+                rd: Some(RD),
+                rs1: Some(RS1),
+                rs2: Some(RS2),
+                ..none
+            },
+            Format::RC => Layout {
+                rd: Some(RD),
+                rs1: Some(RS1),
+                rs2: Some(RS2),
+                imm: Some(COND4),
+            },
+            Format::MemX => Layout {
+                rd: Some(RD),
+                rs1: Some(RS1),
+                rs2: Some(RS2),
+                imm: Some(MODE4),
+            },
+            Format::RP => Layout {
+                rd: Some(RD),
+                rs1: Some(RS1),
+                rs2: Some(RS2),
+                imm: Some(FUNCT5),
+            },
+            Format::I | Format::Mem => Layout {
+                rd: Some(RD),
+                rs1: Some(RS1),
+                imm: Some(IMM14),
+                ..none
+            },
+            Format::J => Layout {
+                imm: Some(OFFSET24),
+                ..none
+            },
+            // Sin rd: los registros suben para dejar 14 bits al desplazamiento
+            Format::B => Layout {
+                rs1: Some(RD),
+                rs2: Some(RS1),
+                imm: Some(IMM14),
+                ..none
+            },
+            Format::SysReg => Layout {
+                rd: Some(RD),
+                rs1: Some(RS1),
+                ..none
+            },
+            Format::SysImm => Layout {
+                rd: Some(RD),
+                imm: Some(IMM19),
+                ..none
+            },
+            Format::IO => Layout {
+                rd: Some(RD),
+                imm: Some(PORT16),
+                ..none
+            },
+        }
+    }
+}
+
+/// Cómo se interpreta el campo inmediato al decodificar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Imm {
+    None,
+    Signed,
+    Unsigned,
+}
+
+impl Imm {
+    #[inline]
+    pub fn extend(self, value: u32, bits: u32) -> u32 {
+        match self {
+            Imm::Signed => {
+                let shift = 32 - bits;
+                (((value << shift) as i32) >> shift) as u32
+            }
+            Imm::Unsigned | Imm::None => value,
+        }
+    }
+
+    /// Indica si `value` se puede codificar en `bits` bits con esta extensión.
+    pub fn fits(self, value: i64, bits: u32) -> bool {
+        match self {
+            Imm::Signed => {
+                let limit = 1i64 << (bits - 1);
+                (-limit..limit).contains(&value)
+            }
+            Imm::Unsigned | Imm::None => (0..(1i64 << bits)).contains(&value),
+        }
+    }
+}
+
+/// Operandos que acepta cada mnemónico en ensamblador.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operands {
+    None,
+    Rd,
+    /// `rd` y un `rs` opcional
+    RdOptRs,
+    RdRs1,
+    RdRs1Rs2,
+    RdImm,
+    RdRs1Imm,
+    /// `rd, rs1, pos, width`; el inmediato se codifica con `alu::bit_field`
+    RdRs1Field,
+    /// `rd, rs1, rs2, cond` con `cond` un sufijo de salto (`Z`, `NZ`, `EQ`...)
+    RdRs1Rs2Cond,
+    /// `rd, rs1, rs2` con la operación en el mnemónico (`PADDB`, `PAVGH`...)
+    Packed,
+    /// `rd, [rs1, offset]` con desplazamiento opcional
+    Mem,
+    /// `rd, [rs1 + rs2*scale]`, `rd, [rs1]+` o `rd, -[rs1]`
+    MemX,
+    /// `rs, offset`: destino de un salto indirecto, con desplazamiento
+    /// opcional. `rs` se codifica en `rs1`
+    Target,
+    Label,
+    /// `rd, label`
+    RdLabel,
+    /// `rs1, rs2, label`
+    RsRsLabel,
+    /// `rs, label`; `rs` se codifica en `rs1`
+    RsLabel,
+    RdPort,
+    /// Solo un inmediato
+    Imm,
+    /// Lista de registros como `{R4-R11, LR}`
+    RegList,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    pub opcode: Opcode,
+    pub mnemonic: &'static str,
+    pub format: Format,
+    pub imm: Imm,
+    pub operands: Operands,
+}
+
+macro_rules! isa {
+    ($($name:ident = $code:literal, $format:ident, $imm:ident, $operands:ident;)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Opcode {
+            $($name = $code,)*
+        }
+
+        pub static ISA: &[OpcodeInfo] = &[
+            $(OpcodeInfo {
+                opcode: Opcode::$name,
+                mnemonic: stringify!($name),
+                format: Format::$format,
+                imm: Imm::$imm,
+                operands: Operands::$operands,
+            },)*
+        ];
+
+        impl Opcode {
+            #[inline]
+            pub fn from_u8(value: u8) -> Option<Self> {
+                match value {
+                    $($code => Some(Opcode::$name),)*
+                    _ => None,
+                }
+            }
+
+            #[inline]
+            pub fn format(self) -> Format {
+                match self {
+                    $(Opcode::$name => Format::$format,)*
+                }
+            }
+
+            #[inline]
+            pub fn imm(self) -> Imm {
+                match self {
+                    $(Opcode::$name => Imm::$imm,)*
+                }
+            }
+
+            pub fn operands(self) -> Operands {
+                match self {
+                    $(Opcode::$name => Operands::$operands,)*
+                }
+            }
+
+            pub fn mnemonic(self) -> &'static str {
+                match self {
+                    $(Opcode::$name => stringify!($name),)*
+                }
+            }
+        }
+    };
+}
+
+isa! {
+    // ALU (R-type)
+    NOP = 0x00, R, None, None;
+    ADD = 0x01, R, None, RdRs1Rs2;
+    SUB = 0x02, R, None, RdRs1Rs2;
+    MUL = 0x03, R, None, RdRs1Rs2;
+    DIV = 0x04, R, None, RdRs1Rs2;
+    MOD = 0x05, R, None, RdRs1Rs2;
+    INC = 0x06, R, None, RdRs1;
+    DEC = 0x07, R, None, RdRs1;
+    NEG = 0x08, R, None, RdRs1;
+    ABS = 0x09, R, None, RdRs1;
+    AND = 0x0A, R, None, RdRs1Rs2;
+    OR = 0x0B, R, None, RdRs1Rs2;
+    XOR = 0x0C, R, None, RdRs1Rs2;
+    NAND = 0x0D, R, None, RdRs1Rs2;
+    NOR = 0x0E, R, None, RdRs1Rs2;
+    XNOR = 0x0F, R, None, RdRs1Rs2;
+    NOT = 0x10, R, None, RdRs1Rs2;
+    SHL = 0x11, R, None, RdRs1Rs2;
+    SHR = 0x12, R, None, RdRs1Rs2;
+    SAR = 0x13, R, None, RdRs1Rs2;
+    ROL = 0x14, R, None, RdRs1Rs2;
+    ROR = 0x15, R, None, RdRs1Rs2;
+    SEXTB = 0x16, R, None, RdRs1Rs2;
+    ZEXTB = 0x17, R, None, RdRs1Rs2;
+    POPCNT = 0x18, R, None, RdRs1Rs2;
+    CMP = 0x19, R, None, RdRs1;
+    UCMP = 0x1A, R, None, RdRs1;
+    SETZ = 0x1B, R, None, RdRs1Rs2;
+    SETNZ = 0x1C, R, None, RdRs1Rs2;
+    PASS = 0x1D, R, None, RdRs1Rs2;
+    SEXTH = 0x1E, R, None, RdRs1Rs2;
+    ZEXTH = 0x1F, R, None, RdRs1Rs2;
+
+    // ALU (I-type)
+    NOPI = 0x20, I, Unsigned, None;
+    ADDI = 0x21, I, Signed, RdRs1Imm;
+    SUBI = 0x22, I, Signed, RdRs1Imm;
+    MULI = 0x23, I, Signed, RdRs1Imm;
+    DIVI = 0x24, I, Signed, RdRs1Imm;
+    MODI = 0x25, I, Signed, RdRs1Imm;
+    INCI = 0x26, I, Signed, RdImm;
+    DECI = 0x27, I, Signed, RdImm;
+    NEGI = 0x28, I, Signed, RdImm;
+    ABSI = 0x29, I, Signed, RdImm;
+    ANDI = 0x2A, I, Unsigned, RdRs1Imm;
+    ORI = 0x2B, I, Unsigned, RdRs1Imm;
+    XORI = 0x2C, I, Unsigned, RdRs1Imm;
+    NANDI = 0x2D, I, Unsigned, RdRs1Imm;
+    NORI = 0x2E, I, Unsigned, RdRs1Imm;
+    XNORI = 0x2F, I, Unsigned, RdRs1Imm;
+    NOTI = 0x30, I, Unsigned, RdRs1Imm;
+    SHLI = 0x31, I, Unsigned, RdRs1Imm;
+    SHRI = 0x32, I, Unsigned, RdRs1Imm;
+    SARI = 0x33, I, Unsigned, RdRs1Imm;
+    ROLI = 0x34, I, Unsigned, RdRs1Imm;
+    RORI = 0x35, I, Unsigned, RdRs1Imm;
+    SEXTBI = 0x36, I, Unsigned, RdRs1Imm;
+    ZEXTBI = 0x37, I, Unsigned, RdRs1Imm;
+    POPCNTI = 0x38, I, Unsigned, RdRs1Imm;
+    CMPI = 0x39, I, Signed, RdImm;
+    UCMPI = 0x3A, I, Unsigned, RdImm;
+    SETZI = 0x3B, I, Unsigned, RdRs1Imm;
+    SETNZI = 0x3C, I, Unsigned, RdRs1Imm;
+    PASSI = 0x3D, I, Unsigned, RdRs1Imm;
+    SEXTHI = 0x3E, I, Unsigned, RdRs1Imm;
+    ZEXTHI = 0x3F, I, Unsigned, RdRs1Imm;
+
+    // Memory
+    LDB = 0x40, Mem, Signed, Mem;
+    LDBU = 0x41, Mem, Signed, Mem;
+    LDH = 0x42, Mem, Signed, Mem;
+    LDHU = 0x43, Mem, Signed, Mem;
+    LDW = 0x44, Mem, Signed, Mem;
+    STB = 0x45, Mem, Signed, Mem;
+    STH = 0x46, Mem, Signed, Mem;
+    STW = 0x47, Mem, Signed, Mem;
+    LDLR = 0x48, Mem, Signed, RdRs1;
+    STLR = 0x49, Mem, Signed, RdRs1;
+    PUSH = 0x4A, Mem, Signed, Rd;
+    POP = 0x4B, Mem, Signed, Rd;
+    // Indexadas, con post-incremento y con pre-decremento (`AddrMode`)
+    LDBX = 0x4C, MemX, Unsigned, MemX;
+    LDBUX = 0x4D, MemX, Unsigned, MemX;
+    LDHX = 0x4E, MemX, Unsigned, MemX;
+    LDHUX = 0x4F, MemX, Unsigned, MemX;
+    LDWX = 0x50, MemX, Unsigned, MemX;
+    STBX = 0x51, MemX, Unsigned, MemX;
+    STHX = 0x52, MemX, Unsigned, MemX;
+    STWX = 0x53, MemX, Unsigned, MemX;
+    // Bloques de memoria: rd = destino, rs1 = origen o valor, rs2 = bytes
+    MEMCPY = 0x54, R, None, RdRs1Rs2;
+    MEMSET = 0x55, R, None, RdRs1Rs2;
+    MEMCMP = 0x56, R, None, RdRs1Rs2;
+
+    // Empaquetadas: comparten opcode y `funct` elige la operación y el ancho
+    // de carril (`packed::PackedOp`)
+    PACKED = 0x57, RP, Unsigned, Packed;
+
+    // Jumps & Branch
+    JMP = 0x60, J, Signed, Label;
+    JZ = 0x61, J, Signed, Label;
+    JNZ = 0x62, J, Signed, Label;
+    JEQ = 0x63, J, Signed, Label;
+    JNE = 0x64, J, Signed, Label;
+    JLT = 0x65, J, Signed, Label;
+    JGT = 0x66, J, Signed, Label;
+    JLE = 0x67, J, Signed, Label;
+    JGE = 0x68, J, Signed, Label;
+    JC = 0x69, J, Signed, Label;
+    JO = 0x6A, J, Signed, Label;
+    CALL = 0x6B, J, Signed, Label;
+    RET = 0x6C, J, Signed, None;
+    HALT = 0x6D, J, Signed, None;
+    RETI = 0x6E, J, Signed, None;
+    // Indirectos: saltan a rs + offset
+    JR = 0x6F, Mem, Signed, Target;
+    CALLR = 0x70, Mem, Signed, Target;
+    // Comparan dos registros y saltan sin tocar FLAGS
+    BEQ = 0x71, B, Signed, RsRsLabel;
+    BNE = 0x72, B, Signed, RsRsLabel;
+    BLT = 0x73, B, Signed, RsRsLabel;
+    BGE = 0x74, B, Signed, RsRsLabel;
+    BLTU = 0x75, B, Signed, RsRsLabel;
+    BGEU = 0x76, B, Signed, RsRsLabel;
+    // rs -= 1 y salta si no llegó a cero
+    DBNZ = 0x77, B, Signed, RsLabel;
+
+    // Move & System
+    MOV = 0x80, SysReg, None, RdOptRs;
+    // Solo usan los 16 bits bajos; con signo para aceptar `LI rd, -1`
+    LI = 0x81, SysImm, Signed, RdImm;
+    LUI = 0x82, SysImm, Signed, RdImm;
+    MOVPC = 0x83, SysReg, None, RdOptRs;
+    MTSR = 0x84, SysReg, None, RdOptRs;
+    MFSR = 0x85, SysReg, None, RdOptRs;
+    MOVSP = 0x86, SysReg, None, RdOptRs;
+    SETSP = 0x87, SysReg, None, RdOptRs;
+    EI = 0x88, SysReg, None, None;
+    DI = 0x89, SysReg, None, None;
+    SYSCALL = 0x8A, SysImm, Unsigned, Imm;
+    MFUSP = 0x8B, SysReg, None, Rd;
+    MTUSP = 0x8C, SysReg, None, Rd;
+    // rd = rs si se cumple la condición; no tocan FLAGS
+    CMOVZ = 0x8D, SysReg, None, RdRs1;
+    CMOVNZ = 0x8E, SysReg, None, RdRs1;
+    CMOVEQ = 0x8F, SysReg, None, RdRs1;
+    CMOVNE = 0x90, SysReg, None, RdRs1;
+    CMOVLT = 0x91, SysReg, None, RdRs1;
+    CMOVGT = 0x92, SysReg, None, RdRs1;
+    CMOVLE = 0x93, SysReg, None, RdRs1;
+    CMOVGE = 0x94, SysReg, None, RdRs1;
+    CMOVC = 0x95, SysReg, None, RdRs1;
+    CMOVO = 0x96, SysReg, None, RdRs1;
+    // rd = cond ? rs1 : rs2
+    SEL = 0x97, RC, Unsigned, RdRs1Rs2Cond;
+    // Relativas al PC, con el desplazamiento en instrucciones
+    LDPC = 0x98, SysImm, Signed, RdLabel;
+    ADDPC = 0x99, SysImm, Signed, RdLabel;
+    // Pila: listas de registros (`REGLIST_*`) y marcos con R31 como FP
+    PUSHM = 0x9A, SysImm, Unsigned, RegList;
+    POPM = 0x9B, SysImm, Unsigned, RegList;
+    ENTER = 0x9C, SysImm, Unsigned, Imm;
+    LEAVE = 0x9D, SysImm, Unsigned, None;
+
+    // Floating Point
+    FADD = 0xA0, FP, None, RdRs1Rs2;
+    FSUB = 0xA1, FP, None, RdRs1Rs2;
+    FMUL = 0xA2, FP, None, RdRs1Rs2;
+    FDIV = 0xA3, FP, None, RdRs1Rs2;
+    FCMP = 0xA4, FP, None, RdRs1Rs2;
+    FEQ = 0xA5, FP, None, RdRs1Rs2;
+    FLT = 0xA6, FP, None, RdRs1Rs2;
+    FGT = 0xA7, FP, None, RdRs1Rs2;
+    FTOI = 0xA8, FP, None, RdRs1;
+    ITOF = 0xA9, FP, None, RdRs1;
+    FMOV = 0xAA, FP, None, RdRs1;
+    FLD = 0xAB, Mem, Signed, Mem;
+    FST = 0xAC, Mem, Signed, Mem;
+    FSQRT = 0xAD, FP, None, RdRs1;
+    FABS = 0xAE, FP, None, RdRs1;
+    FNEG = 0xAF, FP, None, RdRs1;
+    FMIN = 0xB0, FP, None, RdRs1Rs2;
+    FMAX = 0xB1, FP, None, RdRs1Rs2;
+    FMA = 0xB2, FP, None, RdRs1Rs2;
+    FTOIU = 0xB3, FP, None, RdRs1;
+    UTOF = 0xB4, FP, None, RdRs1;
+    MFFCSR = 0xB5, FP, None, Rd;
+    MTFCSR = 0xB6, FP, None, Rd;
+
+    // ALU extendida
+    DIVU = 0xD0, R, None, RdRs1Rs2;
+    MODU = 0xD1, R, None, RdRs1Rs2;
+    MULH = 0xD2, R, None, RdRs1Rs2;
+    MULHU = 0xD3, R, None, RdRs1Rs2;
+    MULHSU = 0xD4, R, None, RdRs1Rs2;
+    DIVUI = 0xD5, I, Unsigned, RdRs1Imm;
+    MODUI = 0xD6, I, Unsigned, RdRs1Imm;
+    ADC = 0xD7, R, None, RdRs1Rs2;
+    SBC = 0xD8, R, None, RdRs1Rs2;
+    ADCI = 0xD9, I, Signed, RdRs1Imm;
+    SBCI = 0xDA, I, Signed, RdRs1Imm;
+
+    // Manipulación de bits
+    CLZ = 0xDB, R, None, RdRs1;
+    CTZ = 0xDC, R, None, RdRs1;
+    BSWAP = 0xDD, R, None, RdRs1;
+    BREV = 0xDE, R, None, RdRs1;
+    BSET = 0xDF, R, None, RdRs1Rs2;
+    BCLR = 0xE0, R, None, RdRs1Rs2;
+    BTST = 0xE1, R, None, RdRs1Rs2;
+    BSETI = 0xE2, I, Unsigned, RdRs1Imm;
+    BCLRI = 0xE3, I, Unsigned, RdRs1Imm;
+    BTSTI = 0xE4, I, Unsigned, RdRs1Imm;
+    BFEXT = 0xE5, I, Unsigned, RdRs1Field;
+    BFEXTS = 0xE6, I, Unsigned, RdRs1Field;
+    BFINS = 0xE7, I, Unsigned, RdRs1Field;
+
+    // Doble precisión: cada operando es el par de registros F(n):F(n+1),
+    // con n par
+    FADDD = 0xE8, FPD, None, RdRs1Rs2;
+    FSUBD = 0xE9, FPD, None, RdRs1Rs2;
+    FMULD = 0xEA, FPD, None, RdRs1Rs2;
+    FDIVD = 0xEB, FPD, None, RdRs1Rs2;
+    FSQRTD = 0xEC, FPD, None, RdRs1;
+    FMAD = 0xED, FPD, None, RdRs1Rs2;
+    FMIND = 0xEE, FPD, None, RdRs1Rs2;
+    FMAXD = 0xEF, FPD, None, RdRs1Rs2;
+    FABSD = 0xF0, FPD, None, RdRs1;
+    FNEGD = 0xF1, FPD, None, RdRs1;
+    FMOVD = 0xF2, FPD, None, RdRs1;
+    FCMPD = 0xF3, FPD, None, RdRs1Rs2;
+    FEQD = 0xF4, FPD, None, RdRs1Rs2;
+    FLTD = 0xF5, FPD, None, RdRs1Rs2;
+    FGTD = 0xF6, FPD, None, RdRs1Rs2;
+    DTOI = 0xF7, FPD, None, RdRs1;
+    DTOIU = 0xF8, FPD, None, RdRs1;
+    ITOD = 0xF9, FPD, None, RdRs1;
+    UTOD = 0xFA, FPD, None, RdRs1;
+    DTOF = 0xFB, FPD, None, RdRs1;
+    FTOD = 0xFC, FPD, None, RdRs1;
+    FLDD = 0xFD, Mem, Signed, Mem;
+    FSTD = 0xFE, Mem, Signed, Mem;
+
+    // IO
+    IN = 0xC0, IO, Unsigned, RdPort;
+    OUT = 0xC1, IO, Unsigned, RdPort;
+}
@@ -1,7 +1,17 @@
 pub mod alu;
+pub mod bus;
 pub mod cpu;
+pub mod fault;
+pub mod fpu;
+pub mod icache;
 pub mod instruction;
+pub mod interrupt;
+pub mod isa;
 pub mod memory;
+pub mod mmu;
+pub mod packed;
 pub mod peripheral;
 pub mod registers;
+pub mod snapshot;
 pub mod tests;
+pub mod timing;
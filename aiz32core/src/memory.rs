@@ -1,6 +1,11 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
 
-use crate::peripheral::Peripheral;
+use crate::fault::FaultKind;
+use crate::peripheral::{Mmio, Peripheral};
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
 
 pub struct RAM {
     pub data: Vec<u8>,
@@ -90,73 +95,675 @@ impl ROM {
     }
 }
 
+/// Tamaño de las páginas de RAM con las que se sigue si el código cambió.
+pub const CODE_PAGE_SHIFT: u32 = 8;
+
+/// Qué pasa con los accesos a direcciones sin memoria.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnmappedPolicy {
+    /// `FaultKind::BusError`.
+    Fault,
+    /// Bus abierto: las lecturas devuelven este valor, recortado al ancho del
+    /// acceso, y las escrituras se pierden.
+    OpenBus(u32),
+}
+
+pub enum RegionKind {
+    Ram(RAM),
+    Rom(ROM),
+    /// Repite la región RAM o ROM que empieza en `target` a lo largo de la
+    /// ventana.
+    Mirror {
+        target: u32,
+    },
+    /// Hueco con una política distinta de la del resto del mapa.
+    Unmapped(UnmappedPolicy),
+}
+
+pub struct Region {
+    pub base: u32,
+    pub size: u32,
+    pub kind: RegionKind,
+    // Índice de la región a la que apunta un espejo
+    target: usize,
+    // Versión de cada página si es RAM; cambia con cada escritura en ella
+    code_versions: Vec<u32>,
+}
+
+/// Tabla de regiones con la que se construye un `MemoryBus`.
+///
+/// Las regiones no pueden solaparse y empiezan en direcciones alineadas a 4.
+/// Un espejo debe añadirse después de la región que repite.
+pub struct MemoryMap {
+    regions: Vec<Region>,
+    /// Dirección en la que empieza a ejecutar la CPU.
+    pub reset_vector: u32,
+    /// Política para las direcciones que no cubre ninguna región.
+    pub unmapped: UnmappedPolicy,
+}
+
+impl MemoryMap {
+    pub fn new(reset_vector: u32) -> Self {
+        Self {
+            regions: Vec::new(),
+            reset_vector,
+            unmapped: UnmappedPolicy::Fault,
+        }
+    }
+
+    /// RAM desde 0, ROM justo detrás y arranque al principio de la ROM.
+    pub fn classic(ram_size: usize, rom_contents: Vec<u8>) -> Self {
+        let mut map = Self::new(ram_size as u32);
+        map.add_ram(0, ram_size as u32);
+        map.add_rom(ram_size as u32, rom_contents);
+        map
+    }
+
+    pub fn add_ram(&mut self, base: u32, size: u32) {
+        let pages = (size as usize >> CODE_PAGE_SHIFT) + 1;
+        let index = self.add(base, size, RegionKind::Ram(RAM::new(size as usize)));
+        self.regions[index].code_versions = vec![0; pages];
+    }
+
+    pub fn add_rom(&mut self, base: u32, contents: Vec<u8>) {
+        let size = contents.len() as u32;
+        self.add(base, size, RegionKind::Rom(ROM::new(contents)));
+    }
+
+    pub fn add_mirror(&mut self, base: u32, size: u32, target: u32) {
+        let target_index = self
+            .regions
+            .iter()
+            .position(|r| {
+                r.base == target
+                    && r.size > 0
+                    && matches!(r.kind, RegionKind::Ram(_) | RegionKind::Rom(_))
+            })
+            .expect("el espejo no apunta al principio de una región RAM o ROM");
+        let index = self.add(base, size, RegionKind::Mirror { target });
+        // Insertar puede mover la región de destino
+        self.regions[index].target = target_index + (index <= target_index) as usize;
+    }
+
+    pub fn add_unmapped(&mut self, base: u32, size: u32, policy: UnmappedPolicy) {
+        self.add(base, size, RegionKind::Unmapped(policy));
+    }
+
+    // Inserta la región manteniendo el orden por base y devuelve su índice
+    fn add(&mut self, base: u32, size: u32, kind: RegionKind) -> usize {
+        assert!(base.is_multiple_of(4), "región desalineada");
+        let end = base as u64 + size as u64;
+        assert!(end <= 1 << 32, "región fuera del espacio de direcciones");
+        assert!(
+            self.regions
+                .iter()
+                .all(|r| end <= r.base as u64 || base as u64 >= r.base as u64 + r.size as u64),
+            "regiones solapadas"
+        );
+
+        let index = self.regions.partition_point(|r| r.base < base);
+        for region in &mut self.regions {
+            if matches!(region.kind, RegionKind::Mirror { .. }) && region.target >= index {
+                region.target += 1;
+            }
+        }
+        self.regions.insert(
+            index,
+            Region {
+                base,
+                size,
+                kind,
+                target: 0,
+                code_versions: Vec::new(),
+            },
+        );
+        index
+    }
+}
+
+/// Accesos a memoria por región, para cobrar sus estados de espera.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessCounts {
+    pub ram: u64,
+    pub rom: u64,
+    pub mmio: u64,
+}
+
+/// Tamaño de las páginas con las que el bus localiza regiones y MMIO.
+const LOOKUP_SHIFT: u32 = 12;
+// La página tiene más de un rango, o uno que no la cubre entera
+const LOOKUP_MIXED: u8 = u8::MAX;
+
+// Índice por página de una lista de rangos que no se solapan, para encontrar
+// el rango de una dirección sin recorrer la lista. Cada entrada es 0 si no hay
+// nada, el índice más uno si un solo rango cubre la página entera o
+// `LOOKUP_MIXED` si hay que buscar en la lista
+struct PageLookup {
+    pages: Box<[u8]>,
+}
+
+impl PageLookup {
+    fn new() -> Self {
+        Self {
+            pages: vec![0; 1 << (32 - LOOKUP_SHIFT)].into_boxed_slice(),
+        }
+    }
+
+    fn insert(&mut self, index: usize, base: u32, size: u32) {
+        if size == 0 {
+            return;
+        }
+        // Con más rangos de los que caben en un byte se busca en la lista
+        let slot = if index + 1 < LOOKUP_MIXED as usize {
+            index as u8 + 1
+        } else {
+            LOOKUP_MIXED
+        };
+        let start = base as u64;
+        let end = start + size as u64;
+        for page in (start >> LOOKUP_SHIFT)..=((end - 1) >> LOOKUP_SHIFT) {
+            let page_start = page << LOOKUP_SHIFT;
+            let whole = start <= page_start && end >= page_start + (1 << LOOKUP_SHIFT);
+            let entry = &mut self.pages[page as usize];
+            *entry = if whole && *entry == 0 {
+                slot
+            } else {
+                LOOKUP_MIXED
+            };
+        }
+    }
+
+    #[inline]
+    fn find(&self, addr: u32, search: impl FnOnce() -> Option<usize>) -> Option<usize> {
+        match self.pages[(addr >> LOOKUP_SHIFT) as usize] {
+            0 => None,
+            LOOKUP_MIXED => search(),
+            slot => Some(slot as usize - 1),
+        }
+    }
+}
+
+struct MmioRegion {
+    base: u32,
+    size: u32,
+    device: Rc<RefCell<dyn Mmio>>,
+}
+
 pub struct MemoryBus {
-    pub ram: RAM,
-    pub rom: ROM,
+    regions: Vec<Region>,
+    unmapped: UnmappedPolicy,
+    reset_vector: u32,
+    accesses: Cell<AccessCounts>,
+    mmio: Vec<MmioRegion>,
+    region_pages: PageLookup,
+    mmio_pages: PageLookup,
+}
+
+// Dónde acaba un acceso: región e índice dentro de ella
+enum Location {
+    Ram(usize, u32),
+    Rom(usize, u32),
+    Mmio(usize, u32),
+    OpenBus(u32),
 }
 
 impl MemoryBus {
+    /// Bus con el mapa clásico (ver `MemoryMap::classic`).
     pub fn new(ram_size: usize, rom_contents: Vec<u8>) -> Self {
+        Self::from_map(MemoryMap::classic(ram_size, rom_contents))
+    }
+
+    pub fn from_map(map: MemoryMap) -> Self {
+        let mut region_pages = PageLookup::new();
+        for (index, region) in map.regions.iter().enumerate() {
+            region_pages.insert(index, region.base, region.size);
+        }
         Self {
-            ram: RAM::new(ram_size),
-            rom: ROM::new(rom_contents),
+            regions: map.regions,
+            unmapped: map.unmapped,
+            reset_vector: map.reset_vector,
+            accesses: Cell::new(AccessCounts::default()),
+            mmio: Vec::new(),
+            region_pages,
+            mmio_pages: PageLookup::new(),
         }
     }
 
-    pub fn read8(&self, addr: u32) -> u8 {
-        if addr < self.ram.data.len() as u32 {
-            self.ram.read8(addr)
-        } else {
-            self.rom.read8(addr - self.ram.data.len() as u32)
+    pub fn reset_vector(&self) -> u32 {
+        self.reset_vector
+    }
+
+    /// Regiones del mapa, ordenadas por dirección base.
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Mapea un dispositivo en `[base, base + size)`. Las regiones MMIO tapan
+    /// la RAM o ROM que haya debajo y no pueden solaparse entre sí.
+    pub fn map_mmio(&mut self, base: u32, size: u32, device: Rc<RefCell<dyn Mmio>>) {
+        assert!(
+            size > 0 && base.is_multiple_of(4) && size.is_multiple_of(4),
+            "región MMIO vacía o desalineada"
+        );
+        let end = base as u64 + size as u64;
+        assert!(
+            end <= 1 << 32,
+            "región MMIO fuera del espacio de direcciones"
+        );
+        assert!(
+            self.mmio
+                .iter()
+                .all(|r| end <= r.base as u64 || base as u64 >= r.base as u64 + r.size as u64),
+            "regiones MMIO solapadas"
+        );
+        self.mmio_pages.insert(self.mmio.len(), base, size);
+        self.mmio.push(MmioRegion { base, size, device });
+    }
+
+    #[inline]
+    fn mmio_index(&self, addr: u32) -> Option<usize> {
+        self.mmio_pages.find(addr, || {
+            self.mmio
+                .iter()
+                .position(|r| addr.wrapping_sub(r.base) < r.size)
+        })
+    }
+
+    /// `addr` cae en una región MMIO. Lo que se lee de ahí no se puede cachear.
+    #[inline]
+    pub fn is_mmio(&self, addr: u32) -> bool {
+        self.mmio_index(addr).is_some()
+    }
+
+    /// Versión del código en `addr`. Cambia cada vez que se escribe en su
+    /// página de RAM; en ROM (o fuera de la memoria) siempre es 0.
+    #[inline]
+    pub fn code_version(&self, addr: u32) -> u32 {
+        match self.resolve(addr) {
+            Ok((index, offset)) if !self.regions[index].code_versions.is_empty() => {
+                self.regions[index].code_versions[(offset >> CODE_PAGE_SHIFT) as usize]
+            }
+            _ => 0,
         }
     }
 
-    pub fn write8(&mut self, addr: u32, value: u8) {
-        if addr < self.ram.data.len() as u32 {
-            self.ram.write8(addr, value);
-        } else {
-            panic!("Cannot write to ROM");
+    /// Invalida todo el código en RAM. Hace falta tras escribir en la memoria
+    /// sin pasar por el bus.
+    pub fn invalidate_code(&mut self) {
+        for region in &mut self.regions {
+            for version in &mut region.code_versions {
+                *version = version.wrapping_add(1);
+            }
         }
     }
 
-    pub fn read16(&self, addr: u32) -> u16 {
-        if addr < self.ram.data.len() as u32 {
-            self.ram.read16(addr)
-        } else {
-            self.rom.read16(addr - self.ram.data.len() as u32)
+    #[inline]
+    fn mark_written(&mut self, index: usize, offset: u32) {
+        let page = &mut self.regions[index].code_versions[(offset >> CODE_PAGE_SHIFT) as usize];
+        *page = page.wrapping_add(1);
+    }
+
+    /// Comprueba y cuenta un acceso como `read*`, sin leer el dato.
+    #[inline]
+    pub fn touch(&self, addr: u32, size: u32) -> Result<(), FaultKind> {
+        self.locate(addr, size).map(|_| ())
+    }
+
+    /// Devuelve los accesos acumulados desde la última llamada y los reinicia.
+    pub fn take_accesses(&self) -> AccessCounts {
+        self.accesses.take()
+    }
+
+    // Región RAM o ROM que contiene `addr`, siguiendo los espejos, y el
+    // desplazamiento dentro de ella. Si no hay memoria, la política que toca.
+    #[inline]
+    fn resolve(&self, addr: u32) -> Result<(usize, u32), UnmappedPolicy> {
+        let Some(index) = self.region_pages.find(addr, || {
+            self.regions
+                .iter()
+                .position(|r| addr.wrapping_sub(r.base) < r.size)
+        }) else {
+            return Err(self.unmapped);
+        };
+        let region = &self.regions[index];
+        let offset = addr - region.base;
+        match region.kind {
+            RegionKind::Mirror { .. } => {
+                let target = region.target;
+                Ok((target, offset % self.regions[target].size))
+            }
+            RegionKind::Unmapped(policy) => Err(policy),
+            RegionKind::Ram(_) | RegionKind::Rom(_) => Ok((index, offset)),
         }
     }
 
-    pub fn write16(&mut self, addr: u32, value: u16) {
-        if addr < self.ram.data.len() as u32 {
-            self.ram.write16(addr, value);
+    // Comprueba alineación y que el acceso completo cae dentro de una región
+    fn locate(&self, addr: u32, size: u32) -> Result<Location, FaultKind> {
+        if !addr.is_multiple_of(size) {
+            return Err(FaultKind::Misaligned { addr });
+        }
+
+        let mut accesses = self.accesses.get();
+        // Las regiones MMIO están alineadas a 4, así que un acceso alineado
+        // que empieza dentro de una cabe entero
+        let location = if let Some(index) = self.mmio_index(addr) {
+            accesses.mmio += 1;
+            Location::Mmio(index, addr - self.mmio[index].base)
         } else {
-            panic!("Cannot write to ROM");
+            match self.resolve(addr) {
+                Ok((index, offset)) => {
+                    let region = &self.regions[index];
+                    if offset as u64 + size as u64 > region.size as u64 {
+                        return Err(FaultKind::BusError { addr });
+                    }
+                    match region.kind {
+                        RegionKind::Ram(_) => {
+                            accesses.ram += 1;
+                            Location::Ram(index, offset)
+                        }
+                        _ => {
+                            accesses.rom += 1;
+                            Location::Rom(index, offset)
+                        }
+                    }
+                }
+                Err(UnmappedPolicy::Fault) => return Err(FaultKind::BusError { addr }),
+                Err(UnmappedPolicy::OpenBus(value)) => Location::OpenBus(value),
+            }
+        };
+        self.accesses.set(accesses);
+        Ok(location)
+    }
+
+    fn ram(&self, index: usize) -> &RAM {
+        match &self.regions[index].kind {
+            RegionKind::Ram(ram) => ram,
+            _ => unreachable!("la región no es RAM"),
         }
     }
 
-    pub fn read32(&self, addr: u32) -> u32 {
-        if addr < self.ram.data.len() as u32 {
-            self.ram.read32(addr)
-        } else {
-            self.rom.read32(addr - self.ram.data.len() as u32)
+    fn ram_mut(&mut self, index: usize) -> &mut RAM {
+        match &mut self.regions[index].kind {
+            RegionKind::Ram(ram) => ram,
+            _ => unreachable!("la región no es RAM"),
         }
     }
 
-    pub fn write32(&mut self, addr: u32, value: u32) {
-        if addr < self.ram.data.len() as u32 {
-            self.ram.write32(addr, value);
-        } else {
-            panic!("Cannot write to ROM");
+    fn rom(&self, index: usize) -> &ROM {
+        match &self.regions[index].kind {
+            RegionKind::Rom(rom) => rom,
+            _ => unreachable!("la región no es ROM"),
+        }
+    }
+
+    pub fn read8(&self, addr: u32) -> Result<u8, FaultKind> {
+        match self.locate(addr, 1)? {
+            Location::Ram(i, a) => Ok(self.ram(i).read8(a)),
+            Location::Rom(i, a) => Ok(self.rom(i).read8(a)),
+            Location::Mmio(index, offset) => Ok(self.mmio_read(index, offset, 1) as u8),
+            Location::OpenBus(value) => Ok(value as u8),
+        }
+    }
+
+    pub fn write8(&mut self, addr: u32, value: u8) -> Result<(), FaultKind> {
+        match self.locate(addr, 1)? {
+            Location::Ram(i, a) => {
+                self.ram_mut(i).write8(a, value);
+                self.mark_written(i, a);
+                Ok(())
+            }
+            Location::Rom(..) => Err(FaultKind::RomWrite { addr }),
+            Location::Mmio(index, offset) => {
+                self.mmio_write(index, offset, 1, value as u32);
+                Ok(())
+            }
+            Location::OpenBus(_) => Ok(()),
+        }
+    }
+
+    pub fn read16(&self, addr: u32) -> Result<u16, FaultKind> {
+        match self.locate(addr, 2)? {
+            Location::Ram(i, a) => Ok(self.ram(i).read16(a)),
+            Location::Rom(i, a) => Ok(self.rom(i).read16(a)),
+            Location::Mmio(index, offset) => Ok(self.mmio_read(index, offset, 2) as u16),
+            Location::OpenBus(value) => Ok(value as u16),
+        }
+    }
+
+    pub fn write16(&mut self, addr: u32, value: u16) -> Result<(), FaultKind> {
+        match self.locate(addr, 2)? {
+            Location::Ram(i, a) => {
+                self.ram_mut(i).write16(a, value);
+                self.mark_written(i, a);
+                Ok(())
+            }
+            Location::Rom(..) => Err(FaultKind::RomWrite { addr }),
+            Location::Mmio(index, offset) => {
+                self.mmio_write(index, offset, 2, value as u32);
+                Ok(())
+            }
+            Location::OpenBus(_) => Ok(()),
+        }
+    }
+
+    pub fn read32(&self, addr: u32) -> Result<u32, FaultKind> {
+        match self.locate(addr, 4)? {
+            Location::Ram(i, a) => Ok(self.ram(i).read32(a)),
+            Location::Rom(i, a) => Ok(self.rom(i).read32(a)),
+            Location::Mmio(index, offset) => Ok(self.mmio_read(index, offset, 4)),
+            Location::OpenBus(value) => Ok(value),
+        }
+    }
+
+    pub fn write32(&mut self, addr: u32, value: u32) -> Result<(), FaultKind> {
+        match self.locate(addr, 4)? {
+            Location::Ram(i, a) => {
+                self.ram_mut(i).write32(a, value);
+                self.mark_written(i, a);
+                Ok(())
+            }
+            Location::Rom(..) => Err(FaultKind::RomWrite { addr }),
+            Location::Mmio(index, offset) => {
+                self.mmio_write(index, offset, 4, value);
+                Ok(())
+            }
+            Location::OpenBus(_) => Ok(()),
+        }
+    }
+
+    // Región RAM o ROM que guarda entero `[addr, addr + len)` sin MMIO encima,
+    // y el desplazamiento dentro de ella. `None` si hay que ir byte a byte.
+    fn span(&self, addr: u32, len: u32) -> Option<(usize, usize)> {
+        let last = addr.checked_add(len.checked_sub(1)?)?;
+        if self
+            .mmio
+            .iter()
+            .any(|r| r.base <= last && (addr as u64) < r.base as u64 + r.size as u64)
+        {
+            return None;
+        }
+        let (index, offset) = self.resolve(addr).ok()?;
+        // Comparar el último byte descarta también los espejos que dan la vuelta
+        let (last_index, last_offset) = self.resolve(last).ok()?;
+        (index == last_index && last_offset - offset == len - 1).then_some((index, offset as usize))
+    }
+
+    fn bytes(&self, index: usize) -> &[u8] {
+        match &self.regions[index].kind {
+            RegionKind::Ram(ram) => &ram.data,
+            RegionKind::Rom(rom) => &rom.data,
+            _ => unreachable!("la región no es RAM ni ROM"),
+        }
+    }
+
+    fn count(&self, index: usize, len: u32) {
+        let mut accesses = self.accesses.get();
+        match self.regions[index].kind {
+            RegionKind::Ram(_) => accesses.ram += len as u64,
+            _ => accesses.rom += len as u64,
+        }
+        self.accesses.set(accesses);
+    }
+
+    // Cuenta la escritura de `[offset, offset + len)` en la RAM `index`
+    fn mark_span_written(&mut self, index: usize, offset: usize, len: u32) {
+        self.count(index, len);
+        let first = offset >> CODE_PAGE_SHIFT;
+        let last = (offset + len as usize - 1) >> CODE_PAGE_SHIFT;
+        for page in &mut self.regions[index].code_versions[first..=last] {
+            *page = page.wrapping_add(1);
         }
     }
 
+    /// Copia `len` bytes de `src` a `dst` como una secuencia de `read8` y
+    /// `write8` en orden ascendente. Si los dos rangos caen enteros en RAM o
+    /// ROM la copia se hace de golpe. Un fallo llega con los bytes que se
+    /// copiaron antes, como en `Bus::copy_block`.
+    pub fn copy_block(&mut self, dst: u32, src: u32, len: u32) -> Result<(), (u32, FaultKind)> {
+        if let Some((si, so)) = self.span(src, len)
+            && let Some((di, d)) = self.span(dst, len)
+            && matches!(self.regions[di].kind, RegionKind::Ram(_))
+        {
+            let n = len as usize;
+            // Con el destino dentro del origen y por delante, la copia byte a
+            // byte repite el patrón, cosa que `copy_within` no hace
+            let repeats = si == di && d > so && d - so < n;
+            if !repeats {
+                self.count(si, len);
+                if si == di {
+                    self.ram_mut(di).data.copy_within(so..so + n, d);
+                } else {
+                    let bytes = self.bytes(si)[so..so + n].to_vec();
+                    self.ram_mut(di).data[d..d + n].copy_from_slice(&bytes);
+                }
+                self.mark_span_written(di, d, len);
+                return Ok(());
+            }
+        }
+
+        for i in 0..len {
+            let value = self.read8(src.wrapping_add(i)).map_err(|e| (i, e))?;
+            self.write8(dst.wrapping_add(i), value)
+                .map_err(|e| (i, e))?;
+        }
+        Ok(())
+    }
+
+    /// Escribe `value` en los `len` bytes que empiezan en `dst`.
+    pub fn fill_block(&mut self, dst: u32, value: u8, len: u32) -> Result<(), FaultKind> {
+        if let Some((di, d)) = self.span(dst, len)
+            && matches!(self.regions[di].kind, RegionKind::Ram(_))
+        {
+            self.ram_mut(di).data[d..d + len as usize].fill(value);
+            self.mark_span_written(di, d, len);
+            return Ok(());
+        }
+
+        for i in 0..len {
+            self.write8(dst.wrapping_add(i), value)?;
+        }
+        Ok(())
+    }
+
+    /// Compara `len` bytes a partir de `a` y de `b`. Devuelve la posición de
+    /// la primera diferencia y los dos bytes, leyendo solo hasta ella.
+    pub fn compare_block(
+        &self,
+        a: u32,
+        b: u32,
+        len: u32,
+    ) -> Result<Option<(u32, u8, u8)>, FaultKind> {
+        if let Some((ai, ao)) = self.span(a, len)
+            && let Some((bi, bo)) = self.span(b, len)
+        {
+            let n = len as usize;
+            let left = &self.bytes(ai)[ao..ao + n];
+            let right = &self.bytes(bi)[bo..bo + n];
+            let mismatch = left.iter().zip(right).position(|(x, y)| x != y);
+            let read = mismatch.map_or(len, |i| i as u32 + 1);
+            self.count(ai, read);
+            self.count(bi, read);
+            return Ok(mismatch.map(|i| (i as u32, left[i], right[i])));
+        }
+
+        for i in 0..len {
+            let x = self.read8(a.wrapping_add(i))?;
+            let y = self.read8(b.wrapping_add(i))?;
+            if x != y {
+                return Ok(Some((i, x, y)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn mmio_read(&self, index: usize, offset: u32, size: u32) -> u32 {
+        self.mmio[index].device.borrow().mmio_read(offset, size)
+    }
+
+    fn mmio_write(&mut self, index: usize, offset: u32, size: u32, value: u32) {
+        self.mmio[index]
+            .device
+            .borrow_mut()
+            .mmio_write(offset, size, value);
+    }
+
+    /// Bytes de RAM entre todas las regiones.
     pub fn ram_size(&self) -> usize {
-        self.ram.data.len()
+        self.regions
+            .iter()
+            .map(|r| match &r.kind {
+                RegionKind::Ram(ram) => ram.data.len(),
+                _ => 0,
+            })
+            .sum()
     }
 
+    /// Bytes de ROM entre todas las regiones.
     pub fn rom_size(&self) -> usize {
-        self.rom.data.len()
+        self.regions
+            .iter()
+            .map(|r| match &r.kind {
+                RegionKind::Rom(rom) => rom.data.len(),
+                _ => 0,
+            })
+            .sum()
+    }
+
+    // La ROM no se guarda: solo se comprueba que sea la misma longitud
+    pub fn save_state(&self, out: &mut StateWriter) {
+        out.write_u32(self.regions.len() as u32);
+        for region in &self.regions {
+            match &region.kind {
+                RegionKind::Ram(ram) => out.write_bytes(&ram.data),
+                RegionKind::Rom(rom) => out.write_u32(rom.data.len() as u32),
+                RegionKind::Mirror { .. } | RegionKind::Unmapped(_) => {}
+            }
+        }
+    }
+
+    pub fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        if input.read_u32()? as usize != self.regions.len() {
+            return Err(SnapshotError::Mismatch("memory map"));
+        }
+        for region in &mut self.regions {
+            match &mut region.kind {
+                RegionKind::Ram(ram) => {
+                    let data = input.read_bytes()?;
+                    if data.len() != ram.data.len() {
+                        return Err(SnapshotError::Mismatch("RAM size"));
+                    }
+                    ram.data.copy_from_slice(data);
+                }
+                RegionKind::Rom(rom) => {
+                    if input.read_u32()? as usize != rom.data.len() {
+                        return Err(SnapshotError::Mismatch("ROM size"));
+                    }
+                }
+                RegionKind::Mirror { .. } | RegionKind::Unmapped(_) => {}
+            }
+        }
+        self.invalidate_code();
+        Ok(())
     }
 }
 
@@ -165,6 +772,12 @@ pub struct IO {
     peripherals: Vec<Rc<RefCell<dyn Peripheral>>>,
 }
 
+impl Default for IO {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl IO {
     pub fn new() -> Self {
         Self {
@@ -196,4 +809,30 @@ impl IO {
             }
         }
     }
+
+    /// Guarda los puertos y, en orden de registro, el estado de cada periférico.
+    pub fn save_state(&self, out: &mut StateWriter) {
+        out.write_words(&self.ports);
+        out.write_u32(self.peripherals.len() as u32);
+        for peripheral in &self.peripherals {
+            let mut state = StateWriter::new();
+            peripheral.borrow().save_state(&mut state);
+            out.write_bytes(&state.into_bytes());
+        }
+    }
+
+    pub fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        input.read_words_into(&mut self.ports, "IO ports")?;
+        if input.read_u32()? as usize != self.peripherals.len() {
+            return Err(SnapshotError::Mismatch("peripheral count"));
+        }
+        for peripheral in &self.peripherals {
+            let mut state = StateReader::new(input.read_bytes()?);
+            peripheral.borrow_mut().load_state(&mut state)?;
+            if !state.is_empty() {
+                return Err(SnapshotError::Mismatch("peripheral state"));
+            }
+        }
+        Ok(())
+    }
 }
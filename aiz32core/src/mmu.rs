@@ -0,0 +1,238 @@
+//! Unidad de gestión de memoria con paginación opcional.
+//!
+//! Está deshabilitada al arrancar: las direcciones pasan tal cual al
+//! `MemoryBus` y los binarios de siempre no notan nada. Al habilitarla, cada
+//! acceso de la CPU se traduce con tablas de páginas de dos niveles en memoria
+//! física:
+//!
+//! ```text
+//!  31        22 21        12 11          0
+//! | índice L1  | índice L2  | desplazamiento |
+//! ```
+//!
+//! `ptbr` apunta a la tabla L1 (1024 entradas de 32 bits, alineada a página).
+//! Cada entrada válida de L1 apunta a una tabla L2 y cada entrada de L2 a un
+//! marco de 4 KiB. En ambas los bits 31..12 son la dirección física y los bajos
+//! son los permisos `PTE_*`; en L1 solo cuenta `PTE_VALID`.
+//!
+//! Las traducciones se guardan en una TLB pequeña de correspondencia directa.
+//! Cambiar `ptbr` o la configuración la vacía; tras modificar una tabla el
+//! sistema debe invalidar la página escribiendo su dirección en `MMU_FLUSH`.
+
+use crate::bus::Bus;
+use crate::fault::FaultKind;
+use crate::peripheral::Peripheral;
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+
+pub const PAGE_SHIFT: u32 = 12;
+pub const PAGE_SIZE: u32 = 1 << PAGE_SHIFT;
+pub const TLB_ENTRIES: usize = 16;
+
+// Bits de permiso de una entrada de tabla de páginas
+pub const PTE_VALID: u32 = 0x01;
+pub const PTE_READ: u32 = 0x02;
+pub const PTE_WRITE: u32 = 0x04;
+pub const PTE_EXEC: u32 = 0x08;
+pub const PTE_USER: u32 = 0x10;
+
+// Puertos de la MMU
+pub const MMU_CONTROL: u16 = 0x1010;
+pub const MMU_PTBR: u16 = 0x1011;
+pub const MMU_FLUSH: u16 = 0x1012;
+pub const MMU_FAULT_ACCESS: u16 = 0x1013;
+
+/// Bit de `MMU_CONTROL` que habilita la traducción.
+pub const MMU_ENABLE: u32 = 0x1;
+
+/// Tipo de acceso que se comprueba contra los permisos de la página.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read = 1,
+    Write = 2,
+    Execute = 3,
+}
+
+impl Access {
+    fn permission(self) -> u32 {
+        match self {
+            Access::Read => PTE_READ,
+            Access::Write => PTE_WRITE,
+            Access::Execute => PTE_EXEC,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TlbEntry {
+    vpn: u32,
+    pte: u32,
+}
+
+pub struct Mmu {
+    enabled: bool,
+    ptbr: u32,
+    /// Tipo de acceso del último fallo de página, para el manejador.
+    fault_access: u32,
+    tlb: [Option<TlbEntry>; TLB_ENTRIES],
+    hits: u64,
+    misses: u64,
+}
+
+impl Default for Mmu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mmu {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            ptbr: 0,
+            fault_access: 0,
+            tlb: [None; TLB_ENTRIES],
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.flush();
+    }
+
+    pub fn ptbr(&self) -> u32 {
+        self.ptbr
+    }
+
+    pub fn set_ptbr(&mut self, ptbr: u32) {
+        self.ptbr = ptbr & !(PAGE_SIZE - 1);
+        self.flush();
+    }
+
+    pub fn flush(&mut self) {
+        self.tlb = [None; TLB_ENTRIES];
+    }
+
+    /// Invalida la traducción de la página que contiene `addr`.
+    pub fn flush_page(&mut self, addr: u32) {
+        let vpn = addr >> PAGE_SHIFT;
+        let slot = &mut self.tlb[vpn as usize % TLB_ENTRIES];
+        if slot.is_some_and(|e| e.vpn == vpn) {
+            *slot = None;
+        }
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Traduce una dirección virtual a física. En modo supervisor se ignora
+    /// `PTE_USER`; en modo usuario la página debe tenerlo.
+    #[inline]
+    pub fn translate<B: Bus>(
+        &mut self,
+        bus: &mut B,
+        addr: u32,
+        access: Access,
+        user: bool,
+    ) -> Result<u32, FaultKind> {
+        if !self.enabled {
+            return Ok(addr);
+        }
+
+        let pte = self.lookup(bus, addr).inspect_err(|_| {
+            self.fault_access = access as u32;
+        })?;
+
+        let mut required = PTE_VALID | access.permission();
+        if user {
+            required |= PTE_USER;
+        }
+        if pte & required != required {
+            self.fault_access = access as u32;
+            return Err(FaultKind::PageFault { addr });
+        }
+
+        Ok((pte & !(PAGE_SIZE - 1)) | (addr & (PAGE_SIZE - 1)))
+    }
+
+    // Entrada L2 de la página, de la TLB o recorriendo las tablas. Solo se
+    // guardan en la TLB las entradas válidas.
+    fn lookup<B: Bus>(&mut self, bus: &mut B, addr: u32) -> Result<u32, FaultKind> {
+        let vpn = addr >> PAGE_SHIFT;
+        let slot = vpn as usize % TLB_ENTRIES;
+        if let Some(entry) = self.tlb[slot]
+            && entry.vpn == vpn
+        {
+            self.hits += 1;
+            return Ok(entry.pte);
+        }
+        self.misses += 1;
+
+        let page_fault = |_| FaultKind::PageFault { addr };
+        let l1 = bus
+            .read32(self.ptbr | ((addr >> 22) << 2))
+            .map_err(page_fault)?;
+        if l1 & PTE_VALID == 0 {
+            return Err(FaultKind::PageFault { addr });
+        }
+        let l2_base = l1 & !(PAGE_SIZE - 1);
+        let pte = bus
+            .read32(l2_base | (((addr >> PAGE_SHIFT) & 0x3FF) << 2))
+            .map_err(page_fault)?;
+        if pte & PTE_VALID == 0 {
+            return Err(FaultKind::PageFault { addr });
+        }
+
+        self.tlb[slot] = Some(TlbEntry { vpn, pte });
+        Ok(pte)
+    }
+}
+
+impl Peripheral for Mmu {
+    fn handles_port(&self, port: u16) -> bool {
+        (MMU_CONTROL..=MMU_FAULT_ACCESS).contains(&port)
+    }
+
+    fn read(&self, port: u16) -> u32 {
+        match port {
+            MMU_CONTROL => self.enabled as u32 * MMU_ENABLE,
+            MMU_PTBR => self.ptbr,
+            MMU_FAULT_ACCESS => self.fault_access,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, port: u16, value: u32) {
+        match port {
+            MMU_CONTROL => self.set_enabled(value & MMU_ENABLE != 0),
+            MMU_PTBR => self.set_ptbr(value),
+            MMU_FLUSH => self.flush_page(value),
+            _ => {}
+        }
+    }
+
+    // La TLB no se guarda; se vuelve a llenar desde las tablas
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_bool(self.enabled);
+        out.write_u32(self.ptbr);
+        out.write_u32(self.fault_access);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.enabled = input.read_bool()?;
+        self.ptbr = input.read_u32()?;
+        self.fault_access = input.read_u32()?;
+        self.flush();
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+//! Aritmética empaquetada sobre los registros generales.
+//!
+//! Cada operación trabaja por separado en los cuatro bytes (sufijo B) o en
+//! las dos medias palabras (sufijo H) de sus operandos, sin acarreos entre
+//! carriles y sin tocar los flags. Con colores 0xAARRGGBB, un carril de byte
+//! es un canal.
+//!
+//! PSHUFB elige cada byte del resultado con el byte del mismo carril de rs2:
+//! los bits 1..0 dicen qué byte de rs1 se copia y el bit 7 lo pone a cero.
+//!
+//! Todas se codifican con el opcode PACKED. En su campo `funct` los bits 3..0
+//! son la posición de la operación en `PackedOp::ALL` y el bit 4 elige
+//! medias palabras.
+
+/// Ancho de los carriles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lanes {
+    Bytes,
+    Halves,
+}
+
+impl Lanes {
+    pub fn bits(self) -> u32 {
+        match self {
+            Lanes::Bytes => 8,
+            Lanes::Halves => 16,
+        }
+    }
+
+    /// Sufijo del mnemónico.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Lanes::Bytes => "B",
+            Lanes::Halves => "H",
+        }
+    }
+}
+
+const FUNCT_HALVES: u8 = 0x10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackedOp {
+    Add,
+    AddSatU,
+    AddSat,
+    Sub,
+    SubSatU,
+    SubSat,
+    MinU,
+    MaxU,
+    /// Media sin signo redondeada hacia arriba.
+    AvgU,
+    /// Mitad alta del producto sin signo de cada carril.
+    MulHighU,
+    Shuffle,
+}
+
+impl PackedOp {
+    pub const ALL: [PackedOp; 11] = [
+        PackedOp::Add,
+        PackedOp::AddSatU,
+        PackedOp::AddSat,
+        PackedOp::Sub,
+        PackedOp::SubSatU,
+        PackedOp::SubSat,
+        PackedOp::MinU,
+        PackedOp::MaxU,
+        PackedOp::AvgU,
+        PackedOp::MulHighU,
+        PackedOp::Shuffle,
+    ];
+
+    /// Operación y ancho de carril de `funct`, o `None` si no codifica
+    /// ninguna. PSHUFB solo existe sobre bytes.
+    #[inline]
+    pub fn from_funct(funct: u8) -> Option<(Self, Lanes)> {
+        let op = *Self::ALL.get((funct & 0xF) as usize)?;
+        let lanes = if funct & FUNCT_HALVES != 0 {
+            Lanes::Halves
+        } else {
+            Lanes::Bytes
+        };
+        (op != PackedOp::Shuffle || lanes == Lanes::Bytes).then_some((op, lanes))
+    }
+
+    pub fn funct(self, lanes: Lanes) -> u8 {
+        let index = Self::ALL.iter().position(|&op| op == self).unwrap() as u8;
+        match lanes {
+            Lanes::Bytes => index,
+            Lanes::Halves => index | FUNCT_HALVES,
+        }
+    }
+
+    /// Todas las combinaciones de operación y ancho que existen.
+    pub fn variants() -> impl Iterator<Item = (Self, Lanes)> {
+        (0..32).filter_map(Self::from_funct)
+    }
+
+    pub fn mnemonic(self, lanes: Lanes) -> String {
+        let name = match self {
+            PackedOp::Add => "PADD",
+            PackedOp::AddSatU => "PADDUS",
+            PackedOp::AddSat => "PADDS",
+            PackedOp::Sub => "PSUB",
+            PackedOp::SubSatU => "PSUBUS",
+            PackedOp::SubSat => "PSUBS",
+            PackedOp::MinU => "PMINU",
+            PackedOp::MaxU => "PMAXU",
+            PackedOp::AvgU => "PAVG",
+            PackedOp::MulHighU => "PMULHU",
+            PackedOp::Shuffle => "PSHUF",
+        };
+        format!("{}{}", name, lanes.suffix())
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<(Self, Lanes)> {
+        Self::variants().find(|&(op, lanes)| op.mnemonic(lanes) == mnemonic)
+    }
+
+    pub fn execute(self, lanes: Lanes, a: u32, b: u32) -> u32 {
+        if self == PackedOp::Shuffle {
+            return shuffle(a, b);
+        }
+
+        let bits = lanes.bits();
+        let mask = u32::MAX >> (32 - bits);
+        (0..32).step_by(bits as usize).fold(0, |out, shift| {
+            let lane = self.lane((a >> shift) & mask, (b >> shift) & mask, bits);
+            out | (lane & mask) << shift
+        })
+    }
+
+    // Un carril; `x` e `y` vienen sin signo en los `bits` bajos
+    fn lane(self, x: u32, y: u32, bits: u32) -> u32 {
+        let max = u32::MAX >> (32 - bits);
+        // Límites con signo del carril
+        let (lo, hi) = (-(1i32 << (bits - 1)), (1i32 << (bits - 1)) - 1);
+        let signed = |v: u32| ((v << (32 - bits)) as i32) >> (32 - bits);
+
+        match self {
+            PackedOp::Add => x.wrapping_add(y),
+            PackedOp::AddSatU => (x + y).min(max),
+            PackedOp::AddSat => (signed(x) + signed(y)).clamp(lo, hi) as u32,
+            PackedOp::Sub => x.wrapping_sub(y),
+            PackedOp::SubSatU => x.saturating_sub(y),
+            PackedOp::SubSat => (signed(x) - signed(y)).clamp(lo, hi) as u32,
+            PackedOp::MinU => x.min(y),
+            PackedOp::MaxU => x.max(y),
+            PackedOp::AvgU => (x + y + 1) >> 1,
+            PackedOp::MulHighU => (x * y) >> bits,
+            PackedOp::Shuffle => unreachable!("PSHUFB no va por carriles"),
+        }
+    }
+}
+
+fn shuffle(a: u32, b: u32) -> u32 {
+    let bytes = a.to_le_bytes();
+    let selectors = b.to_le_bytes();
+    u32::from_le_bytes(selectors.map(|sel| {
+        if sel & 0x80 != 0 {
+            0
+        } else {
+            bytes[(sel & 0x3) as usize]
+        }
+    }))
+}
@@ -1,5 +1,28 @@
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+
 pub trait Peripheral {
     fn handles_port(&self, port: u16) -> bool;
     fn read(&self, port: u16) -> u32;
     fn write(&mut self, port: u16, value: u32);
-}
\ No newline at end of file
+
+    /// Guarda el estado del periférico en un estado de la máquina. Por defecto
+    /// no guarda nada; los periféricos con estado deben implementarlo junto a
+    /// `load_state`.
+    fn save_state(&self, _out: &mut StateWriter) {}
+
+    /// Restaura lo que escribió `save_state`.
+    fn load_state(&mut self, _input: &mut StateReader) -> Result<(), SnapshotError> {
+        Ok(())
+    }
+}
+
+/// Dispositivo mapeado en un rango de direcciones del `MemoryBus`.
+///
+/// `offset` es relativo al inicio de la región y `size` es el ancho del
+/// acceso en bytes (1, 2 o 4); el bus ya comprobó la alineación. Un mismo
+/// dispositivo puede implementar también `Peripheral` para exponer puertos, y
+/// es ahí donde guarda su estado.
+pub trait Mmio {
+    fn mmio_read(&self, offset: u32, size: u32) -> u32;
+    fn mmio_write(&mut self, offset: u32, size: u32, value: u32);
+}
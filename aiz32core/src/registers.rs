@@ -1,3 +1,9 @@
+use crate::snapshot::{SnapshotError, StateReader, StateWriter};
+
+/// Registro que ENTER y LEAVE usan como puntero de marco.
+pub const FRAME_POINTER: u8 = 31;
+
+#[derive(Default)]
 pub struct Register {
     pub value: u32,
 }
@@ -16,9 +22,13 @@ pub struct RegisterBank {
     pub general: [Register; 32],
     pub pc: Register,
     pub sp: Register,
+    /// SP del otro modo de privilegio; se intercambia con `sp` al cambiar de modo.
+    pub banked_sp: Register,
     pub lr: Register,
     pub flags: Register,
     pub fregs: [f32; 32],
+    /// Registro de control y estado de coma flotante (ver `fpu`).
+    pub fcsr: Register,
 }
 
 impl RegisterBank {
@@ -27,9 +37,11 @@ impl RegisterBank {
             general: std::array::from_fn(|_| Register::new()),
             pc: Register::from(pc),
             sp: Register::from(sp),
+            banked_sp: Register::new(),
             lr: Register::new(),
             flags: Register::new(),
             fregs: [0.0; 32],
+            fcsr: Register::new(),
         }
     }
 
@@ -69,6 +81,20 @@ impl RegisterBank {
         self.sp.value = v
     }
 
+    #[inline]
+    pub fn banked_sp(&self) -> u32 {
+        self.banked_sp.value
+    }
+
+    #[inline]
+    pub fn set_banked_sp(&mut self, v: u32) {
+        self.banked_sp.value = v
+    }
+
+    pub fn swap_sp(&mut self) {
+        std::mem::swap(&mut self.sp, &mut self.banked_sp);
+    }
+
     #[inline]
     pub fn lr(&self) -> u32 {
         self.lr.value
@@ -88,6 +114,16 @@ impl RegisterBank {
         self.flags.value = v
     }
 
+    #[inline]
+    pub fn fcsr(&self) -> u32 {
+        self.fcsr.value
+    }
+
+    #[inline]
+    pub fn set_fcsr(&mut self, v: u32) {
+        self.fcsr.value = v
+    }
+
     #[inline]
     pub fn fget(&self, idx: u8) -> f32 {
         let i = idx as usize;
@@ -107,4 +143,53 @@ impl RegisterBank {
         );
         self.fregs[i] = val;
     }
+
+    /// Lee el par F(idx):F(idx + 1) como un f64: la palabra baja va en el
+    /// registro par. `idx` debe ser par.
+    #[inline]
+    pub fn dget(&self, idx: u8) -> f64 {
+        debug_assert!(idx.is_multiple_of(2), "double register must be even");
+        let lo = self.fget(idx).to_bits() as u64;
+        let hi = self.fget(idx + 1).to_bits() as u64;
+        f64::from_bits(hi << 32 | lo)
+    }
+
+    #[inline]
+    pub fn dset(&mut self, idx: u8, val: f64) {
+        debug_assert!(idx.is_multiple_of(2), "double register must be even");
+        let bits = val.to_bits();
+        self.fset(idx, f32::from_bits(bits as u32));
+        self.fset(idx + 1, f32::from_bits((bits >> 32) as u32));
+    }
+
+    pub fn save_state(&self, out: &mut StateWriter) {
+        for reg in &self.general {
+            out.write_u32(reg.value);
+        }
+        out.write_u32(self.pc.value);
+        out.write_u32(self.sp.value);
+        out.write_u32(self.banked_sp.value);
+        out.write_u32(self.lr.value);
+        out.write_u32(self.flags.value);
+        for &freg in &self.fregs {
+            out.write_f32(freg);
+        }
+        out.write_u32(self.fcsr.value);
+    }
+
+    pub fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        for reg in &mut self.general {
+            reg.value = input.read_u32()?;
+        }
+        self.pc.value = input.read_u32()?;
+        self.sp.value = input.read_u32()?;
+        self.banked_sp.value = input.read_u32()?;
+        self.lr.value = input.read_u32()?;
+        self.flags.value = input.read_u32()?;
+        for freg in &mut self.fregs {
+            *freg = input.read_f32()?;
+        }
+        self.fcsr.value = input.read_u32()?;
+        Ok(())
+    }
 }
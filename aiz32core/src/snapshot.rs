@@ -0,0 +1,168 @@
+//! Formato binario de los estados guardados de la máquina.
+//!
+//! Un estado empieza por `SNAPSHOT_MAGIC` y `SNAPSHOT_VERSION`, seguidos de
+//! los registros, el PIC, la MMU y el estado del bus; el de `SystemBus` es la
+//! memoria, los puertos y el estado de cada periférico en orden de registro.
+//! Todos los enteros van en little-endian.
+
+use std::fmt;
+
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"AZ32";
+pub const SNAPSHOT_VERSION: u32 = 6;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    UnexpectedEof,
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// El estado no corresponde a esta máquina (tamaños o periféricos distintos).
+    Mismatch(&'static str),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::UnexpectedEof => write!(f, "unexpected end of snapshot"),
+            SnapshotError::BadMagic => write!(f, "not an aiz32 snapshot"),
+            SnapshotError::UnsupportedVersion(v) => {
+                write!(f, "unsupported snapshot version {}", v)
+            }
+            SnapshotError::Mismatch(what) => write!(f, "snapshot does not match machine: {}", what),
+            SnapshotError::Io(err) => write!(f, "snapshot io error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(err: std::io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_f32(&mut self, value: f32) {
+        self.write_u32(value.to_bits());
+    }
+
+    /// Bytes precedidos de su longitud.
+    pub fn write_bytes(&mut self, data: &[u8]) {
+        self.write_u32(data.len() as u32);
+        self.buf.extend_from_slice(data);
+    }
+
+    /// Palabras precedidas de su número.
+    pub fn write_words(&mut self, data: &[u32]) {
+        self.write_u32(data.len() as u32);
+        for &word in data {
+            self.write_u32(word);
+        }
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(SnapshotError::UnexpectedEof)?;
+        let slice = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SnapshotError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SnapshotError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SnapshotError> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn read_f32(&mut self) -> Result<f32, SnapshotError> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], SnapshotError> {
+        let len = self.read_u32()? as usize;
+        self.take(len)
+    }
+
+    pub fn read_words(&mut self) -> Result<Vec<u32>, SnapshotError> {
+        let len = self.read_u32()? as usize;
+        let bytes = self.take(len.checked_mul(4).ok_or(SnapshotError::UnexpectedEof)?)?;
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Lee palabras en un búfer existente, que debe tener la misma longitud.
+    pub fn read_words_into(
+        &mut self,
+        out: &mut [u32],
+        what: &'static str,
+    ) -> Result<(), SnapshotError> {
+        let words = self.read_words()?;
+        if words.len() != out.len() {
+            return Err(SnapshotError::Mismatch(what));
+        }
+        out.copy_from_slice(&words);
+        Ok(())
+    }
+}
@@ -1,8 +1,29 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
-    use crate::alu::Flags;
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    use crate::alu::{Condition, Flags, bit_field};
+    use crate::bus::Bus;
     use crate::cpu::CPU;
-    use crate::instruction::{Instruction, Opcode};
+    use crate::fault::{FaultKind, SYSCALL_CAUSE, SYSCALL_VECTOR, StepOutcome};
+    use crate::fpu::{
+        FCSR_DIV_ZERO, FCSR_FLAGS, FCSR_INEXACT, FCSR_INVALID, FCSR_MASK, FCSR_OVERFLOW,
+        FCSR_UNDERFLOW, Rounding,
+    };
+    use crate::instruction::{AddrMode, Instruction, Opcode, REGLIST_HIGH, REGLIST_LR};
+    use crate::interrupt::{PIC_FAULT_ADDR, PIC_FAULT_CAUSE, PIC_MASK, PIC_VECTOR_BASE};
+    use crate::isa::{Field, ISA, OPCODE};
+    use crate::memory::{MemoryBus, MemoryMap, RegionKind, UnmappedPolicy};
+    use crate::mmu::{
+        Access, MMU_CONTROL, MMU_ENABLE, MMU_FAULT_ACCESS, MMU_FLUSH, MMU_PTBR, PTE_EXEC, PTE_READ,
+        PTE_USER, PTE_VALID, PTE_WRITE,
+    };
+    use crate::packed::PackedOp;
+    use crate::peripheral::{Mmio, Peripheral};
+    use crate::registers::FRAME_POINTER;
+    use crate::snapshot::{SNAPSHOT_VERSION, SnapshotError, StateReader, StateWriter};
+    use crate::timing::{DEFAULT_CLOCK_HZ, Timing, VECTOR_ENTRY_CYCLES};
 
     #[test]
     fn test_cpu_initialization() {
@@ -15,11 +36,11 @@ mod tests {
 
     #[test]
     fn test_cpu_rtype_add() {
-        let mut cpu = CPU::new(1024, vec![0, 0, 0, 1], 0, 0); // ADD R0, R0, R0
-        cpu.regs.set(0, 5);
-        cpu.step();
-        assert_eq!(cpu.regs.get(0), 10);
-        assert_eq!(cpu.cycle_count, 1);
+        let mut cpu = CPU::new(1024, vec![0x00, 0x42, 0x08, 0x01], 0, 1024); // ADD R1, R1, R1
+        cpu.regs.set(1, 5);
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.get(1), 10);
+        assert_eq!(cpu.cycle_count, 2); // 1 base + 1 espera por buscar en ROM
         let flags = Flags::from_u32(cpu.regs.flags());
         assert!(!flags.zero);
     }
@@ -35,7 +56,7 @@ mod tests {
             rs1: 1,
             rs2: 2,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
 
         assert_eq!(cpu.regs.get(0), 0);
         let flags = Flags::from_u32(cpu.regs.flags());
@@ -45,14 +66,14 @@ mod tests {
     #[test]
     fn test_cpu_itype_addi() {
         let mut cpu = CPU::new(1024, vec![], 0, 0);
-        cpu.regs.set(0, 7);
+        cpu.regs.set(2, 7);
         let instr = Instruction::I {
             opcode: Opcode::ADDI,
             rd: 1,
-            rs1: 0,
+            rs1: 2,
             imm: 5,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(1), 12);
 
         let flags = Flags::from_u32(cpu.regs.flags());
@@ -62,14 +83,14 @@ mod tests {
     #[test]
     fn test_cpu_flags_carry_zero() {
         let mut cpu = CPU::new(1024, vec![], 0, 0);
-        cpu.regs.set(0, 0xFFFFFFFF);
+        cpu.regs.set(2, 0xFFFFFFFF);
         let instr = Instruction::I {
             opcode: Opcode::ADDI,
             rd: 1,
-            rs1: 0,
+            rs1: 2,
             imm: 1,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(1), 0);
 
         let flags = Flags::from_u32(cpu.regs.flags());
@@ -80,11 +101,11 @@ mod tests {
     #[test]
     fn test_cpu_memorybus_ram_read_write() {
         let mut cpu = CPU::new(1024, vec![], 0, 0);
-        cpu.mem.write32(0, 0x12345678);
-        assert_eq!(cpu.mem.read32(0), 0x12345678);
-        cpu.mem.write16(2, 0xABCD);
-        assert_eq!(cpu.mem.read16(2), 0xABCD);
-        assert_eq!(cpu.mem.read8(3), 0xAB);
+        cpu.mem.write32(0, 0x12345678).unwrap();
+        assert_eq!(cpu.mem.read32(0).unwrap(), 0x12345678);
+        cpu.mem.write16(2, 0xABCD).unwrap();
+        assert_eq!(cpu.mem.read16(2).unwrap(), 0xABCD);
+        assert_eq!(cpu.mem.read8(3).unwrap(), 0xAB);
     }
 
     #[test]
@@ -92,11 +113,11 @@ mod tests {
         let rom_data = vec![0xDE, 0xAD, 0xBE, 0xEF];
         let cpu = CPU::new(1024, rom_data.clone(), 0, 0);
 
-        for i in 0..4 {
-            assert_eq!(cpu.mem.read8(i as u32), rom_data[i]);
+        for (i, byte) in rom_data.iter().enumerate() {
+            assert_eq!(cpu.mem.read8(1024 + i as u32).unwrap(), *byte);
         }
-        assert_eq!(cpu.mem.read16(0), 0xADDE);
-        assert_eq!(cpu.mem.read32(0), 0xEFBEADDE);
+        assert_eq!(cpu.mem.read16(1024).unwrap(), 0xADDE);
+        assert_eq!(cpu.mem.read32(1024).unwrap(), 0xEFBEADDE);
     }
 
     #[test]
@@ -104,7 +125,7 @@ mod tests {
         let mut cpu = CPU::new(1024, vec![], 0, 0);
         cpu.halted = true;
         let pc_before = cpu.regs.pc();
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.regs.pc(), pc_before);
         assert_eq!(cpu.cycle_count, 0);
     }
@@ -117,8 +138,8 @@ mod tests {
             opcode: Opcode::JMP,
             offset: 100, // relativo
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.regs.pc(), 10 + 100);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 10 + 100 * 4);
     }
 
     #[test]
@@ -131,8 +152,8 @@ mod tests {
             opcode: Opcode::JZ,
             offset: 50,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.regs.pc(), 20 + 50);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 20 + 50 * 4);
 
         cpu.regs.set_pc(20);
         cpu.regs.set_flags(0x00); // zero = 0
@@ -140,7 +161,7 @@ mod tests {
             opcode: Opcode::JZ,
             offset: 60,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.pc(), 20); // no cambia
     }
 
@@ -154,8 +175,8 @@ mod tests {
             opcode: Opcode::JNZ,
             offset: 30,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.regs.pc(), 30 + 30);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 30 + 30 * 4);
 
         cpu.regs.set_pc(30);
         cpu.regs.set_flags(0x01); // zero = 1
@@ -163,7 +184,7 @@ mod tests {
             opcode: Opcode::JNZ,
             offset: 40,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.pc(), 30); // no cambia
     }
 
@@ -176,8 +197,8 @@ mod tests {
             opcode: Opcode::JGT,
             offset: 123,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.regs.pc(), 50 + 123);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 50 + 123 * 4);
 
         cpu.regs.set_pc(50);
         cpu.regs.set_flags(0x80); // less
@@ -185,8 +206,8 @@ mod tests {
             opcode: Opcode::JLT,
             offset: 200,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.regs.pc(), 50 + 200);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 50 + 200 * 4);
 
         cpu.regs.set_pc(50);
         cpu.regs.set_flags(0x00); // neither
@@ -194,10 +215,27 @@ mod tests {
             opcode: Opcode::JGE,
             offset: 250,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.pc(), 50); // no cambia
     }
 
+    #[test]
+    fn test_conditional_jumps_follow_conditions() {
+        // JZ..JO saltan justo cuando se cumple su condición
+        let mut cpu = CPU::new(1024, vec![], 0, 0);
+        for (i, cond) in Condition::ALL.into_iter().enumerate() {
+            let opcode = Opcode::from_u8(Opcode::JZ as u8 + i as u8).unwrap();
+            assert_eq!(opcode.mnemonic(), format!("J{}", cond.suffix()));
+            for flags in (0..10).map(|bit| 1u32 << bit).chain([0]) {
+                cpu.regs.set_pc(40);
+                cpu.regs.set_flags(flags);
+                cpu.execute(Instruction::J { opcode, offset: 3 }).unwrap();
+                let taken = Flags::from_u32(flags).holds(cond);
+                assert_eq!(cpu.regs.pc(), if taken { 52 } else { 40 });
+            }
+        }
+    }
+
     #[test]
     fn test_call_ret() {
         let mut cpu = CPU::new(1024, vec![], 0, 0);
@@ -208,20 +246,326 @@ mod tests {
             opcode: Opcode::CALL,
             offset: 200,
         };
-        cpu.execute(call_instr);
-        assert_eq!(cpu.regs.pc(), 10 + 200);
+        cpu.execute(call_instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 10 + 200 * 4);
         assert_eq!(cpu.regs.sp(), 1020);
-        assert_eq!(cpu.mem.read32(1020), 10 + 1); // dirección siguiente guardada
+        assert_eq!(cpu.mem.read32(1020).unwrap(), 10 + 4); // dirección siguiente guardada
 
         let ret_instr = Instruction::J {
             opcode: Opcode::RET,
             offset: 0,
         };
-        cpu.execute(ret_instr);
-        assert_eq!(cpu.regs.pc(), 10 + 1);
+        cpu.execute(ret_instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 10 + 4);
+        assert_eq!(cpu.regs.sp(), 1024);
+    }
+
+    #[test]
+    fn test_push_pop_multiple() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        for reg in 1..32 {
+            cpu.regs.set(reg, reg as u32 * 0x11);
+        }
+        cpu.regs.set_lr(0xCAFE);
+        let sys = |opcode, imm| Instruction::Sys {
+            opcode,
+            rd: 0,
+            imm,
+            rs: 0,
+        };
+
+        // PUSHM {R20, R31, LR} y después PUSHM {R4-R6}
+        let high = REGLIST_HIGH | REGLIST_LR | 1 << 3 | 1 << 14;
+        cpu.execute(sys(Opcode::PUSHM, high)).unwrap();
+        cpu.execute(sys(Opcode::PUSHM, 0b111 << 3)).unwrap();
+        assert_eq!(cpu.regs.sp(), 0x400 - 6 * 4);
+        let stack: Vec<u32> = (0..6)
+            .map(|i| cpu.mem.read32(0x400 - 24 + i * 4).unwrap())
+            .collect();
+        assert_eq!(stack, [0x44, 0x55, 0x66, 20 * 0x11, 31 * 0x11, 0xCAFE]);
+
+        for reg in 1..32 {
+            cpu.regs.set(reg, 0);
+        }
+        cpu.regs.set_lr(0);
+        cpu.execute(sys(Opcode::POPM, 0b111 << 3)).unwrap();
+        cpu.execute(sys(Opcode::POPM, high)).unwrap();
+        assert_eq!(cpu.regs.sp(), 0x400);
+        assert_eq!(cpu.regs.get(5), 0x55);
+        assert_eq!(cpu.regs.get(20), 20 * 0x11);
+        assert_eq!(cpu.regs.get(31), 31 * 0x11);
+        assert_eq!(cpu.regs.lr(), 0xCAFE);
+        assert_eq!(cpu.regs.get(7), 0);
+
+        // Un fallo a mitad no mueve SP
+        cpu.regs.set_sp(0x402);
+        let fault = cpu.execute(sys(Opcode::PUSHM, 0b11 << 1));
+        assert!(matches!(fault, Err(FaultKind::Misaligned { .. })));
+        assert_eq!(cpu.regs.sp(), 0x402);
+    }
+
+    #[test]
+    fn test_enter_leave() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        cpu.regs.set(FRAME_POINTER, 0x1234);
+
+        // ENTER 16
+        cpu.execute(Instruction::Sys {
+            opcode: Opcode::ENTER,
+            rd: 0,
+            imm: 16,
+            rs: 0,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.get(FRAME_POINTER), 0x3FC);
+        assert_eq!(cpu.regs.sp(), 0x3FC - 16);
+        assert_eq!(cpu.mem.read32(0x3FC).unwrap(), 0x1234);
+
+        // Los locales se direccionan desde FP y LEAVE deshace el marco
+        cpu.regs.set_sp(0x300);
+        cpu.execute(Instruction::Sys {
+            opcode: Opcode::LEAVE,
+            rd: 0,
+            imm: 0,
+            rs: 0,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.sp(), 0x400);
+        assert_eq!(cpu.regs.get(FRAME_POINTER), 0x1234);
+    }
+
+    #[test]
+    fn test_register_indirect_jump_and_call() {
+        let mut cpu = CPU::new(1024, vec![], 0, 0);
+        cpu.regs.set_pc(10);
+        cpu.regs.set_sp(1024);
+        cpu.regs.set(5, 0x200);
+
+        // JR R5, 8
+        let update_pc = cpu
+            .execute(Instruction::Mem {
+                opcode: Opcode::JR,
+                rd: 0,
+                rs1: 5,
+                imm: 8,
+            })
+            .unwrap();
+        assert!(update_pc);
+        assert_eq!(cpu.regs.pc(), 0x208);
+
+        // CALLR R5: guarda la vuelta en la pila y en LR, como CALL
+        cpu.execute(Instruction::Mem {
+            opcode: Opcode::CALLR,
+            rd: 0,
+            rs1: 5,
+            imm: 0,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.pc(), 0x200);
+        assert_eq!(cpu.regs.sp(), 1020);
+        assert_eq!(cpu.mem.read32(1020).unwrap(), 0x208 + 4);
+        assert_eq!(cpu.regs.lr(), 0x208 + 4);
+
+        cpu.execute(Instruction::J {
+            opcode: Opcode::RET,
+            offset: 0,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.pc(), 0x208 + 4);
         assert_eq!(cpu.regs.sp(), 1024);
     }
 
+    #[test]
+    fn test_compare_and_branch() {
+        let mut cpu = CPU::new(1024, vec![], 0, 0);
+        cpu.regs.set(1, -1i32 as u32);
+        cpu.regs.set(2, 1);
+        cpu.regs.set_flags(0);
+
+        let mut branch = |opcode, rs1, rs2| {
+            cpu.regs.set_pc(0x100);
+            cpu.execute(Instruction::B {
+                opcode,
+                rs1,
+                rs2,
+                offset: -4i32 as u32,
+            })
+            .unwrap();
+            cpu.regs.pc()
+        };
+
+        // -1 < 1 con signo pero 0xFFFFFFFF > 1 sin él
+        assert_eq!(branch(Opcode::BLT, 1, 2), 0xF0);
+        assert_eq!(branch(Opcode::BGE, 1, 2), 0x100);
+        assert_eq!(branch(Opcode::BLTU, 1, 2), 0x100);
+        assert_eq!(branch(Opcode::BGEU, 1, 2), 0xF0);
+        assert_eq!(branch(Opcode::BEQ, 1, 1), 0xF0);
+        assert_eq!(branch(Opcode::BEQ, 1, 2), 0x100);
+        assert_eq!(branch(Opcode::BNE, 1, 2), 0xF0);
+        assert_eq!(branch(Opcode::BGE, 2, 2), 0xF0);
+
+        // No dejan rastro en FLAGS
+        assert_eq!(cpu.regs.flags(), 0);
+    }
+
+    #[test]
+    fn test_decrement_and_branch_loop() {
+        let program = [
+            Instruction::Sys {
+                opcode: Opcode::LI,
+                rd: 1,
+                imm: 5,
+                rs: 0,
+            },
+            Instruction::Sys {
+                opcode: Opcode::LI,
+                rd: 2,
+                imm: 0,
+                rs: 0,
+            },
+            Instruction::I {
+                opcode: Opcode::ADDI,
+                rd: 2,
+                rs1: 2,
+                imm: 3,
+            },
+            Instruction::B {
+                opcode: Opcode::DBNZ,
+                rs1: 1,
+                rs2: 0,
+                offset: -1i32 as u32,
+            },
+            Instruction::J {
+                opcode: Opcode::HALT,
+                offset: 0,
+            },
+        ];
+        let mut cpu = CPU::new(1024, rom_image(&program), 0x400, 1024);
+
+        let mut steps = 0;
+        while !cpu.halted {
+            cpu.step().unwrap();
+            steps += 1;
+        }
+        assert_eq!(cpu.regs.get(2), 15);
+        assert_eq!(cpu.regs.get(1), 0);
+        assert_eq!(steps, 2 + 5 * 2 + 1);
+    }
+
+    #[test]
+    fn test_pc_relative_load_is_position_independent() {
+        let program = [
+            // ADDPC R1, +3 y LDPC R2, +2: ambos apuntan a la palabra 3
+            Instruction::Sys {
+                opcode: Opcode::ADDPC,
+                rd: 1,
+                imm: 3,
+                rs: 0,
+            },
+            Instruction::Sys {
+                opcode: Opcode::LDPC,
+                rd: 2,
+                imm: 2,
+                rs: 0,
+            },
+            Instruction::J {
+                opcode: Opcode::HALT,
+                offset: 0,
+            },
+        ];
+        let mut rom = rom_image(&program);
+        rom.extend_from_slice(&0xDEAD_BEEFu32.to_le_bytes());
+
+        // La misma imagen con la ROM en dos bases distintas
+        for base in [0x400, 0x800] {
+            let mut cpu = CPU::new(base as usize, rom.clone(), base, base);
+            while !cpu.halted {
+                cpu.step().unwrap();
+            }
+            assert_eq!(cpu.regs.get(1), base + 12);
+            assert_eq!(cpu.regs.get(2), 0xDEAD_BEEF);
+        }
+    }
+
+    #[test]
+    fn test_conditional_move() {
+        let mut cpu = CPU::new(1024, vec![], 0, 0);
+        cpu.regs.set(1, 10);
+        cpu.regs.set(2, 20);
+        let cmov = |cpu: &mut CPU, opcode| {
+            cpu.regs.set(3, 0);
+            cpu.execute(Instruction::Sys {
+                opcode,
+                rd: 3,
+                imm: 0,
+                rs: 1,
+            })
+            .unwrap();
+            cpu.regs.get(3)
+        };
+
+        // CMP R1, R2: 10 < 20
+        cpu.execute(Instruction::R {
+            opcode: Opcode::CMP,
+            rd: 1,
+            rs1: 2,
+            rs2: 0,
+        })
+        .unwrap();
+        let flags = cpu.regs.flags();
+        assert_eq!(cmov(&mut cpu, Opcode::CMOVLT), 10);
+        assert_eq!(cmov(&mut cpu, Opcode::CMOVLE), 10);
+        assert_eq!(cmov(&mut cpu, Opcode::CMOVNE), 10);
+        assert_eq!(cmov(&mut cpu, Opcode::CMOVGE), 0);
+        assert_eq!(cmov(&mut cpu, Opcode::CMOVEQ), 0);
+        assert_eq!(cmov(&mut cpu, Opcode::CMOVGT), 0);
+        assert_eq!(cpu.regs.flags(), flags);
+    }
+
+    #[test]
+    fn test_select() {
+        let mut cpu = CPU::new(1024, vec![], 0, 0);
+        cpu.regs.set(1, 10);
+        cpu.regs.set(2, 20);
+        let sel = |cpu: &mut CPU, cond: Condition| {
+            cpu.execute(Instruction::RC {
+                opcode: Opcode::SEL,
+                rd: 3,
+                rs1: 1,
+                rs2: 2,
+                cond: cond as u8,
+            })
+            .unwrap();
+            cpu.regs.get(3)
+        };
+
+        // min(R1, R2) sin saltos
+        cpu.execute(Instruction::R {
+            opcode: Opcode::CMP,
+            rd: 1,
+            rs1: 2,
+            rs2: 0,
+        })
+        .unwrap();
+        assert_eq!(sel(&mut cpu, Condition::Less), 10);
+        assert_eq!(sel(&mut cpu, Condition::Greater), 20);
+
+        cpu.regs.set_flags(0x02);
+        assert_eq!(sel(&mut cpu, Condition::Carry), 10);
+        assert_eq!(sel(&mut cpu, Condition::Zero), 20);
+        assert_eq!(sel(&mut cpu, Condition::NotZero), 10);
+
+        // Código de condición fuera de rango
+        let bad = cpu.execute(Instruction::RC {
+            opcode: Opcode::SEL,
+            rd: 3,
+            rs1: 1,
+            rs2: 2,
+            cond: 12,
+        });
+        assert_eq!(bad, Err(FaultKind::IllegalOpcode));
+    }
+
     #[test]
     fn test_halt() {
         let mut cpu = CPU::new(1024, vec![], 0, 0);
@@ -229,7 +573,7 @@ mod tests {
             opcode: Opcode::HALT,
             offset: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert!(cpu.halted);
     }
 
@@ -237,23 +581,23 @@ mod tests {
     fn test_jmp_signed_offset() {
         let mut cpu = CPU::new(1024, vec![], 0, 0);
 
-        // PC inicial 100, offset positivo
-        cpu.regs.set_pc(100);
+        // PC inicial 400, offset positivo
+        cpu.regs.set_pc(400);
         let instr = Instruction::J {
             opcode: Opcode::JMP,
             offset: 50,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.regs.pc(), 150);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 600);
 
-        // PC inicial 100, offset negativo
-        cpu.regs.set_pc(100);
+        // PC inicial 400, offset negativo
+        cpu.regs.set_pc(400);
         let instr = Instruction::J {
             opcode: Opcode::JMP,
             offset: -30i32 as u32,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.regs.pc(), 70);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 280);
     }
 
     #[test]
@@ -267,18 +611,18 @@ mod tests {
             opcode: Opcode::JZ,
             offset: 25,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.regs.pc(), 225);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 300);
 
         // JZ negativo
-        cpu.regs.set_pc(200);
+        cpu.regs.set_pc(400);
         cpu.regs.set_flags(0x01); // zero
         let instr = Instruction::J {
             opcode: Opcode::JZ,
             offset: -50i32 as u32,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.regs.pc(), 150);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 200);
 
         // JNZ positivo
         cpu.regs.set_pc(300);
@@ -287,17 +631,17 @@ mod tests {
             opcode: Opcode::JNZ,
             offset: 40,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.regs.pc(), 340);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.regs.pc(), 460);
 
         // JNZ negativo
-        cpu.regs.set_pc(300);
+        cpu.regs.set_pc(600);
         cpu.regs.set_flags(0x00); // not zero
         let instr = Instruction::J {
             opcode: Opcode::JNZ,
             offset: -100i32 as u32,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.pc(), 200);
     }
 
@@ -313,8 +657,8 @@ mod tests {
             rs1: 1,
             imm: 0,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.mem.read8(0), 0xAB);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.mem.read8(0).unwrap(), 0xAB);
 
         cpu.regs.set(3, 0);
         let instr = Instruction::Mem {
@@ -323,7 +667,7 @@ mod tests {
             rs1: 1,
             imm: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(3), 0xFFFFFFAB);
 
         let instr = Instruction::Mem {
@@ -332,7 +676,7 @@ mod tests {
             rs1: 1,
             imm: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(3), 0xAB);
     }
 
@@ -348,8 +692,8 @@ mod tests {
             rs1: 1,
             imm: 0,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.mem.read16(0), 0xABCD);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.mem.read16(0).unwrap(), 0xABCD);
 
         cpu.regs.set(3, 0);
         let instr = Instruction::Mem {
@@ -358,7 +702,7 @@ mod tests {
             rs1: 1,
             imm: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(3), 0xFFFFABCD);
 
         let instr = Instruction::Mem {
@@ -367,7 +711,7 @@ mod tests {
             rs1: 1,
             imm: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(3), 0xABCD);
     }
 
@@ -383,8 +727,8 @@ mod tests {
             rs1: 1,
             imm: 0,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.mem.read32(0), 0x12345678);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.mem.read32(0).unwrap(), 0x12345678);
 
         cpu.regs.set(3, 0);
         let instr = Instruction::Mem {
@@ -393,7 +737,7 @@ mod tests {
             rs1: 1,
             imm: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(3), 0x12345678);
     }
 
@@ -409,8 +753,8 @@ mod tests {
             rs1: 1,
             imm: 0,
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.mem.read32(0), 0xDEADBEEF);
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.mem.read32(0).unwrap(), 0xDEADBEEF);
 
         cpu.regs.set(3, 0);
         let instr = Instruction::Mem {
@@ -419,7 +763,7 @@ mod tests {
             rs1: 1,
             imm: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(3), 0xDEADBEEF);
     }
 
@@ -429,19 +773,31 @@ mod tests {
 
         let instr_li = Instruction::Sys {
             opcode: Opcode::LI,
-            rd: 0,
+            rd: 3,
             imm: 0x1234,
+            rs: 0,
         };
-        cpu.execute(instr_li);
-        assert_eq!(cpu.regs.get(0), 0x1234);
+        cpu.execute(instr_li).unwrap();
+        assert_eq!(cpu.regs.get(3), 0x1234);
 
         let instr_lui = Instruction::Sys {
             opcode: Opcode::LUI,
             rd: 1,
             imm: 0x5678,
+            rs: 0,
         };
-        cpu.execute(instr_lui);
+        cpu.execute(instr_lui).unwrap();
         assert_eq!(cpu.regs.get(1), 0x56780000);
+
+        // Un inmediato negativo llena la mitad baja
+        let instr_li = Instruction::Sys {
+            opcode: Opcode::LI,
+            rd: 1,
+            imm: -1i32 as u32,
+            rs: 0,
+        };
+        cpu.execute(instr_li).unwrap();
+        assert_eq!(cpu.regs.get(1), 0x5678FFFF);
     }
 
     #[test]
@@ -451,41 +807,45 @@ mod tests {
 
         let instr_mov = Instruction::Sys {
             opcode: Opcode::MOV,
-            rd: 0,
-            imm: 2,
+            rd: 3,
+            imm: 0,
+            rs: 2,
         };
-        cpu.execute(instr_mov);
-        assert_eq!(cpu.regs.get(0), 0x42);
+        cpu.execute(instr_mov).unwrap();
+        assert_eq!(cpu.regs.get(3), 0x42);
 
         cpu.regs.set_pc(0x100);
         let instr_movpc = Instruction::Sys {
             opcode: Opcode::MOVPC,
             rd: 1,
             imm: 0,
+            rs: 0,
         };
-        cpu.execute(instr_movpc);
+        cpu.execute(instr_movpc).unwrap();
         assert_eq!(cpu.regs.get(1), 0x100);
     }
 
     #[test]
     fn test_sys_mtsr_mfsr() {
         let mut cpu = CPU::new(0, vec![], 0, 0);
-        cpu.regs.set(0, 0xABCD);
+        cpu.regs.set(3, 0xABCD);
 
         let instr_mtsr = Instruction::Sys {
             opcode: Opcode::MTSR,
-            rd: 0,
+            rd: 3,
             imm: 0,
+            rs: 0,
         };
-        cpu.execute(instr_mtsr);
+        cpu.execute(instr_mtsr).unwrap();
         assert_eq!(cpu.regs.flags(), 0xABCD);
 
         let instr_mfsr = Instruction::Sys {
             opcode: Opcode::MFSR,
             rd: 1,
             imm: 0,
+            rs: 0,
         };
-        cpu.execute(instr_mfsr);
+        cpu.execute(instr_mfsr).unwrap();
         assert_eq!(cpu.regs.get(1), 0xABCD);
     }
 
@@ -496,19 +856,21 @@ mod tests {
 
         let instr_movsp = Instruction::Sys {
             opcode: Opcode::MOVSP,
-            rd: 0,
+            rd: 3,
             imm: 0,
+            rs: 0,
         };
-        cpu.execute(instr_movsp);
-        assert_eq!(cpu.regs.get(0), 0x200);
+        cpu.execute(instr_movsp).unwrap();
+        assert_eq!(cpu.regs.get(3), 0x200);
 
-        cpu.regs.set(0, 0x300);
+        cpu.regs.set(3, 0x300);
         let instr_setsp = Instruction::Sys {
             opcode: Opcode::SETSP,
-            rd: 0,
+            rd: 3,
             imm: 0,
+            rs: 0,
         };
-        cpu.execute(instr_setsp);
+        cpu.execute(instr_setsp).unwrap();
         assert_eq!(cpu.regs.sp(), 0x300);
     }
 
@@ -526,7 +888,7 @@ mod tests {
             rs1: 0,
             rs2: 1,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.fregs[2], 4.0);
 
         // FSUB
@@ -536,7 +898,7 @@ mod tests {
             rs1: 1,
             rs2: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.fregs[2], 1.0);
 
         // FMUL
@@ -546,7 +908,7 @@ mod tests {
             rs1: 0,
             rs2: 1,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.fregs[2], 3.75);
 
         // FDIV
@@ -556,7 +918,7 @@ mod tests {
             rs1: 1,
             rs2: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.fregs[2], 2.5 / 1.5);
     }
 
@@ -575,7 +937,7 @@ mod tests {
             rs1: 0,
             rs2: 1,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         let flags = Flags::from_u32(cpu.regs.flags());
         assert!(flags.zero && !flags.less && !flags.greater);
 
@@ -586,7 +948,7 @@ mod tests {
             rs1: 0,
             rs2: 1,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(3), 1);
 
         // FLT
@@ -596,7 +958,7 @@ mod tests {
             rs1: 0,
             rs2: 2,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(3), 1);
 
         // FGT
@@ -606,7 +968,7 @@ mod tests {
             rs1: 2,
             rs2: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(3), 1);
     }
 
@@ -615,7 +977,7 @@ mod tests {
         let mut cpu = CPU::new(1024, vec![], 0x1000, 0x0);
 
         cpu.regs.fregs[0] = 3.5;
-        cpu.regs.set(0, 7);
+        cpu.regs.set(3, 7);
 
         // FTOI
         let instr = Instruction::FP {
@@ -624,17 +986,17 @@ mod tests {
             rs1: 0,
             rs2: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.get(1), 3);
 
         // ITOF
         let instr = Instruction::FP {
             opcode: Opcode::ITOF,
             rd: 1,
-            rs1: 0,
+            rs1: 3,
             rs2: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.fregs[1], 7.0);
 
         // FMOV
@@ -644,10 +1006,392 @@ mod tests {
             rs1: 1,
             rs2: 0,
         };
-        cpu.execute(instr);
+        cpu.execute(instr).unwrap();
         assert_eq!(cpu.regs.fregs[2], 7.0);
     }
 
+    #[test]
+    fn test_indexed_load_store() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        cpu.regs.set(2, 0x100);
+        cpu.regs.set(3, 5);
+        let mem = |opcode, rd, mode: AddrMode| Instruction::MemX {
+            opcode,
+            rd,
+            rs1: 2,
+            rs2: 3,
+            mode: mode.bits(),
+        };
+
+        // STW R1, [R2 + R3*4]
+        cpu.regs.set(1, 0xDEAD_BEEF);
+        cpu.execute(mem(Opcode::STWX, 1, AddrMode::Indexed { scale: 2 }))
+            .unwrap();
+        assert_eq!(cpu.mem.read32(0x114).unwrap(), 0xDEAD_BEEF);
+
+        // LDB R4, [R2 + R3*4]: el byte bajo 0xEF con signo
+        cpu.execute(mem(Opcode::LDBX, 4, AddrMode::Indexed { scale: 2 }))
+            .unwrap();
+        assert_eq!(cpu.regs.get(4), 0xFFFF_FFEF);
+        cpu.execute(mem(Opcode::LDHUX, 4, AddrMode::Indexed { scale: 2 }))
+            .unwrap();
+        assert_eq!(cpu.regs.get(4), 0xBEEF);
+
+        // LDBU R4, [R2 + R3]: sin escala
+        cpu.mem.write8(0x105, 0x80).unwrap();
+        cpu.execute(mem(Opcode::LDBUX, 4, AddrMode::Indexed { scale: 0 }))
+            .unwrap();
+        assert_eq!(cpu.regs.get(4), 0x80);
+        assert_eq!(cpu.regs.get(2), 0x100);
+
+        // Modo 3 sin asignar
+        let bad = cpu.execute(Instruction::MemX {
+            opcode: Opcode::LDWX,
+            rd: 4,
+            rs1: 2,
+            rs2: 3,
+            mode: 0xC,
+        });
+        assert_eq!(bad, Err(FaultKind::IllegalOpcode));
+    }
+
+    #[test]
+    fn test_post_increment_pre_decrement() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        let mem = |opcode, rd, mode: AddrMode| Instruction::MemX {
+            opcode,
+            rd,
+            rs1: 2,
+            rs2: 0,
+            mode: mode.bits(),
+        };
+
+        // STH R1, -[R2] dos veces y LDHU R3, [R2]+ para recorrerlas
+        cpu.regs.set(2, 0x104);
+        for value in [0x1111, 0x2222] {
+            cpu.regs.set(1, value);
+            cpu.execute(mem(Opcode::STHX, 1, AddrMode::PreDecrement))
+                .unwrap();
+        }
+        assert_eq!(cpu.regs.get(2), 0x100);
+        assert_eq!(cpu.mem.read32(0x100).unwrap(), 0x1111_2222);
+
+        cpu.execute(mem(Opcode::LDHUX, 3, AddrMode::PostIncrement))
+            .unwrap();
+        assert_eq!((cpu.regs.get(3), cpu.regs.get(2)), (0x2222, 0x102));
+        cpu.execute(mem(Opcode::LDHUX, 3, AddrMode::PostIncrement))
+            .unwrap();
+        assert_eq!((cpu.regs.get(3), cpu.regs.get(2)), (0x1111, 0x104));
+
+        // El paso es el tamaño del acceso
+        cpu.execute(mem(Opcode::LDWX, 3, AddrMode::PostIncrement))
+            .unwrap();
+        assert_eq!(cpu.regs.get(2), 0x108);
+        cpu.execute(mem(Opcode::STBX, 3, AddrMode::PreDecrement))
+            .unwrap();
+        assert_eq!(cpu.regs.get(2), 0x107);
+
+        // Si el acceso falla, la base no cambia
+        cpu.regs.set(2, 0x101);
+        let fault = cpu.execute(mem(Opcode::LDWX, 3, AddrMode::PostIncrement));
+        assert_eq!(fault, Err(FaultKind::Misaligned { addr: 0x101 }));
+        assert_eq!(cpu.regs.get(2), 0x101);
+
+        // Con rd == base gana el valor cargado
+        cpu.regs.set(2, 0x100);
+        cpu.execute(Instruction::MemX {
+            opcode: Opcode::LDWX,
+            rd: 2,
+            rs1: 2,
+            rs2: 0,
+            mode: AddrMode::PostIncrement.bits(),
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.get(2), 0x1111_2222);
+    }
+
+    #[test]
+    fn test_block_copy_is_interruptible() {
+        // MEMCPY R1, R2, R3 en 0x100 y un manejador en 0x200 que solo hace RETI
+        let mut cpu = CPU::new(0x4000, vec![], 0x400, 0x100);
+        cpu.mem.write32(4, 0x200).unwrap();
+        cpu.pic.borrow_mut().set_mask(0b10);
+        let memcpy = Instruction::R {
+            opcode: Opcode::MEMCPY,
+            rd: 1,
+            rs1: 2,
+            rs2: 3,
+        };
+        cpu.mem.write32(0x100, memcpy.encode()).unwrap();
+        cpu.mem.write32(0x200, (Opcode::RETI as u32) << 24).unwrap();
+        for i in 0..0x300 {
+            cpu.mem.write8(0x1F00 + i, (i * 7) as u8).unwrap();
+        }
+        cpu.regs.set(1, 0x3000);
+        cpu.regs.set(2, 0x1F00);
+        cpu.regs.set(3, 0x300);
+        cpu.regs.set_flags(0x400);
+
+        // El primer tramo acaba en la página del origen y la PC no avanza
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc(), 0x100);
+        assert_eq!(
+            (cpu.regs.get(1), cpu.regs.get(2), cpu.regs.get(3)),
+            (0x3100, 0x2000, 0x200)
+        );
+
+        // Entre tramos se atiende la interrupción y al volver sigue la copia
+        cpu.irq_line(1).raise();
+        assert!(matches!(cpu.step(), Ok(StepOutcome::Interrupt(1))));
+        cpu.step().unwrap(); // RETI
+        assert_eq!(cpu.regs.pc(), 0x100);
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc(), 0x104);
+        assert_eq!(cpu.regs.get(3), 0);
+        for i in 0..0x300 {
+            assert_eq!(cpu.mem.read8(0x3000 + i).unwrap(), (i * 7) as u8);
+        }
+    }
+
+    #[test]
+    fn test_block_fill_and_compare() {
+        let mut cpu = CPU::new(0x1000, vec![], 0x400, 0);
+        let r = |opcode, rd, rs1, rs2| Instruction::R {
+            opcode,
+            rd,
+            rs1,
+            rs2,
+        };
+
+        // MEMSET solo usa el byte bajo del valor e invalida el código
+        let version = cpu.mem.code_version(0x200);
+        cpu.regs.set(1, 0x200);
+        cpu.regs.set(2, 0x12AB);
+        cpu.regs.set(3, 0x100);
+        assert_eq!(cpu.execute(r(Opcode::MEMSET, 1, 2, 3)), Ok(false));
+        assert_eq!((cpu.regs.get(1), cpu.regs.get(2)), (0x300, 0x12AB));
+        assert_eq!(cpu.mem.read32(0x2FC).unwrap(), 0xABAB_ABAB);
+        assert_eq!(cpu.mem.read8(0x300).unwrap(), 0);
+        assert_ne!(cpu.mem.code_version(0x200), version);
+
+        // Con el destino justo detrás del origen el byte se repite, como en
+        // una copia byte a byte
+        cpu.mem.write8(0x600, 0x5A).unwrap();
+        cpu.regs.set(1, 0x601);
+        cpu.regs.set(2, 0x600);
+        cpu.regs.set(3, 8);
+        cpu.execute(r(Opcode::MEMCPY, 1, 2, 3)).unwrap();
+        assert_eq!(cpu.mem.read32(0x604).unwrap(), 0x5A5A_5A5A);
+        assert_eq!(cpu.mem.read8(0x608).unwrap(), 0x5A);
+
+        // MEMCMP se para en la primera diferencia con los flags de UCMP
+        cpu.regs.set(1, 0x500);
+        cpu.regs.set(2, 0x200);
+        cpu.regs.set(3, 0x100);
+        cpu.execute(r(Opcode::MEMCPY, 1, 2, 3)).unwrap();
+        cpu.mem.write8(0x540, 0x10).unwrap();
+        cpu.regs.set(1, 0x200);
+        cpu.regs.set(2, 0x500);
+        cpu.regs.set(3, 0x100);
+        assert_eq!(cpu.execute(r(Opcode::MEMCMP, 1, 2, 3)), Ok(false));
+        assert_eq!(
+            (cpu.regs.get(1), cpu.regs.get(2), cpu.regs.get(3)),
+            (0x240, 0x540, 0xC0)
+        );
+        let flags = Flags::from_u32(cpu.regs.flags());
+        assert!(flags.greater && !flags.equal);
+
+        // Sin diferencias, o sin bytes, quedan los flags de igualdad
+        cpu.mem.write8(0x540, 0xAB).unwrap();
+        cpu.execute(r(Opcode::MEMCMP, 1, 2, 3)).unwrap();
+        assert_eq!(cpu.regs.get(3), 0);
+        assert!(Flags::from_u32(cpu.regs.flags()).equal);
+        cpu.regs.set_flags(0);
+        cpu.execute(r(Opcode::MEMCMP, 1, 2, 3)).unwrap();
+        assert!(Flags::from_u32(cpu.regs.flags()).equal);
+    }
+
+    #[test]
+    fn test_block_restarts_after_fault() {
+        let mut cpu = CPU::new(0x4000, vec![], 0x400, 0);
+        let memset = Instruction::R {
+            opcode: Opcode::MEMSET,
+            rd: 1,
+            rs1: 2,
+            rs2: 3,
+        };
+        cpu.regs.set(1, 0x3F00);
+        cpu.regs.set(2, 0xEE);
+        cpu.regs.set(3, 0x200);
+
+        // El tramo que cabe en RAM se completa; el siguiente falla sin tocar
+        // los registros, que siguen apuntando al byte pendiente
+        assert_eq!(cpu.execute(memset), Ok(true));
+        assert_eq!(
+            cpu.execute(memset),
+            Err(FaultKind::BusError { addr: 0x4000 })
+        );
+        assert_eq!((cpu.regs.get(1), cpu.regs.get(3)), (0x4000, 0x100));
+        assert_eq!(cpu.mem.read8(0x3FFF).unwrap(), 0xEE);
+    }
+
+    #[test]
+    fn test_block_copy_keeps_progress_on_fault() {
+        // La RAM acaba a mitad de página: la copia solapada falla a mitad de
+        // tramo y los registros quedan tras el último byte copiado
+        let mut cpu = CPU::new(0x3F80, vec![], 0x400, 0);
+        cpu.mem.write8(0x3F00, 0x5A).unwrap();
+        cpu.regs.set(1, 0x3F01);
+        cpu.regs.set(2, 0x3F00);
+        cpu.regs.set(3, 0x100);
+        let memcpy = Instruction::R {
+            opcode: Opcode::MEMCPY,
+            rd: 1,
+            rs1: 2,
+            rs2: 3,
+        };
+        assert_eq!(
+            cpu.execute(memcpy),
+            Err(FaultKind::BusError { addr: 0x3F80 })
+        );
+        assert_eq!(
+            (cpu.regs.get(1), cpu.regs.get(2), cpu.regs.get(3)),
+            (0x3F80, 0x3F7F, 0x81)
+        );
+        assert_eq!(cpu.mem.read32(0x3F7C).unwrap(), 0x5A5A_5A5A);
+    }
+
+    #[test]
+    fn test_block_needs_distinct_registers() {
+        let mut cpu = CPU::new(0x1000, vec![], 0x400, 0);
+        cpu.regs.set(1, 0x200);
+        cpu.regs.set(2, 0x300);
+        cpu.regs.set(3, 0x10);
+        for (opcode, rd, rs1, rs2) in [
+            (Opcode::MEMCPY, 1, 1, 3),
+            (Opcode::MEMSET, 1, 2, 1),
+            (Opcode::MEMCMP, 1, 2, 2),
+        ] {
+            let instr = Instruction::R {
+                opcode,
+                rd,
+                rs1,
+                rs2,
+            };
+            assert_eq!(cpu.execute(instr), Err(FaultKind::IllegalOpcode));
+        }
+        assert_eq!(
+            (cpu.regs.get(1), cpu.regs.get(2), cpu.regs.get(3)),
+            (0x200, 0x300, 0x10)
+        );
+    }
+
+    // La instrucción PACKED de `mnemonic` con rd = R1, rs1 = R2 y rs2 = R3
+    fn packed_instr(mnemonic: &str) -> Instruction {
+        let (op, lanes) = PackedOp::from_mnemonic(mnemonic).unwrap();
+        Instruction::RP {
+            opcode: Opcode::PACKED,
+            rd: 1,
+            rs1: 2,
+            rs2: 3,
+            funct: op.funct(lanes),
+        }
+    }
+
+    #[test]
+    fn test_packed_add_sub() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        cpu.regs.set(2, 0xFF80_7F10);
+        cpu.regs.set(3, 0x0180_0120);
+        cpu.regs.set_flags(0x01);
+        let mut packed = |mnemonic| {
+            cpu.execute(packed_instr(mnemonic)).unwrap();
+            cpu.regs.get(1)
+        };
+
+        // Sin acarreo entre carriles
+        assert_eq!(packed("PADDB"), 0x0000_8030);
+        assert_eq!(packed("PADDUSB"), 0xFFFF_8030);
+        assert_eq!(packed("PADDSB"), 0x0080_7F30);
+        assert_eq!(packed("PSUBB"), 0xFE00_7EF0);
+        assert_eq!(packed("PSUBUSB"), 0xFE00_7E00);
+        assert_eq!(packed("PSUBSB"), 0xFE00_7EF0);
+        assert_eq!(packed("PADDH"), 0x0100_8030);
+        assert_eq!(packed("PADDUSH"), 0xFFFF_8030);
+        assert_eq!(packed("PADDSH"), 0x0100_7FFF);
+        assert_eq!(packed("PSUBUSH"), 0xFE00_7DF0);
+        assert_eq!(packed("PSUBSH"), 0xFE00_7DF0);
+
+        // Los flags no cambian
+        assert_eq!(cpu.regs.flags(), 0x01);
+    }
+
+    #[test]
+    fn test_packed_min_max_average_multiply() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        cpu.regs.set(2, 0x10FF_8000);
+        cpu.regs.set(3, 0x2001_80FF);
+        let mut packed = |mnemonic| {
+            cpu.execute(packed_instr(mnemonic)).unwrap();
+            cpu.regs.get(1)
+        };
+
+        assert_eq!(packed("PMINUB"), 0x1001_8000);
+        assert_eq!(packed("PMAXUB"), 0x20FF_80FF);
+        assert_eq!(packed("PMINUH"), 0x10FF_8000);
+        assert_eq!(packed("PMAXUH"), 0x2001_80FF);
+        assert_eq!(packed("PAVGB"), 0x1880_8080);
+        assert_eq!(packed("PAVGH"), 0x1880_8080);
+        assert_eq!(packed("PMULHUB"), 0x0200_4000);
+        assert_eq!(packed("PMULHUH"), 0x021F_407F);
+    }
+
+    #[test]
+    fn test_packed_shuffle() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        let pshufb = packed_instr("PSHUFB");
+
+        // 0xAARRGGBB -> 0x00BBGGRR: cambia R y B y pone A a cero
+        cpu.regs.set(2, 0x1122_3344);
+        cpu.regs.set(3, 0x8000_0102);
+        cpu.execute(pshufb).unwrap();
+        assert_eq!(cpu.regs.get(1), 0x0044_3322);
+
+        // Repetir el canal azul en todos los bytes
+        cpu.regs.set(3, 0);
+        cpu.execute(pshufb).unwrap();
+        assert_eq!(cpu.regs.get(1), 0x4444_4444);
+    }
+
+    #[test]
+    fn test_packed_funct() {
+        // Cada combinación tiene su `funct` y sobrevive a codificar y decodificar
+        assert_eq!(PackedOp::variants().count(), 21);
+        for (op, lanes) in PackedOp::variants() {
+            let instr = packed_instr(&op.mnemonic(lanes));
+            assert_eq!(Instruction::decode(instr.encode()), Ok(instr));
+            assert_eq!(PackedOp::from_funct(op.funct(lanes)), Some((op, lanes)));
+        }
+        assert_eq!(packed_instr("PADDH").encode() & 0x1F, 0x10);
+
+        // Operaciones que no existen: un índice libre y PSHUF sobre medias palabras
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        for funct in [11, 15, 0x1A] {
+            let instr = Instruction::RP {
+                opcode: Opcode::PACKED,
+                rd: 1,
+                rs1: 2,
+                rs2: 3,
+                funct,
+            };
+            assert_eq!(cpu.execute(instr), Err(FaultKind::IllegalOpcode));
+        }
+
+        // PMULHU cuesta lo que MULHU
+        assert_eq!(program_cycles(&mut cpu, &[packed_instr("PMULHUH")]), 3);
+        assert_eq!(program_cycles(&mut cpu, &[packed_instr("PAVGB")]), 1);
+    }
+
     #[test]
     fn test_fp_memory_load_store() {
         let mut cpu = CPU::new(1024, vec![], 0x1000, 0x0);
@@ -655,27 +1399,444 @@ mod tests {
         cpu.regs.fregs[0] = 5.5;
         cpu.regs.set(1, 100);
 
-        // FST
-        let instr = Instruction::FP {
+        // FST F0, [R1, 8]
+        let instr = Instruction::Mem {
             opcode: Opcode::FST,
             rd: 0,
             rs1: 1,
-            rs2: 0,
+            imm: 8,
         };
-        cpu.execute(instr);
-        let bits = cpu.mem.read32(100);
+        cpu.execute(instr).unwrap();
+        let bits = cpu.mem.read32(108).unwrap();
         assert_eq!(f32::from_bits(bits), 5.5);
 
-        // FLD
-        cpu.regs.fregs[0] = 0.0;
-        let instr = Instruction::FP {
+        // FLD F2, [R1, 8]
+        let instr = Instruction::Mem {
             opcode: Opcode::FLD,
-            rd: 0,
+            rd: 2,
             rs1: 1,
-            rs2: 0,
+            imm: 8,
+        };
+        cpu.execute(instr).unwrap();
+        assert_eq!(cpu.regs.fregs[2], 5.5);
+
+        // decodificada desde memoria usa también el desplazamiento
+        let raw = Instruction::Mem {
+            opcode: Opcode::FLD,
+            rd: 3,
+            rs1: 1,
+            imm: -4i32 as u32,
+        }
+        .encode();
+        cpu.mem.write32(96, 2.25f32.to_bits()).unwrap();
+        cpu.execute(Instruction::decode(raw).unwrap()).unwrap();
+        assert_eq!(cpu.regs.fregs[3], 2.25);
+    }
+
+    fn fp(cpu: &mut CPU, opcode: Opcode, rd: u8, rs1: u8, rs2: u8) {
+        cpu.execute(Instruction::FP {
+            opcode,
+            rd,
+            rs1,
+            rs2,
+        })
+        .unwrap();
+    }
+
+    // Ejecuta `opcode F2, F0, F1` con el modo de redondeo dado y devuelve el
+    // resultado y las excepciones que levantó
+    fn fp_rounded(cpu: &mut CPU, opcode: Opcode, a: f32, b: f32, rounding: Rounding) -> (f32, u32) {
+        cpu.regs.fregs[0] = a;
+        cpu.regs.fregs[1] = b;
+        cpu.regs.set_fcsr(rounding.to_fcsr());
+        fp(cpu, opcode, 2, 0, 1);
+        (cpu.regs.fregs[2], cpu.regs.fcsr() & FCSR_FLAGS)
+    }
+
+    #[test]
+    fn test_fp_compare_sets_relational_flags() {
+        let mut cpu = CPU::new(1024, vec![], 0x1000, 0x0);
+        let mut fcmp = |a: f32, b: f32| {
+            cpu.regs.fregs[0] = a;
+            cpu.regs.fregs[1] = b;
+            fp(&mut cpu, Opcode::FCMP, 0, 0, 1);
+            Flags::from_u32(cpu.regs.flags())
+        };
+
+        let f = fcmp(1.0, 2.0);
+        assert!(f.less && f.less_equal && f.not_equal && !f.equal && !f.overflow);
+        let f = fcmp(-0.0, 0.0);
+        assert!(f.equal && f.zero && f.less_equal && f.greater_equal && !f.not_equal);
+        let f = fcmp(3.0, -1.0);
+        assert!(f.greater && f.greater_equal && !f.less_equal);
+
+        // desordenada: solo not_equal, con overflow marcando el NaN
+        let f = fcmp(f32::NAN, 1.0);
+        assert!(f.not_equal && f.overflow);
+        assert!(!f.equal && !f.less && !f.greater && !f.less_equal && !f.greater_equal);
+        assert_eq!(cpu.regs.fcsr() & FCSR_INVALID, FCSR_INVALID);
+
+        // FEQ es silenciosa con NaN silenciosos; FLT no
+        cpu.regs.set_fcsr(0);
+        cpu.regs.fregs[0] = f32::NAN;
+        fp(&mut cpu, Opcode::FEQ, 3, 0, 0);
+        assert_eq!(cpu.regs.get(3), 0);
+        assert_eq!(cpu.regs.fcsr(), 0);
+        fp(&mut cpu, Opcode::FLT, 3, 0, 0);
+        assert_eq!(cpu.regs.fcsr(), FCSR_INVALID);
+        // un NaN señalizador levanta invalid también en FEQ
+        cpu.regs.set_fcsr(0);
+        cpu.regs.fregs[0] = f32::from_bits(0x7F80_0001);
+        fp(&mut cpu, Opcode::FEQ, 3, 0, 0);
+        assert_eq!(cpu.regs.fcsr(), FCSR_INVALID);
+    }
+
+    #[test]
+    fn test_fp_rounding_modes() {
+        let mut cpu = CPU::new(1024, vec![], 0x1000, 0x0);
+        let third = |rounding| {
+            fp_rounded(
+                &mut CPU::new(64, vec![], 0, 0),
+                Opcode::FDIV,
+                1.0,
+                3.0,
+                rounding,
+            )
+        };
+
+        let (nearest, flags) = third(Rounding::NearestEven);
+        assert_eq!(nearest, 1.0 / 3.0);
+        assert_eq!(flags, FCSR_INEXACT);
+        let (down, _) = third(Rounding::Down);
+        let (up, _) = third(Rounding::Up);
+        let (zero, _) = third(Rounding::TowardZero);
+        assert_eq!(up.to_bits() - down.to_bits(), 1);
+        assert!((down as f64) < 1.0 / 3.0 && (up as f64) > 1.0 / 3.0);
+        assert_eq!(zero, down);
+
+        // -1/3 hacia cero se acerca a cero, es decir, sube
+        let (neg, _) = fp_rounded(&mut cpu, Opcode::FDIV, -1.0, 3.0, Rounding::TowardZero);
+        assert_eq!(neg, -down);
+
+        // 1 + 2^-30 no cabe: solo hacia +inf cambia el resultado
+        let tiny = 2f32.powi(-30);
+        let (r, flags) = fp_rounded(&mut cpu, Opcode::FADD, 1.0, tiny, Rounding::NearestEven);
+        assert_eq!((r, flags), (1.0, FCSR_INEXACT));
+        let (r, _) = fp_rounded(&mut cpu, Opcode::FADD, 1.0, tiny, Rounding::Up);
+        assert_eq!(r, 1.0 + f32::EPSILON);
+        let (r, _) = fp_rounded(&mut cpu, Opcode::FSUB, 1.0, tiny, Rounding::Down);
+        assert_eq!(r, 1.0 - f32::EPSILON / 2.0);
+        let (r, flags) = fp_rounded(&mut cpu, Opcode::FMUL, 3.0, 0.5, Rounding::Up);
+        assert_eq!((r, flags), (1.5, 0));
+
+        // desbordamiento: infinito salvo en los modos que lo evitan
+        let (r, flags) = fp_rounded(&mut cpu, Opcode::FMUL, f32::MAX, 2.0, Rounding::NearestEven);
+        assert_eq!(r, f32::INFINITY);
+        assert_eq!(flags, FCSR_OVERFLOW | FCSR_INEXACT);
+        let (r, _) = fp_rounded(&mut cpu, Opcode::FMUL, f32::MAX, 2.0, Rounding::TowardZero);
+        assert_eq!(r, f32::MAX);
+        let (r, _) = fp_rounded(&mut cpu, Opcode::FMUL, -f32::MAX, 2.0, Rounding::Up);
+        assert_eq!(r, -f32::MAX);
+        let (r, _) = fp_rounded(&mut cpu, Opcode::FMUL, -f32::MAX, 2.0, Rounding::Down);
+        assert_eq!(r, f32::NEG_INFINITY);
+
+        // subdesbordamiento
+        let (r, flags) = fp_rounded(
+            &mut cpu,
+            Opcode::FMUL,
+            f32::MIN_POSITIVE,
+            0.3,
+            Rounding::NearestEven,
+        );
+        assert!(r > 0.0 && r < f32::MIN_POSITIVE);
+        assert_eq!(flags, FCSR_UNDERFLOW | FCSR_INEXACT);
+
+        // x - x es -0 solo redondeando hacia -inf
+        let (r, _) = fp_rounded(&mut cpu, Opcode::FSUB, 2.0, 2.0, Rounding::NearestEven);
+        assert!(r == 0.0 && r.is_sign_positive());
+        let (r, _) = fp_rounded(&mut cpu, Opcode::FSUB, 2.0, 2.0, Rounding::Down);
+        assert!(r == 0.0 && r.is_sign_negative());
+    }
+
+    #[test]
+    fn test_fp_exceptions_are_sticky() {
+        let mut cpu = CPU::new(1024, vec![], 0x1000, 0x0);
+
+        let (r, flags) = fp_rounded(&mut cpu, Opcode::FDIV, 1.0, -0.0, Rounding::NearestEven);
+        assert_eq!((r, flags), (f32::NEG_INFINITY, FCSR_DIV_ZERO));
+        fp_rounded(&mut cpu, Opcode::FDIV, 0.0, 0.0, Rounding::NearestEven);
+        assert!(cpu.regs.fregs[2].is_nan());
+        assert_eq!(cpu.regs.fregs[2].to_bits(), 0x7FC0_0000); // NaN canónico
+
+        // las excepciones se acumulan hasta que se escribe FCSR
+        cpu.regs.set_fcsr(0);
+        cpu.regs.fregs[0] = 1.0;
+        cpu.regs.fregs[1] = 0.0;
+        fp(&mut cpu, Opcode::FDIV, 2, 0, 1);
+        cpu.regs.fregs[1] = 3.0;
+        fp(&mut cpu, Opcode::FDIV, 2, 0, 1);
+        fp(&mut cpu, Opcode::MFFCSR, 5, 0, 0);
+        assert_eq!(cpu.regs.get(5), FCSR_DIV_ZERO | FCSR_INEXACT);
+
+        cpu.regs.set(5, 0xFFFF_FFFF);
+        fp(&mut cpu, Opcode::MTFCSR, 5, 0, 0);
+        assert_eq!(cpu.regs.fcsr(), FCSR_MASK);
+        assert_eq!(Rounding::from_fcsr(cpu.regs.fcsr()), Rounding::Up);
+        cpu.regs.set(5, 0);
+        fp(&mut cpu, Opcode::MTFCSR, 5, 0, 0);
+        assert_eq!(cpu.regs.fcsr(), 0);
+    }
+
+    #[test]
+    fn test_fp_sqrt_fma_min_max() {
+        let mut cpu = CPU::new(1024, vec![], 0x1000, 0x0);
+
+        assert_eq!(
+            fp_rounded(&mut cpu, Opcode::FSQRT, 2.25, 0.0, Rounding::NearestEven),
+            (1.5, 0)
+        );
+        let (r, flags) = fp_rounded(&mut cpu, Opcode::FSQRT, -1.0, 0.0, Rounding::NearestEven);
+        assert!(r.is_nan());
+        assert_eq!(flags, FCSR_INVALID);
+        let (down, _) = fp_rounded(&mut cpu, Opcode::FSQRT, 2.0, 0.0, Rounding::Down);
+        let (up, _) = fp_rounded(&mut cpu, Opcode::FSQRT, 2.0, 0.0, Rounding::Up);
+        assert_eq!(up.to_bits() - down.to_bits(), 1);
+
+        // FMA redondea una sola vez: (1 + e) * (1 - e) - 1 = -e^2
+        let e = f32::EPSILON;
+        cpu.regs.fregs[0] = 1.0 + e;
+        cpu.regs.fregs[1] = 1.0 - e;
+        cpu.regs.fregs[2] = -1.0;
+        cpu.regs.set_fcsr(0);
+        fp(&mut cpu, Opcode::FMA, 2, 0, 1);
+        assert_eq!(cpu.regs.fregs[2], -e * e);
+        assert_eq!(cpu.regs.fcsr(), 0);
+
+        let nan = f32::NAN;
+        assert_eq!(
+            fp_rounded(&mut cpu, Opcode::FMIN, 1.0, -2.0, Rounding::NearestEven).0,
+            -2.0
+        );
+        assert_eq!(
+            fp_rounded(&mut cpu, Opcode::FMAX, 1.0, -2.0, Rounding::NearestEven).0,
+            1.0
+        );
+        assert_eq!(
+            fp_rounded(&mut cpu, Opcode::FMIN, nan, 4.0, Rounding::NearestEven),
+            (4.0, 0)
+        );
+        assert_eq!(
+            fp_rounded(&mut cpu, Opcode::FMAX, 4.0, nan, Rounding::NearestEven),
+            (4.0, 0)
+        );
+        assert!(
+            fp_rounded(&mut cpu, Opcode::FMAX, nan, nan, Rounding::NearestEven)
+                .0
+                .is_nan()
+        );
+        assert!(
+            fp_rounded(&mut cpu, Opcode::FMIN, 0.0, -0.0, Rounding::NearestEven)
+                .0
+                .is_sign_negative()
+        );
+        assert!(
+            fp_rounded(&mut cpu, Opcode::FMAX, -0.0, 0.0, Rounding::NearestEven)
+                .0
+                .is_sign_positive()
+        );
+
+        cpu.regs.fregs[0] = -3.5;
+        fp(&mut cpu, Opcode::FABS, 1, 0, 0);
+        assert_eq!(cpu.regs.fregs[1], 3.5);
+        fp(&mut cpu, Opcode::FNEG, 1, 1, 0);
+        assert_eq!(cpu.regs.fregs[1], -3.5);
+    }
+
+    #[test]
+    fn test_fp_integer_conversions_saturate() {
+        let mut cpu = CPU::new(1024, vec![], 0x1000, 0x0);
+        let mut to_int = |opcode, value: f32| {
+            cpu.regs.fregs[0] = value;
+            cpu.regs.set_fcsr(0);
+            fp(&mut cpu, opcode, 1, 0, 0);
+            (cpu.regs.get(1), cpu.regs.fcsr())
         };
-        cpu.execute(instr);
-        assert_eq!(cpu.regs.fregs[0], 5.5);
+
+        assert_eq!(to_int(Opcode::FTOI, -3.7), (-3i32 as u32, FCSR_INEXACT));
+        assert_eq!(to_int(Opcode::FTOI, 1e10), (i32::MAX as u32, FCSR_INVALID));
+        assert_eq!(to_int(Opcode::FTOI, -1e10), (i32::MIN as u32, FCSR_INVALID));
+        assert_eq!(to_int(Opcode::FTOI, f32::NAN), (0, FCSR_INVALID));
+        assert_eq!(to_int(Opcode::FTOIU, 3e9), (3_000_000_000, 0));
+        assert_eq!(to_int(Opcode::FTOIU, -1.0), (0, FCSR_INVALID));
+        assert_eq!(to_int(Opcode::FTOIU, -0.5), (0, FCSR_INEXACT));
+        assert_eq!(to_int(Opcode::FTOIU, 5e9), (u32::MAX, FCSR_INVALID));
+
+        cpu.regs.set(1, -7i32 as u32);
+        cpu.regs.set_fcsr(0);
+        fp(&mut cpu, Opcode::ITOF, 0, 1, 0);
+        assert_eq!(cpu.regs.fregs[0], -7.0);
+        fp(&mut cpu, Opcode::UTOF, 0, 1, 0);
+        assert_eq!(cpu.regs.fregs[0], 4294967289.0);
+        assert_eq!(cpu.regs.fcsr(), FCSR_INEXACT);
+
+        // 2^24 + 1 no es representable: el modo de redondeo decide
+        cpu.regs.set(1, (1 << 24) + 1);
+        cpu.regs.set_fcsr(Rounding::Up.to_fcsr());
+        fp(&mut cpu, Opcode::ITOF, 0, 1, 0);
+        assert_eq!(cpu.regs.fregs[0], 16777218.0);
+        cpu.regs.set_fcsr(Rounding::NearestEven.to_fcsr());
+        fp(&mut cpu, Opcode::ITOF, 0, 1, 0);
+        assert_eq!(cpu.regs.fregs[0], 16777216.0);
+    }
+
+    #[test]
+    fn test_double_register_pairs() {
+        let mut cpu = CPU::new(1024, vec![], 0x1000, 0x0);
+
+        // La palabra baja va en el registro par
+        cpu.regs.dset(2, 1.0);
+        assert_eq!(cpu.regs.fregs[2].to_bits(), 0);
+        assert_eq!(cpu.regs.fregs[3].to_bits(), 0x3FF0_0000);
+        assert_eq!(cpu.regs.dget(2), 1.0);
+
+        cpu.regs.dset(0, 0.1);
+        cpu.regs.dset(2, 0.2);
+        fp(&mut cpu, Opcode::FADDD, 4, 0, 2);
+        assert_eq!(cpu.regs.dget(4), 0.1 + 0.2);
+        assert_eq!(cpu.regs.fcsr(), FCSR_INEXACT);
+
+        cpu.regs.dset(0, 1.0);
+        cpu.regs.dset(2, 3.0);
+        fp(&mut cpu, Opcode::FDIVD, 4, 0, 2);
+        assert_eq!(cpu.regs.dget(4), 1.0 / 3.0);
+
+        cpu.regs.dset(0, 2.0);
+        fp(&mut cpu, Opcode::FSQRTD, 4, 0, 0);
+        assert_eq!(cpu.regs.dget(4), std::f64::consts::SQRT_2);
+
+        // F4 = F0 * F2 + F4
+        cpu.regs.dset(4, 1.0);
+        fp(&mut cpu, Opcode::FMAD, 4, 0, 2);
+        assert_eq!(cpu.regs.dget(4), 7.0);
+
+        cpu.regs.dset(0, -2.5);
+        fp(&mut cpu, Opcode::FCMPD, 0, 0, 2);
+        let f = Flags::from_u32(cpu.regs.flags());
+        assert!(f.less && f.not_equal && !f.equal);
+        fp(&mut cpu, Opcode::FLTD, 1, 0, 2);
+        assert_eq!(cpu.regs.get(1), 1);
+
+        // Un registro impar no forma par
+        for instr in [
+            Instruction::FP {
+                opcode: Opcode::FADDD,
+                rd: 4,
+                rs1: 1,
+                rs2: 2,
+            },
+            Instruction::FP {
+                opcode: Opcode::FMOVD,
+                rd: 5,
+                rs1: 0,
+                rs2: 0,
+            },
+        ] {
+            assert_eq!(cpu.execute(instr), Err(FaultKind::IllegalOpcode));
+        }
+        assert_eq!(cpu.regs.dget(4), 7.0);
+    }
+
+    #[test]
+    fn test_double_conversions() {
+        let mut cpu = CPU::new(1024, vec![], 0x1000, 0x0);
+
+        // f32 -> f64 es exacta
+        cpu.regs.fregs[1] = 0.1;
+        cpu.regs.set_fcsr(0);
+        fp(&mut cpu, Opcode::FTOD, 2, 1, 0);
+        assert_eq!(cpu.regs.dget(2), 0.1f32 as f64);
+        assert_eq!(cpu.regs.fcsr(), 0);
+
+        // f64 -> f32 redondea según FCSR
+        cpu.regs.dset(2, 1.0 + f64::EPSILON);
+        fp(&mut cpu, Opcode::DTOF, 1, 2, 0);
+        assert_eq!(cpu.regs.fregs[1], 1.0);
+        assert_eq!(cpu.regs.fcsr(), FCSR_INEXACT);
+        cpu.regs.set_fcsr(Rounding::Up.to_fcsr());
+        fp(&mut cpu, Opcode::DTOF, 1, 2, 0);
+        assert_eq!(cpu.regs.fregs[1], 1.0 + f32::EPSILON);
+
+        cpu.regs.set_fcsr(0);
+        cpu.regs.dset(2, 1e300);
+        fp(&mut cpu, Opcode::DTOF, 1, 2, 0);
+        assert_eq!(cpu.regs.fregs[1], f32::INFINITY);
+        assert_eq!(cpu.regs.fcsr(), FCSR_OVERFLOW | FCSR_INEXACT);
+
+        // Los enteros de 32 bits caben exactos en un f64
+        cpu.regs.set_fcsr(0);
+        cpu.regs.set(1, (1 << 24) + 1);
+        fp(&mut cpu, Opcode::ITOD, 2, 1, 0);
+        assert_eq!(cpu.regs.dget(2), 16777217.0);
+        cpu.regs.set(1, u32::MAX);
+        fp(&mut cpu, Opcode::UTOD, 2, 1, 0);
+        assert_eq!(cpu.regs.dget(2), 4294967295.0);
+        assert_eq!(cpu.regs.fcsr(), 0);
+
+        fp(&mut cpu, Opcode::DTOIU, 3, 2, 0);
+        assert_eq!(cpu.regs.get(3), u32::MAX);
+        fp(&mut cpu, Opcode::DTOI, 3, 2, 0);
+        assert_eq!(cpu.regs.get(3), i32::MAX as u32);
+        assert_eq!(cpu.regs.fcsr(), FCSR_INVALID);
+
+        cpu.regs.dset(2, -123.75);
+        fp(&mut cpu, Opcode::DTOI, 3, 2, 0);
+        assert_eq!(cpu.regs.get(3), -123i32 as u32);
+    }
+
+    #[test]
+    fn test_double_memory_load_store() {
+        let mut cpu = CPU::new(1024, vec![], 0x1000, 0x0);
+        cpu.regs.set(1, 96);
+        cpu.regs.dset(2, std::f64::consts::PI);
+
+        // FSTD F2, [R1, 8]
+        cpu.execute(Instruction::Mem {
+            opcode: Opcode::FSTD,
+            rd: 2,
+            rs1: 1,
+            imm: 8,
+        })
+        .unwrap();
+        let bits = std::f64::consts::PI.to_bits();
+        assert_eq!(cpu.mem.read32(104).unwrap(), bits as u32);
+        assert_eq!(cpu.mem.read32(108).unwrap(), (bits >> 32) as u32);
+
+        // FLDD F4, [R1, 8]
+        cpu.execute(Instruction::Mem {
+            opcode: Opcode::FLDD,
+            rd: 4,
+            rs1: 1,
+            imm: 8,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.dget(4), std::f64::consts::PI);
+
+        // Los accesos de 64 bits van alineados a 8
+        let misaligned = cpu.execute(Instruction::Mem {
+            opcode: Opcode::FLDD,
+            rd: 4,
+            rs1: 1,
+            imm: 4,
+        });
+        assert_eq!(misaligned, Err(FaultKind::Misaligned { addr: 100 }));
+
+        let odd = cpu.execute(Instruction::Mem {
+            opcode: Opcode::FSTD,
+            rd: 3,
+            rs1: 1,
+            imm: 8,
+        });
+        assert_eq!(odd, Err(FaultKind::IllegalOpcode));
     }
 
     #[test]
@@ -683,15 +1844,16 @@ mod tests {
         let mut cpu = CPU::new(1024, vec![], 0x1000, 0);
 
         // escribimos en el puerto 0x1234
-        cpu.regs.set(0, 0xDEADBEEF);
+        cpu.regs.set(3, 0xDEADBEEF);
         let port: u16 = 0x1234;
 
         // OUT
         cpu.execute(crate::instruction::Instruction::IO {
             opcode: Opcode::OUT,
-            rd: 0,
+            rd: 3,
             port,
-        });
+        })
+        .unwrap();
         assert_eq!(cpu.io.read(port), 0xDEADBEEF);
 
         // limpiamos registro y hacemos IN
@@ -700,7 +1862,8 @@ mod tests {
             opcode: Opcode::IN,
             rd: 1,
             port,
-        });
+        })
+        .unwrap();
         assert_eq!(cpu.regs.get(1), 0xDEADBEEF);
     }
 
@@ -712,12 +1875,13 @@ mod tests {
 
         // escribir en varios puertos
         for (port, val) in &values {
-            cpu.regs.set(0, *val);
+            cpu.regs.set(3, *val);
             cpu.execute(crate::instruction::Instruction::IO {
                 opcode: Opcode::OUT,
-                rd: 0,
+                rd: 3,
                 port: *port as u16,
-            });
+            })
+            .unwrap();
         }
 
         // leer y verificar
@@ -727,7 +1891,8 @@ mod tests {
                 opcode: Opcode::IN,
                 rd: 1,
                 port: *port as u16,
-            });
+            })
+            .unwrap();
             assert_eq!(cpu.regs.get(1), *val);
         }
     }
@@ -738,21 +1903,1777 @@ mod tests {
 
         let port: u16 = 0x42;
 
-        cpu.regs.set(0, 0xAAAA);
+        cpu.regs.set(3, 0xAAAA);
         cpu.execute(crate::instruction::Instruction::IO {
             opcode: Opcode::OUT,
-            rd: 0,
+            rd: 3,
             port,
-        });
+        })
+        .unwrap();
         assert_eq!(cpu.io.read(port), 0xAAAA);
 
         // sobrescribir mismo puerto
-        cpu.regs.set(0, 0x5555);
+        cpu.regs.set(3, 0x5555);
         cpu.execute(crate::instruction::Instruction::IO {
             opcode: Opcode::OUT,
-            rd: 0,
+            rd: 3,
             port,
-        });
+        })
+        .unwrap();
         assert_eq!(cpu.io.read(port), 0x5555);
     }
+
+    fn interrupt_cpu() -> CPU {
+        // vector IRQ 1 -> 0x200, programa en 0x100, pila en 0x400
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0x100);
+        cpu.mem.write32(4, 0x200).unwrap();
+        cpu.pic.borrow_mut().set_mask(0b10);
+        cpu
+    }
+
+    #[test]
+    fn test_interrupt_taken() {
+        let mut cpu = interrupt_cpu();
+        cpu.regs.set_flags(0x400 | 0x01); // IE + zero
+
+        cpu.irq_line(1).raise();
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.regs.pc(), 0x200);
+        assert_eq!(cpu.regs.sp(), 0x400 - 8);
+        assert_eq!(cpu.mem.read32(0x400 - 8).unwrap(), 0x100); // PC guardado
+        assert_eq!(cpu.mem.read32(0x400 - 4).unwrap(), 0x401); // FLAGS guardados
+        assert!(!Flags::from_u32(cpu.regs.flags()).interrupt_enable);
+        assert_eq!(cpu.pic.borrow().pending(), 0);
+        assert_eq!(cpu.cycle_count, VECTOR_ENTRY_CYCLES as u64);
+    }
+
+    #[test]
+    fn test_interrupt_disabled_or_masked() {
+        let mut cpu = interrupt_cpu();
+
+        // IE = 0: la petición queda pendiente
+        cpu.irq_line(1).raise();
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc(), 0x104);
+        assert_eq!(cpu.pic.borrow().pending(), 0b10);
+
+        // IE = 1 pero línea enmascarada
+        cpu.regs.set_flags(0x400);
+        cpu.pic.borrow_mut().set_mask(0);
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc(), 0x108);
+
+        cpu.pic.borrow_mut().set_mask(0b10);
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc(), 0x200);
+    }
+
+    #[test]
+    fn test_interrupt_priority() {
+        let mut cpu = interrupt_cpu();
+        cpu.mem.write32(12, 0x300).unwrap();
+        cpu.pic.borrow_mut().set_mask(0b1010);
+        cpu.regs.set_flags(0x400);
+
+        cpu.irq_line(3).raise();
+        cpu.irq_line(1).raise();
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc(), 0x200);
+        assert_eq!(cpu.pic.borrow().pending(), 0b1000);
+    }
+
+    #[test]
+    fn test_reti_restores_pc_and_flags() {
+        let mut cpu = interrupt_cpu();
+        cpu.mem.write32(0x200, (Opcode::RETI as u32) << 24).unwrap();
+        cpu.regs.set_flags(0x400 | 0x02); // IE + carry
+
+        cpu.irq_line(1).raise();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+
+        assert_eq!(cpu.regs.pc(), 0x100);
+        assert_eq!(cpu.regs.sp(), 0x400);
+        assert_eq!(cpu.regs.flags(), 0x402);
+    }
+
+    #[test]
+    fn test_ei_di() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        cpu.regs.set_flags(0x01);
+
+        cpu.execute(Instruction::Sys {
+            opcode: Opcode::EI,
+            rd: 0,
+            imm: 0,
+            rs: 0,
+        })
+        .unwrap();
+        let flags = Flags::from_u32(cpu.regs.flags());
+        assert!(flags.interrupt_enable && flags.zero);
+
+        cpu.execute(Instruction::Sys {
+            opcode: Opcode::DI,
+            rd: 0,
+            imm: 0,
+            rs: 0,
+        })
+        .unwrap();
+        let flags = Flags::from_u32(cpu.regs.flags());
+        assert!(!flags.interrupt_enable && flags.zero);
+    }
+
+    #[test]
+    fn test_interrupt_controller_ports() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0x100);
+        cpu.mem.write32(0x80 + 2 * 4, 0x240).unwrap();
+
+        cpu.regs.set(1, 0x80);
+        cpu.execute(Instruction::IO {
+            opcode: Opcode::OUT,
+            rd: 1,
+            port: PIC_VECTOR_BASE,
+        })
+        .unwrap();
+        cpu.regs.set(1, 0b100);
+        cpu.execute(Instruction::IO {
+            opcode: Opcode::OUT,
+            rd: 1,
+            port: PIC_MASK,
+        })
+        .unwrap();
+        assert_eq!(cpu.port_read(PIC_MASK), 0b100);
+
+        cpu.regs.set_flags(0x400);
+        cpu.irq_line(2).raise();
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc(), 0x240);
+    }
+
+    #[test]
+    fn test_illegal_opcode_fault() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0x100);
+        cpu.mem.write32(0x100, 0xFF00_0000).unwrap();
+
+        let fault = cpu.step().unwrap_err();
+        assert_eq!(fault.pc, 0x100);
+        assert_eq!(fault.raw, 0xFF00_0000);
+        assert_eq!(fault.kind, FaultKind::IllegalOpcode);
+        assert_eq!(cpu.regs.pc(), 0x100); // no avanza
+        assert!(Instruction::decode(0xFF00_0000).is_err());
+    }
+
+    #[test]
+    fn test_nopi_is_a_no_op() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0x100);
+        cpu.mem.write32(0x100, 0x2000_0000).unwrap();
+        // NOPI con rd e inmediato: tampoco toca el registro
+        let nopi = Instruction::I {
+            opcode: Opcode::NOPI,
+            rd: 3,
+            rs1: 4,
+            imm: 5,
+        };
+        cpu.mem.write32(0x104, nopi.encode()).unwrap();
+        cpu.regs.set(3, 0x1234);
+        cpu.regs.set_flags(0x5);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc(), 0x108);
+        assert_eq!(cpu.regs.get(3), 0x1234);
+        assert_eq!(cpu.regs.flags(), 0x5);
+    }
+
+    #[test]
+    fn test_memory_faults() {
+        let mut cpu = CPU::new(1024, vec![0; 16], 0x400, 0);
+        cpu.regs.set(1, 1024);
+        cpu.regs.set(2, 2000);
+        cpu.regs.set(3, 2);
+
+        let store_rom = Instruction::Mem {
+            opcode: Opcode::STW,
+            rd: 0,
+            rs1: 1,
+            imm: 0,
+        };
+        assert_eq!(
+            cpu.execute(store_rom),
+            Err(FaultKind::RomWrite { addr: 1024 })
+        );
+
+        let load_unmapped = Instruction::Mem {
+            opcode: Opcode::LDW,
+            rd: 4,
+            rs1: 2,
+            imm: 0,
+        };
+        assert_eq!(
+            cpu.execute(load_unmapped),
+            Err(FaultKind::BusError { addr: 2000 })
+        );
+
+        let load_misaligned = Instruction::Mem {
+            opcode: Opcode::LDW,
+            rd: 4,
+            rs1: 3,
+            imm: 0,
+        };
+        assert_eq!(
+            cpu.execute(load_misaligned),
+            Err(FaultKind::Misaligned { addr: 2 })
+        );
+
+        // la última palabra de ROM sigue siendo legible
+        assert_eq!(cpu.mem.read32(1024 + 12), Ok(0));
+        assert_eq!(
+            cpu.mem.read32(1024 + 16),
+            Err(FaultKind::BusError { addr: 1040 })
+        );
+    }
+
+    #[test]
+    fn test_divide_by_zero_trap() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        cpu.regs.set(1, 10);
+        let div = Instruction::R {
+            opcode: Opcode::DIV,
+            rd: 2,
+            rs1: 1,
+            rs2: 0,
+        };
+
+        cpu.execute(div).unwrap();
+        assert!(Flags::from_u32(cpu.regs.flags()).overflow);
+
+        cpu.trap_div_zero = true;
+        assert_eq!(cpu.execute(div), Err(FaultKind::DivideByZero));
+
+        let modi = Instruction::I {
+            opcode: Opcode::MODI,
+            rd: 2,
+            rs1: 1,
+            imm: 0,
+        };
+        assert_eq!(cpu.execute(modi), Err(FaultKind::DivideByZero));
+    }
+
+    #[test]
+    fn test_unsigned_divide_and_high_multiply() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        let mut run = |opcode, a: u32, b: u32| {
+            cpu.regs.set(1, a);
+            cpu.regs.set(2, b);
+            cpu.execute(Instruction::R {
+                opcode,
+                rd: 3,
+                rs1: 1,
+                rs2: 2,
+            })
+            .unwrap();
+            cpu.regs.get(3)
+        };
+
+        assert_eq!(run(Opcode::DIVU, 0xFFFF_FFFE, 2), 0x7FFF_FFFF);
+        assert_eq!(run(Opcode::MODU, 0xFFFF_FFFF, 10), 5);
+        assert_eq!(run(Opcode::DIV, 0xFFFF_FFFE, 2), 0xFFFF_FFFF);
+        assert_eq!(run(Opcode::MULHU, 0xFFFF_FFFF, 0xFFFF_FFFF), 0xFFFF_FFFE);
+        assert_eq!(run(Opcode::MULH, 0xFFFF_FFFF, 0xFFFF_FFFF), 0);
+        assert_eq!(run(Opcode::MULH, 0x8000_0000, 2), 0xFFFF_FFFF);
+        assert_eq!(run(Opcode::MULHSU, 0xFFFF_FFFF, 0xFFFF_FFFF), 0xFFFF_FFFF);
+        assert_eq!(run(Opcode::MULHSU, 0x4000_0000, 0x8000_0000), 0x2000_0000);
+        // multiplicación en punto fijo 16.16: 1.5 * 2.5 = 3.75
+        let (a, b) = (0x0001_8000, 0x0002_8000);
+        let lo = run(Opcode::MUL, a, b);
+        let hi = run(Opcode::MULH, a, b);
+        assert_eq!((hi << 16) | (lo >> 16), 0x0003_C000);
+
+        assert_eq!(run(Opcode::DIVU, 7, 0), 0);
+        assert!(Flags::from_u32(cpu.regs.flags()).overflow);
+
+        cpu.regs.set(1, 40_000);
+        let divui = Instruction::I {
+            opcode: Opcode::DIVUI,
+            rd: 3,
+            rs1: 1,
+            imm: 16_000,
+        };
+        cpu.execute(divui).unwrap();
+        assert_eq!(cpu.regs.get(3), 2);
+        cpu.execute(Instruction::I {
+            opcode: Opcode::MODUI,
+            rd: 3,
+            rs1: 1,
+            imm: 16_000,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.get(3), 8_000);
+        // el inmediato de DIVUI no se extiende con signo
+        let raw = divui.encode() | 0x3FFF;
+        let Instruction::I { imm, .. } = Instruction::decode(raw).unwrap() else {
+            panic!("no es I-type");
+        };
+        assert_eq!(imm, 0x3FFF);
+
+        cpu.trap_div_zero = true;
+        cpu.regs.set(2, 0);
+        let modu = Instruction::R {
+            opcode: Opcode::MODU,
+            rd: 3,
+            rs1: 1,
+            rs2: 2,
+        };
+        assert_eq!(cpu.execute(modu), Err(FaultKind::DivideByZero));
+    }
+
+    // Ejecuta `opcode R3, R1, R2` con el acarreo de entrada indicado
+    fn alu_with_carry(cpu: &mut CPU, opcode: Opcode, a: u32, b: u32, carry: bool) -> (u32, Flags) {
+        cpu.regs.set(1, a);
+        cpu.regs.set(2, b);
+        cpu.regs.set_flags(if carry { 0x02 } else { 0 });
+        cpu.execute(Instruction::R {
+            opcode,
+            rd: 3,
+            rs1: 1,
+            rs2: 2,
+        })
+        .unwrap();
+        (cpu.regs.get(3), Flags::from_u32(cpu.regs.flags()))
+    }
+
+    #[test]
+    fn test_add_with_carry_and_subtract_with_borrow() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+
+        let (r, f) = alu_with_carry(&mut cpu, Opcode::ADC, 1, 2, true);
+        assert_eq!(r, 4);
+        assert!(!f.carry && !f.overflow);
+        let (r, f) = alu_with_carry(&mut cpu, Opcode::ADC, 0xFFFF_FFFF, 0, true);
+        assert_eq!(r, 0);
+        assert!(f.carry && f.zero && !f.overflow);
+        let (r, f) = alu_with_carry(&mut cpu, Opcode::ADC, 0x7FFF_FFFF, 0, true);
+        assert_eq!(r, 0x8000_0000);
+        assert!(!f.carry && f.overflow && f.sign);
+        let (r, f) = alu_with_carry(&mut cpu, Opcode::ADC, 0xFFFF_FFFF, 0xFFFF_FFFF, true);
+        assert_eq!(r, 0xFFFF_FFFF);
+        assert!(f.carry && !f.overflow);
+
+        // en SBC el acarreo es un préstamo, igual que en SUB
+        let (r, f) = alu_with_carry(&mut cpu, Opcode::SBC, 5, 3, true);
+        assert_eq!(r, 1);
+        assert!(!f.carry && !f.overflow);
+        let (r, f) = alu_with_carry(&mut cpu, Opcode::SBC, 3, 3, true);
+        assert_eq!(r, 0xFFFF_FFFF);
+        assert!(f.carry && !f.overflow);
+        let (r, f) = alu_with_carry(&mut cpu, Opcode::SBC, 0, 0xFFFF_FFFF, true);
+        assert_eq!(r, 0);
+        assert!(f.carry && f.zero);
+        let (r, f) = alu_with_carry(&mut cpu, Opcode::SBC, 0x8000_0000, 0, true);
+        assert_eq!(r, 0x7FFF_FFFF);
+        assert!(!f.carry && f.overflow);
+
+        // suma y resta de 64 bits: la parte baja con ADD/SUB y la alta con
+        // ADC/SBC
+        let (a, b) = (0x0000_0001_FFFF_FFFFu64, 0x0000_0002_0000_0001u64);
+        let mut r64 = |lo_op, hi_op| {
+            cpu.regs.set(1, a as u32);
+            cpu.regs.set(2, (a >> 32) as u32);
+            cpu.regs.set(3, b as u32);
+            cpu.regs.set(4, (b >> 32) as u32);
+            for (opcode, rd, rs1, rs2) in [(lo_op, 5, 1, 3), (hi_op, 6, 2, 4)] {
+                cpu.execute(Instruction::R {
+                    opcode,
+                    rd,
+                    rs1,
+                    rs2,
+                })
+                .unwrap();
+            }
+            (cpu.regs.get(6) as u64) << 32 | cpu.regs.get(5) as u64
+        };
+        assert_eq!(r64(Opcode::ADD, Opcode::ADC), a + b);
+        assert_eq!(r64(Opcode::SUB, Opcode::SBC), a.wrapping_sub(b));
+
+        // ADCI R1, R1, #0 propaga el acarreo
+        cpu.regs.set(1, 9);
+        cpu.regs.set_flags(0x02);
+        cpu.execute(Instruction::I {
+            opcode: Opcode::ADCI,
+            rd: 1,
+            rs1: 1,
+            imm: 0,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.get(1), 10);
+        cpu.execute(Instruction::I {
+            opcode: Opcode::SBCI,
+            rd: 1,
+            rs1: 1,
+            imm: -3i32 as u32,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.get(1), 13);
+    }
+
+    #[test]
+    fn test_bit_manipulation() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        let mut r = |opcode, a: u32, b: u32| alu_with_carry(&mut cpu, opcode, a, b, false);
+
+        assert_eq!(r(Opcode::CLZ, 0x0001_0000, 0).0, 15);
+        assert_eq!(r(Opcode::CLZ, 0, 0).0, 32);
+        assert_eq!(r(Opcode::CTZ, 0x0001_0000, 0).0, 16);
+        assert_eq!(r(Opcode::CTZ, 0, 0).0, 32);
+        assert_eq!(r(Opcode::BSWAP, 0x1234_5678, 0).0, 0x7856_3412);
+        assert_eq!(r(Opcode::BREV, 0x0000_0001, 0).0, 0x8000_0000);
+        assert_eq!(r(Opcode::BREV, 0x1234_5678, 0).0, 0x1E6A_2C48);
+
+        assert_eq!(r(Opcode::BSET, 0x10, 35).0, 0x18); // el bit va módulo 32
+        assert_eq!(r(Opcode::BCLR, 0xFF, 4).0, 0xEF);
+        let (bit, f) = r(Opcode::BTST, 0x10, 4);
+        assert!(bit == 1 && !f.zero);
+        let (bit, f) = r(Opcode::BTST, 0x10, 3);
+        assert!(bit == 0 && f.zero);
+    }
+
+    #[test]
+    fn test_bit_field_extract_insert() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        let mut field = |opcode, rd_value: u32, src: u32, pos, width| {
+            cpu.regs.set(1, src);
+            cpu.regs.set(3, rd_value);
+            cpu.execute(Instruction::I {
+                opcode,
+                rd: 3,
+                rs1: 1,
+                imm: bit_field(pos, width),
+            })
+            .unwrap();
+            cpu.regs.get(3)
+        };
+
+        // RGB565: R en 15..11, G en 10..5, B en 4..0
+        let pixel = (0b10110 << 11) | (0b101010 << 5) | 0b01101;
+        assert_eq!(field(Opcode::BFEXT, 0, pixel, 11, 5), 0b10110);
+        assert_eq!(field(Opcode::BFEXT, 0, pixel, 5, 6), 0b101010);
+        assert_eq!(field(Opcode::BFEXTS, 0, pixel, 11, 5), 0xFFFF_FFF6);
+        assert_eq!(field(Opcode::BFEXTS, 0, pixel, 0, 5), 0b01101);
+        assert_eq!(field(Opcode::BFEXT, 0, 0xDEAD_BEEF, 0, 32), 0xDEAD_BEEF);
+        assert_eq!(field(Opcode::BFEXTS, 0, 0x8000_0000, 31, 1), 0xFFFF_FFFF);
+
+        assert_eq!(
+            field(Opcode::BFINS, pixel, 0b111111, 5, 6),
+            (0b10110 << 11) | (0b111111 << 5) | 0b01101
+        );
+        // solo se copian los `width` bits bajos de la fuente
+        assert_eq!(field(Opcode::BFINS, 0, 0xFFFF_FFFF, 28, 4), 0xF000_0000);
+        assert_eq!(field(Opcode::BFINS, 0x1234_5678, 0xCAFE, 0, 32), 0xCAFE);
+
+        cpu.regs.set(1, 0x10);
+        cpu.execute(Instruction::I {
+            opcode: Opcode::BTSTI,
+            rd: 2,
+            rs1: 1,
+            imm: 4,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.get(2), 1);
+        cpu.execute(Instruction::I {
+            opcode: Opcode::BSETI,
+            rd: 2,
+            rs1: 1,
+            imm: 31,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.get(2), 0x8000_0010);
+        cpu.execute(Instruction::I {
+            opcode: Opcode::BCLRI,
+            rd: 2,
+            rs1: 2,
+            imm: 4,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.get(2), 0x8000_0000);
+    }
+
+    #[test]
+    fn test_fault_delivered_as_exception() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0x100);
+        let vector = FaultKind::Misaligned { addr: 0 }.vector() as u32;
+        cpu.mem.write32(vector * 4, 0x300).unwrap();
+        // LDW R1, [R2 + 0] con R2 desalineado
+        cpu.mem
+            .write32(0x100, ((Opcode::LDW as u32) << 24) | (1 << 19) | (2 << 14))
+            .unwrap();
+        cpu.regs.set(2, 0x41);
+        cpu.regs.set_flags(0x400);
+        cpu.trap_faults = true;
+
+        let outcome = cpu.step().unwrap();
+        match outcome {
+            StepOutcome::Exception(fault) => {
+                assert_eq!(fault.pc, 0x100);
+                assert_eq!(fault.kind, FaultKind::Misaligned { addr: 0x41 });
+            }
+            other => panic!("se esperaba una excepción, no {:?}", other),
+        }
+
+        assert_eq!(cpu.regs.pc(), 0x300);
+        assert_eq!(cpu.mem.read32(0x400 - 8).unwrap(), 0x100); // PC que falló
+        assert_eq!(cpu.mem.read32(0x400 - 4).unwrap(), 0x400);
+        assert!(!Flags::from_u32(cpu.regs.flags()).interrupt_enable);
+        assert_eq!(cpu.port_read(PIC_FAULT_CAUSE), 4);
+        assert_eq!(cpu.port_read(PIC_FAULT_ADDR), 0x41);
+    }
+
+    #[test]
+    fn test_fault_during_exception_entry() {
+        // sin pila válida el fallo vuelve al anfitrión
+        let mut cpu = CPU::new(1024, vec![], 0, 0x100);
+        cpu.mem.write32(0x100, 0xFF00_0000).unwrap();
+        cpu.trap_faults = true;
+
+        let fault = cpu.step().unwrap_err();
+        assert_eq!(fault.pc, 0x100);
+        assert_eq!(fault.kind, FaultKind::BusError { addr: 0xFFFF_FFFC });
+    }
+
+    fn syscall_cpu() -> CPU {
+        // vector SYSCALL -> 0x200, programa de usuario en 0x100,
+        // pila de supervisor en 0x400 y de usuario en 0x300
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0x100);
+        cpu.mem.write32(SYSCALL_VECTOR as u32 * 4, 0x200).unwrap();
+        cpu.mem.write32(0x200, (Opcode::RETI as u32) << 24).unwrap();
+        cpu.regs.set_banked_sp(0x300);
+        cpu.regs.set(1, 0x800 | 0x400); // usuario + IE
+        cpu.execute(Instruction::Sys {
+            opcode: Opcode::MTSR,
+            rd: 1,
+            imm: 0,
+            rs: 0,
+        })
+        .unwrap();
+        cpu
+    }
+
+    #[test]
+    fn test_privileged_instructions_fault_in_user_mode() {
+        let mut cpu = syscall_cpu();
+        assert_eq!(cpu.regs.sp(), 0x300);
+        assert_eq!(cpu.regs.banked_sp(), 0x400);
+
+        let sys = |opcode| Instruction::Sys {
+            opcode,
+            rd: 1,
+            imm: 0,
+            rs: 0,
+        };
+        for instr in [
+            sys(Opcode::MTSR),
+            sys(Opcode::EI),
+            sys(Opcode::DI),
+            sys(Opcode::MFUSP),
+            sys(Opcode::MTUSP),
+            Instruction::J {
+                opcode: Opcode::RETI,
+                offset: 0,
+            },
+            Instruction::J {
+                opcode: Opcode::HALT,
+                offset: 0,
+            },
+            Instruction::IO {
+                opcode: Opcode::OUT,
+                rd: 1,
+                port: PIC_MASK,
+            },
+            Instruction::IO {
+                opcode: Opcode::IN,
+                rd: 1,
+                port: PIC_MASK,
+            },
+        ] {
+            assert_eq!(cpu.execute(instr), Err(FaultKind::PrivilegeViolation));
+        }
+        assert_eq!(cpu.regs.flags(), 0xC00);
+        assert!(!cpu.halted);
+
+        // SETSP y MFSR solo tocan el estado del modo actual
+        cpu.regs.set(2, 0x2F0);
+        cpu.execute(Instruction::Sys {
+            opcode: Opcode::SETSP,
+            rd: 2,
+            imm: 0,
+            rs: 0,
+        })
+        .unwrap();
+        cpu.execute(sys(Opcode::MFSR)).unwrap();
+        assert_eq!(cpu.regs.get(1), 0xC00);
+        assert_eq!(cpu.regs.sp(), 0x2F0);
+        assert_eq!(cpu.regs.banked_sp(), 0x400);
+
+        // a través de step el fallo se entrega por su vector
+        cpu.mem.write32(0x100, (Opcode::HALT as u32) << 24).unwrap();
+        let fault = cpu.step().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::PrivilegeViolation);
+        assert_eq!(cpu.regs.pc(), 0x100);
+
+        cpu.mem
+            .write32(FaultKind::PrivilegeViolation.vector() as u32 * 4, 0x240)
+            .unwrap();
+        cpu.trap_faults = true;
+        assert!(matches!(cpu.step(), Ok(StepOutcome::Exception(_))));
+        assert_eq!(cpu.regs.pc(), 0x240);
+        assert_eq!(cpu.regs.sp(), 0x400 - 8);
+        assert_eq!(cpu.regs.banked_sp(), 0x2F0);
+        assert_eq!(cpu.port_read(PIC_FAULT_CAUSE), 6);
+    }
+
+    #[test]
+    fn test_syscall_enters_supervisor() {
+        let mut cpu = syscall_cpu();
+        cpu.mem
+            .write32(
+                0x100,
+                Instruction::Sys {
+                    opcode: Opcode::SYSCALL,
+                    rd: 0,
+                    imm: 42,
+                    rs: 0,
+                }
+                .encode(),
+            )
+            .unwrap();
+
+        assert_eq!(cpu.step().unwrap(), StepOutcome::Syscall(42));
+        assert_eq!(cpu.regs.pc(), 0x200);
+        let flags = Flags::from_u32(cpu.regs.flags());
+        assert!(!flags.user_mode && !flags.interrupt_enable);
+
+        // marco en la pila de supervisor; la de usuario queda intacta
+        assert_eq!(cpu.regs.sp(), 0x400 - 8);
+        assert_eq!(cpu.regs.banked_sp(), 0x300);
+        assert_eq!(cpu.mem.read32(0x400 - 8).unwrap(), 0x104);
+        assert_eq!(cpu.mem.read32(0x400 - 4).unwrap(), 0xC00);
+        assert_eq!(cpu.port_read(PIC_FAULT_CAUSE), SYSCALL_CAUSE);
+        assert_eq!(cpu.port_read(PIC_FAULT_ADDR), 42);
+
+        // RETI vuelve a modo usuario con su pila
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc(), 0x104);
+        assert_eq!(cpu.regs.flags(), 0xC00);
+        assert_eq!(cpu.regs.sp(), 0x300);
+        assert_eq!(cpu.regs.banked_sp(), 0x400);
+    }
+
+    #[test]
+    fn test_interrupt_from_user_mode_uses_supervisor_stack() {
+        let mut cpu = syscall_cpu();
+        cpu.mem.write32(4, 0x200).unwrap();
+        cpu.pic.borrow_mut().set_mask(0b10);
+
+        cpu.irq_line(1).raise();
+        assert_eq!(cpu.step().unwrap(), StepOutcome::Interrupt(1));
+        assert_eq!(cpu.regs.sp(), 0x400 - 8);
+        assert_eq!(cpu.regs.banked_sp(), 0x300);
+        assert_eq!(cpu.mem.read32(0x400 - 8).unwrap(), 0x100);
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.pc(), 0x100);
+        assert_eq!(cpu.regs.sp(), 0x300);
+    }
+
+    #[test]
+    fn test_user_stack_access_from_supervisor() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        cpu.regs.set(1, 0x280);
+        cpu.execute(Instruction::Sys {
+            opcode: Opcode::MTUSP,
+            rd: 1,
+            imm: 0,
+            rs: 0,
+        })
+        .unwrap();
+        cpu.execute(Instruction::Sys {
+            opcode: Opcode::MFUSP,
+            rd: 2,
+            imm: 0,
+            rs: 0,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.get(2), 0x280);
+        assert_eq!(cpu.regs.sp(), 0x400);
+
+        // bajar a usuario cambia de pila
+        cpu.regs.set(1, 0x800);
+        cpu.execute(Instruction::Sys {
+            opcode: Opcode::MTSR,
+            rd: 1,
+            imm: 0,
+            rs: 0,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.sp(), 0x280);
+        assert_eq!(cpu.regs.banked_sp(), 0x400);
+    }
+
+    fn paged_cpu() -> CPU {
+        // L1 en 0x1000, L2 en 0x2000; la región virtual 0x0040_0000 (índice
+        // L1 1) mapea la página 0 a 0x3000 (usuario RWX), la 1 a 0x4000
+        // (usuario, solo lectura) y la 2 a 0x5000 (solo supervisor)
+        let mut cpu = CPU::new(0x8000, vec![], 0x8000, 0x0040_0000);
+        cpu.mem.write32(0x1000 + 4, 0x2000 | PTE_VALID).unwrap();
+        let rwx = PTE_VALID | PTE_READ | PTE_WRITE | PTE_EXEC;
+        cpu.mem.write32(0x2000, 0x3000 | rwx | PTE_USER).unwrap();
+        cpu.mem
+            .write32(0x2004, 0x4000 | PTE_VALID | PTE_READ | PTE_USER)
+            .unwrap();
+        cpu.mem.write32(0x2008, 0x5000 | rwx).unwrap();
+
+        cpu.regs.set(1, 0x1000);
+        cpu.execute(Instruction::IO {
+            opcode: Opcode::OUT,
+            rd: 1,
+            port: MMU_PTBR,
+        })
+        .unwrap();
+        cpu.regs.set(1, MMU_ENABLE);
+        cpu.execute(Instruction::IO {
+            opcode: Opcode::OUT,
+            rd: 1,
+            port: MMU_CONTROL,
+        })
+        .unwrap();
+        cpu
+    }
+
+    fn ldw(rd: u8, rs1: u8) -> Instruction {
+        Instruction::Mem {
+            opcode: Opcode::LDW,
+            rd,
+            rs1,
+            imm: 0,
+        }
+    }
+
+    fn stw(rd: u8, rs1: u8) -> Instruction {
+        Instruction::Mem {
+            opcode: Opcode::STW,
+            rd,
+            rs1,
+            imm: 0,
+        }
+    }
+
+    #[test]
+    fn test_mmu_disabled_by_default() {
+        let mut cpu = CPU::new(0x8000, vec![], 0x8000, 0);
+        assert!(!cpu.mmu.borrow().enabled());
+        cpu.mem.write32(0x3010, 0xCAFE).unwrap();
+        cpu.regs.set(2, 0x3010);
+        cpu.execute(ldw(1, 2)).unwrap();
+        assert_eq!(cpu.regs.get(1), 0xCAFE);
+        assert_eq!(cpu.mmu.borrow().misses(), 0);
+    }
+
+    #[test]
+    fn test_mmu_translates_fetch_and_data() {
+        let mut cpu = paged_cpu();
+        // LDW R1, [R2]; STW R1, [R3]
+        cpu.mem.write32(0x3000, ldw(1, 2).encode()).unwrap();
+        cpu.mem.write32(0x3004, stw(1, 3).encode()).unwrap();
+        cpu.mem.write32(0x4010, 0x1234_5678).unwrap();
+        cpu.regs.set(2, 0x0040_1010);
+        cpu.regs.set(3, 0x0040_2020);
+
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.get(1), 0x1234_5678);
+        assert_eq!(cpu.mem.read32(0x5020).unwrap(), 0x1234_5678);
+        assert_eq!(cpu.regs.pc(), 0x0040_0008);
+
+        // una recorrida por página; el resto sale de la TLB
+        let mmu = cpu.mmu.borrow();
+        assert_eq!(mmu.misses(), 3);
+        assert_eq!(mmu.hits(), 1);
+    }
+
+    #[test]
+    fn test_mmu_permissions() {
+        let mut cpu = paged_cpu();
+        cpu.regs.set(2, 0x0040_1000); // solo lectura
+        cpu.regs.set(3, 0x0040_2000); // solo supervisor
+        cpu.regs.set(4, 0x0080_0000); // L1 sin tabla
+
+        assert_eq!(
+            cpu.execute(stw(1, 2)),
+            Err(FaultKind::PageFault { addr: 0x0040_1000 })
+        );
+        assert_eq!(cpu.port_read(MMU_FAULT_ACCESS), Access::Write as u32);
+        assert_eq!(
+            cpu.execute(ldw(1, 4)),
+            Err(FaultKind::PageFault { addr: 0x0080_0000 })
+        );
+        assert_eq!(cpu.port_read(MMU_FAULT_ACCESS), Access::Read as u32);
+        cpu.execute(stw(1, 3)).unwrap();
+
+        // en modo usuario hace falta PTE_USER
+        cpu.regs.set(1, 0x800);
+        cpu.execute(Instruction::Sys {
+            opcode: Opcode::MTSR,
+            rd: 1,
+            imm: 0,
+            rs: 0,
+        })
+        .unwrap();
+        cpu.execute(ldw(5, 2)).unwrap();
+        assert_eq!(
+            cpu.execute(ldw(5, 3)),
+            Err(FaultKind::PageFault { addr: 0x0040_2000 })
+        );
+    }
+
+    #[test]
+    fn test_mmu_page_fault_is_precise() {
+        let mut cpu = paged_cpu();
+        cpu.mem.write32(0x3000, ldw(1, 2).encode()).unwrap();
+        cpu.regs.set(1, 0);
+        cpu.regs.set(2, 0x0040_3000); // página 3, aún sin mapear
+        cpu.regs.set_sp(0x0040_2100);
+
+        let vector = FaultKind::PageFault { addr: 0 }.vector() as u32;
+        cpu.pic.borrow_mut().set_vector_base(0x0040_2000);
+        cpu.mem.write32(0x5000 + vector * 4, 0x0040_0100).unwrap();
+        cpu.mem
+            .write32(0x3100, (Opcode::RETI as u32) << 24)
+            .unwrap();
+        cpu.trap_faults = true;
+
+        match cpu.step().unwrap() {
+            StepOutcome::Exception(fault) => {
+                assert_eq!(fault.pc, 0x0040_0000);
+                assert_eq!(fault.kind, FaultKind::PageFault { addr: 0x0040_3000 });
+            }
+            other => panic!("se esperaba un fallo de página, no {:?}", other),
+        }
+        assert_eq!(cpu.port_read(PIC_FAULT_CAUSE), 7);
+        assert_eq!(cpu.port_read(PIC_FAULT_ADDR), 0x0040_3000);
+        assert_eq!(cpu.regs.get(1), 0);
+
+        // el "sistema" mapea la página y la instrucción se repite
+        cpu.mem.write32(0x6000, 77).unwrap();
+        cpu.mem
+            .write32(0x200C, 0x6000 | PTE_VALID | PTE_READ | PTE_USER)
+            .unwrap();
+        cpu.step().unwrap(); // RETI
+        assert_eq!(cpu.regs.pc(), 0x0040_0000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.get(1), 77);
+    }
+
+    #[test]
+    fn test_mmu_tlb_needs_flush() {
+        let mut cpu = paged_cpu();
+        cpu.mem.write32(0x4000, 1).unwrap();
+        cpu.mem.write32(0x6000, 2).unwrap();
+        cpu.regs.set(2, 0x0040_1000);
+        cpu.execute(ldw(1, 2)).unwrap();
+        assert_eq!(cpu.regs.get(1), 1);
+
+        // la TLB guarda la traducción vieja hasta que se invalida la página
+        cpu.mem
+            .write32(0x2004, 0x6000 | PTE_VALID | PTE_READ)
+            .unwrap();
+        cpu.execute(ldw(1, 2)).unwrap();
+        assert_eq!(cpu.regs.get(1), 1);
+
+        cpu.execute(Instruction::IO {
+            opcode: Opcode::OUT,
+            rd: 2,
+            port: MMU_FLUSH,
+        })
+        .unwrap();
+        cpu.execute(ldw(1, 2)).unwrap();
+        assert_eq!(cpu.regs.get(1), 2);
+    }
+
+    /// Generador xorshift para las pruebas de ida y vuelta, sin dependencias.
+    struct XorShift(u32);
+
+    impl XorShift {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_isa_encode_decode_round_trip() {
+        let mut rng = XorShift(0x1234_5678);
+        for info in ISA {
+            let layout = info.format.layout();
+            for _ in 0..256 {
+                let mut field = |f: Option<Field>| f.map_or(0, |f| rng.next() & f.mask());
+                let rd = field(layout.rd) as u8;
+                let rs1 = field(layout.rs1) as u8;
+                let rs2 = field(layout.rs2) as u8;
+                let imm = layout
+                    .imm
+                    .map_or(0, |f| info.imm.extend(field(Some(f)), f.bits));
+
+                let instr = Instruction::from_parts(info.opcode, rd, rs1, rs2, imm);
+                let decoded = Instruction::decode(instr.encode()).unwrap();
+                assert_eq!(decoded, instr, "{}", info.mnemonic);
+            }
+        }
+    }
+
+    #[test]
+    fn test_isa_decode_encode_round_trip() {
+        let mut rng = XorShift(0x9E37_79B9);
+        for info in ISA {
+            let layout = info.format.layout();
+            let used = [layout.rd, layout.rs1, layout.rs2, layout.imm]
+                .into_iter()
+                .flatten()
+                .fold(0, |acc, f| acc | (f.mask() << f.shift));
+            for _ in 0..256 {
+                let raw = OPCODE.insert(info.opcode as u32) | (rng.next() & used);
+                let instr = Instruction::decode(raw).unwrap();
+                assert_eq!(instr.opcode(), info.opcode);
+                assert_eq!(instr.encode(), raw, "{}", info.mnemonic);
+            }
+        }
+    }
+
+    #[test]
+    fn test_isa_unknown_opcodes_are_illegal() {
+        for byte in 0..=u8::MAX {
+            let raw = OPCODE.insert(byte as u32);
+            let known = ISA.iter().any(|info| info.opcode as u8 == byte);
+            assert_eq!(Instruction::decode(raw).is_ok(), known, "{:#04X}", byte);
+        }
+    }
+
+    #[test]
+    fn test_addi_negative_immediate_executes() {
+        let word = Instruction::I {
+            opcode: Opcode::ADDI,
+            rd: 1,
+            rs1: 2,
+            imm: -5i32 as u32,
+        }
+        .encode();
+        let mut cpu = CPU::new(1024, word.to_le_bytes().to_vec(), 0, 1024);
+        cpu.regs.set(2, 7);
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.get(1), 2);
+    }
+
+    // Ejecuta un programa en RAM (sin esperas por defecto) y devuelve los ciclos
+    fn program_cycles(cpu: &mut CPU, program: &[Instruction]) -> u64 {
+        for (i, instr) in program.iter().enumerate() {
+            cpu.mem
+                .write32(0x100 + i as u32 * 4, instr.encode())
+                .unwrap();
+        }
+        cpu.regs.set_pc(0x100);
+        let start = cpu.cycle_count;
+        for _ in program {
+            cpu.step().unwrap();
+        }
+        cpu.cycle_count - start
+    }
+
+    #[test]
+    fn test_timing_base_costs() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        cpu.regs.set(2, 100);
+        cpu.regs.set(3, 7);
+        let r = |opcode| Instruction::R {
+            opcode,
+            rd: 1,
+            rs1: 2,
+            rs2: 3,
+        };
+
+        assert_eq!(program_cycles(&mut cpu, &[r(Opcode::ADD)]), 1);
+        assert_eq!(program_cycles(&mut cpu, &[r(Opcode::MUL)]), 3);
+        assert_eq!(program_cycles(&mut cpu, &[r(Opcode::DIV)]), 12);
+
+        cpu.timing.set_base_cycles(Opcode::MUL, 1);
+        assert_eq!(program_cycles(&mut cpu, &[r(Opcode::MUL)]), 1);
+    }
+
+    #[test]
+    fn test_timing_register_list_per_register() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        let sys = |opcode, imm| Instruction::Sys {
+            opcode,
+            rd: 0,
+            imm,
+            rs: 0,
+        };
+
+        // PUSHM {R1-R8, LR}: base más un ciclo por palabra
+        let list = 0xFF | REGLIST_LR;
+        assert_eq!(program_cycles(&mut cpu, &[sys(Opcode::PUSHM, list)]), 10);
+        assert_eq!(program_cycles(&mut cpu, &[sys(Opcode::POPM, 0b1)]), 2);
+    }
+
+    #[test]
+    fn test_timing_block_per_byte() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        let block = |opcode| Instruction::R {
+            opcode,
+            rd: 1,
+            rs1: 2,
+            rs2: 3,
+        };
+        let set = |cpu: &mut CPU, a, b, len| {
+            cpu.regs.set(1, a);
+            cpu.regs.set(2, b);
+            cpu.regs.set(3, len);
+        };
+
+        // MEMSET de 16 bytes: base más un ciclo por byte
+        set(&mut cpu, 0x200, 0, 16);
+        assert_eq!(program_cycles(&mut cpu, &[block(Opcode::MEMSET)]), 17);
+        cpu.timing.block_byte_cycles = 2;
+        set(&mut cpu, 0x200, 0, 16);
+        assert_eq!(program_cycles(&mut cpu, &[block(Opcode::MEMSET)]), 33);
+
+        // MEMCMP cobra hasta el byte que difiere, incluido
+        cpu.mem.write8(0x283, 1).unwrap();
+        set(&mut cpu, 0x200, 0x280, 16);
+        assert_eq!(program_cycles(&mut cpu, &[block(Opcode::MEMCMP)]), 9);
+    }
+
+    #[test]
+    fn test_timing_wait_states() {
+        let mut cpu = CPU::new(1024, vec![0; 16], 0x400, 0);
+        cpu.timing.ram_wait = 2;
+        cpu.timing.rom_wait = 5;
+        cpu.regs.set(2, 1024); // base de la ROM
+
+        let ldw = Instruction::Mem {
+            opcode: Opcode::LDW,
+            rd: 1,
+            rs1: 2,
+            imm: 0,
+        };
+        // 1 base + búsqueda en RAM (2) + lectura en ROM (5)
+        assert_eq!(program_cycles(&mut cpu, &[ldw]), 8);
+
+        cpu.regs.set(2, 0x200);
+        // 1 base + búsqueda en RAM (2) + lectura en RAM (2)
+        assert_eq!(program_cycles(&mut cpu, &[ldw]), 5);
+
+        // Los accesos del anfitrión entre pasos no se cobran
+        cpu.mem.read32(1024).unwrap();
+        let out = Instruction::IO {
+            opcode: Opcode::OUT,
+            rd: 1,
+            port: 0x10,
+        };
+        assert_eq!(program_cycles(&mut cpu, &[out]), 1 + 2 + 1);
+    }
+
+    #[test]
+    fn test_timing_clock() {
+        let timing = Timing::default();
+        assert_eq!(timing.clock_hz, DEFAULT_CLOCK_HZ);
+
+        let timing = Timing::new(6_000_000);
+        assert_eq!(timing.cycles_per_frame(60), 100_000);
+        assert_eq!(timing.cycles_to_secs(3_000_000), 0.5);
+    }
+
+    #[derive(Default)]
+    struct Latch {
+        value: u32,
+    }
+
+    impl Peripheral for Latch {
+        fn handles_port(&self, port: u16) -> bool {
+            port == 0x7000
+        }
+
+        fn read(&self, _port: u16) -> u32 {
+            self.value
+        }
+
+        fn write(&mut self, _port: u16, value: u32) {
+            self.value = value;
+        }
+
+        fn save_state(&self, out: &mut StateWriter) {
+            out.write_u32(self.value);
+        }
+
+        fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+            self.value = input.read_u32()?;
+            Ok(())
+        }
+    }
+
+    // Periférico sin estado: usa los ganchos por defecto
+    struct Stateless;
+
+    impl Peripheral for Stateless {
+        fn handles_port(&self, port: u16) -> bool {
+            port == 0x7001
+        }
+
+        fn read(&self, _port: u16) -> u32 {
+            0
+        }
+
+        fn write(&mut self, _port: u16, _value: u32) {}
+    }
+
+    fn snapshot_cpu() -> (CPU, Rc<RefCell<Latch>>) {
+        let mut cpu = CPU::new(1024, vec![0; 8], 0x400, 0x100);
+        let latch = Rc::new(RefCell::new(Latch::default()));
+        cpu.io.register_peripheral(latch.clone());
+        cpu.io.register_peripheral(Rc::new(RefCell::new(Stateless)));
+        (cpu, latch)
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let (mut cpu, latch) = snapshot_cpu();
+        cpu.regs.set(5, 0xDEAD_BEEF);
+        cpu.regs.fset(3, 1.5);
+        cpu.regs.set_lr(0x1234);
+        cpu.regs.set_flags(0x401);
+        cpu.mem.write32(0x40, 0xCAFE_F00D).unwrap();
+        cpu.io.write(0x10, 77);
+        cpu.io.write(0x7000, 9);
+        cpu.pic.borrow_mut().set_mask(0b101);
+        cpu.irq_line(2).raise();
+        cpu.cycle_count = 12345;
+
+        let state = cpu.save_state();
+
+        cpu.regs.set(5, 0);
+        cpu.regs.fset(3, 0.0);
+        cpu.regs.set_lr(0);
+        cpu.regs.set_flags(0);
+        cpu.regs.set_pc(0);
+        cpu.mem.write32(0x40, 0).unwrap();
+        cpu.io.write(0x10, 0);
+        cpu.io.write(0x7000, 0);
+        cpu.pic.borrow_mut().set_mask(0);
+        cpu.pic.borrow_mut().clear(2);
+        cpu.cycle_count = 0;
+        cpu.halted = true;
+
+        cpu.load_state(&state).unwrap();
+
+        assert_eq!(cpu.regs.get(5), 0xDEAD_BEEF);
+        assert_eq!(cpu.regs.fget(3), 1.5);
+        assert_eq!(cpu.regs.lr(), 0x1234);
+        assert_eq!(cpu.regs.flags(), 0x401);
+        assert_eq!(cpu.regs.pc(), 0x100);
+        assert_eq!(cpu.regs.sp(), 0x400);
+        assert_eq!(cpu.mem.read32(0x40).unwrap(), 0xCAFE_F00D);
+        assert_eq!(cpu.io.read(0x10), 77);
+        assert_eq!(latch.borrow().value, 9);
+        assert_eq!(cpu.pic.borrow().mask(), 0b101);
+        assert_eq!(cpu.pic.borrow().pending(), 0b100);
+        assert_eq!(cpu.cycle_count, 12345);
+        assert!(!cpu.halted);
+        assert_eq!(cpu.save_state(), state);
+    }
+
+    #[test]
+    fn test_snapshot_rejects_other_machines() {
+        let (cpu, _) = snapshot_cpu();
+        let state = cpu.save_state();
+
+        let mut bigger = CPU::new(2048, vec![0; 8], 0x400, 0x100);
+        bigger.regs.set(1, 42);
+        assert!(matches!(
+            bigger.load_state(&state),
+            Err(SnapshotError::Mismatch(_))
+        ));
+        assert_eq!(bigger.regs.get(1), 42); // no queda a medio restaurar
+
+        let mut fewer = CPU::new(1024, vec![0; 8], 0x400, 0x100);
+        assert!(matches!(
+            fewer.load_state(&state),
+            Err(SnapshotError::Mismatch("peripheral count"))
+        ));
+
+        let (mut same, _) = snapshot_cpu();
+        assert!(matches!(
+            same.load_state(b"nope"),
+            Err(SnapshotError::BadMagic)
+        ));
+        assert!(matches!(
+            same.load_state(&state[..state.len() - 1]),
+            Err(SnapshotError::UnexpectedEof)
+        ));
+
+        let mut future = state.clone();
+        future[4..8].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            same.load_state(&future),
+            Err(SnapshotError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn test_snapshot_resumes_execution() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0x100);
+        let addi = Instruction::I {
+            opcode: Opcode::ADDI,
+            rd: 1,
+            rs1: 1,
+            imm: 3,
+        };
+        for i in 0..4 {
+            cpu.mem.write32(0x100 + i * 4, addi.encode()).unwrap();
+        }
+
+        cpu.step().unwrap();
+        let state = cpu.save_state();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        let expected = (cpu.regs.get(1), cpu.regs.pc(), cpu.cycle_count);
+
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.regs.get(1), 3);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!((cpu.regs.get(1), cpu.regs.pc(), cpu.cycle_count), expected);
+    }
+
+    #[test]
+    fn test_icache_self_modifying_code() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0x100);
+        let addi = |imm| Instruction::I {
+            opcode: Opcode::ADDI,
+            rd: 1,
+            rs1: 1,
+            imm,
+        };
+        // 0x100: ADDI R1, R1, 1 ; 0x104: STW R2, [R3] ; 0x108: JMP -2
+        cpu.mem.write32(0x100, addi(1).encode()).unwrap();
+        let stw = Instruction::Mem {
+            opcode: Opcode::STW,
+            rd: 2,
+            rs1: 3,
+            imm: 0,
+        };
+        cpu.mem.write32(0x104, stw.encode()).unwrap();
+        let jmp = Instruction::J {
+            opcode: Opcode::JMP,
+            offset: -2i32 as u32 & 0xFF_FFFF,
+        };
+        cpu.mem.write32(0x108, jmp.encode()).unwrap();
+        cpu.regs.set(2, addi(100).encode());
+        cpu.regs.set(3, 0x100);
+
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.regs.get(1), 1);
+        assert_eq!(cpu.regs.pc(), 0x100);
+
+        // La instrucción reescrita por el STW se vuelve a decodificar
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.get(1), 101);
+
+        // También cuando escribe el anfitrión
+        cpu.mem.write32(0x100, addi(7).encode()).unwrap();
+        cpu.regs.set_pc(0x100);
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.get(1), 108);
+    }
+
+    #[test]
+    fn test_icache_rom_hits_and_timing() {
+        let addi = Instruction::I {
+            opcode: Opcode::ADDI,
+            rd: 1,
+            rs1: 1,
+            imm: 1,
+        };
+        let jmp = Instruction::J {
+            opcode: Opcode::JMP,
+            offset: -1i32 as u32 & 0xFF_FFFF,
+        };
+        let rom: Vec<u8> = [addi, jmp]
+            .iter()
+            .flat_map(|i| i.encode().to_le_bytes())
+            .collect();
+
+        let run = |cached: bool| {
+            let mut cpu = CPU::new(1024, rom.clone(), 0x400, 1024);
+            cpu.icache.enabled = cached;
+            for _ in 0..100 {
+                cpu.step().unwrap();
+            }
+            (cpu.regs.get(1), cpu.cycle_count, cpu.icache.hits())
+        };
+
+        let (r1, cycles, hits) = run(true);
+        assert_eq!(hits, 98);
+        assert_eq!(run(false), (r1, cycles, 0));
+    }
+
+    #[test]
+    fn test_icache_invalidated_by_load_state() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0x100);
+        let addi = |imm| Instruction::I {
+            opcode: Opcode::ADDI,
+            rd: 1,
+            rs1: 1,
+            imm,
+        };
+        cpu.mem.write32(0x100, addi(5).encode()).unwrap();
+        let state = cpu.save_state();
+
+        cpu.mem.write32(0x100, addi(1).encode()).unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.get(1), 1);
+
+        cpu.load_state(&state).unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.regs.get(1), 5);
+    }
+
+    // Bloque de registros visible por MMIO y por puertos a la vez. Guarda el
+    // ancho del último acceso MMIO.
+    #[derive(Default)]
+    struct RegisterBlock {
+        regs: [u32; 4],
+        last_size: u32,
+    }
+
+    impl Mmio for RegisterBlock {
+        fn mmio_read(&self, offset: u32, _size: u32) -> u32 {
+            self.regs[(offset / 4) as usize] >> ((offset % 4) * 8)
+        }
+
+        fn mmio_write(&mut self, offset: u32, size: u32, value: u32) {
+            self.regs[(offset / 4) as usize] = value;
+            self.last_size = size;
+        }
+    }
+
+    impl Peripheral for RegisterBlock {
+        fn handles_port(&self, port: u16) -> bool {
+            (0x7010..0x7014).contains(&port)
+        }
+
+        fn read(&self, port: u16) -> u32 {
+            self.regs[(port - 0x7010) as usize]
+        }
+
+        fn write(&mut self, port: u16, value: u32) {
+            self.regs[(port - 0x7010) as usize] = value;
+        }
+    }
+
+    #[test]
+    fn test_mmio_routes_loads_and_stores() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        let block = Rc::new(RefCell::new(RegisterBlock::default()));
+        cpu.mem.map_mmio(0x8000_0000, 16, block.clone());
+        cpu.io.register_peripheral(block.clone());
+
+        cpu.regs.set(1, 0xDEAD_BEEF);
+        cpu.regs.set(2, 0x8000_0000);
+        cpu.execute(Instruction::Mem {
+            opcode: Opcode::STW,
+            rd: 1,
+            rs1: 2,
+            imm: 8,
+        })
+        .unwrap();
+        assert_eq!(block.borrow().regs[2], 0xDEAD_BEEF);
+        assert_eq!(block.borrow().last_size, 4);
+
+        // el mismo registro por puerto
+        cpu.execute(Instruction::IO {
+            opcode: Opcode::IN,
+            rd: 3,
+            port: 0x7012,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.get(3), 0xDEAD_BEEF);
+
+        block.borrow_mut().regs[1] = 0x1234_5678;
+        cpu.execute(Instruction::Mem {
+            opcode: Opcode::LDHU,
+            rd: 4,
+            rs1: 2,
+            imm: 6,
+        })
+        .unwrap();
+        assert_eq!(cpu.regs.get(4), 0x1234);
+
+        cpu.execute(Instruction::Mem {
+            opcode: Opcode::STB,
+            rd: 1,
+            rs1: 2,
+            imm: 0,
+        })
+        .unwrap();
+        assert_eq!(block.borrow().regs[0], 0xEF);
+        assert_eq!(block.borrow().last_size, 1);
+
+        // fuera de la región sigue siendo un error de bus
+        cpu.regs.set(2, 0x8000_0010);
+        assert_eq!(
+            cpu.execute(Instruction::Mem {
+                opcode: Opcode::LDW,
+                rd: 4,
+                rs1: 2,
+                imm: 0,
+            }),
+            Err(FaultKind::BusError { addr: 0x8000_0010 })
+        );
+    }
+
+    #[test]
+    fn test_mmio_overrides_ram_and_costs_io_wait() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        let block = Rc::new(RefCell::new(RegisterBlock::default()));
+        block.borrow_mut().regs[0] = 7;
+        cpu.mem.write32(0x200, 99).unwrap();
+        cpu.mem.map_mmio(0x200, 16, block);
+        cpu.timing.io_wait = 3;
+
+        cpu.regs.set(2, 0x200);
+        let ldw = Instruction::Mem {
+            opcode: Opcode::LDW,
+            rd: 1,
+            rs1: 2,
+            imm: 0,
+        };
+        // 1 base + búsqueda en RAM (0) + lectura MMIO (3)
+        assert_eq!(program_cycles(&mut cpu, &[ldw]), 4);
+        assert_eq!(cpu.regs.get(1), 7);
+        match &cpu.mem.regions()[0].kind {
+            RegionKind::Ram(ram) => assert_eq!(ram.read32(0x200), 99),
+            _ => panic!("se esperaba RAM en 0"),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "regiones MMIO solapadas")]
+    fn test_mmio_overlap_rejected() {
+        let mut cpu = CPU::new(1024, vec![], 0x400, 0);
+        let block = Rc::new(RefCell::new(RegisterBlock::default()));
+        cpu.mem.map_mmio(0x1000, 16, block.clone());
+        cpu.mem.map_mmio(0x100C, 16, block);
+    }
+
+    #[test]
+    fn test_regions_sharing_a_page() {
+        // RAM y ROM se reparten una página y dos bloques MMIO otra
+        let mut map = MemoryMap::new(0x1800);
+        map.add_ram(0, 0x1800);
+        map.add_rom(0x1800, vec![0xAA; 0x800]);
+        let mut mem = MemoryBus::from_map(map);
+        let first = Rc::new(RefCell::new(RegisterBlock::default()));
+        let second = Rc::new(RefCell::new(RegisterBlock::default()));
+        mem.map_mmio(0x4000, 16, first.clone());
+        mem.map_mmio(0x4010, 16, second.clone());
+
+        mem.write32(0x17FC, 1).unwrap();
+        assert_eq!(mem.read32(0x17FC).unwrap(), 1);
+        assert_eq!(mem.read8(0x1800).unwrap(), 0xAA);
+        assert!(mem.write8(0x1800, 0).is_err());
+
+        mem.write32(0x4010, 5).unwrap();
+        assert_eq!(second.borrow().regs[0], 5);
+        assert_eq!(first.borrow().regs[0], 0);
+        assert!(mem.is_mmio(0x401C));
+        assert!(!mem.is_mmio(0x4020));
+    }
+
+    fn rom_image(program: &[Instruction]) -> Vec<u8> {
+        program
+            .iter()
+            .flat_map(|instr| instr.encode().to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_memory_map_rom_low_ram_high() {
+        // ROM en 0 con el vector de reset y RAM arriba
+        let program = [
+            Instruction::Sys {
+                opcode: Opcode::LUI,
+                rd: 2,
+                imm: 0x8000,
+                rs: 0,
+            },
+            Instruction::I {
+                opcode: Opcode::ADDI,
+                rd: 1,
+                rs1: 0,
+                imm: 42,
+            },
+            Instruction::Mem {
+                opcode: Opcode::STW,
+                rd: 1,
+                rs1: 2,
+                imm: 4,
+            },
+            Instruction::Mem {
+                opcode: Opcode::PUSH,
+                rd: 1,
+                rs1: 0,
+                imm: 0,
+            },
+            Instruction::J {
+                opcode: Opcode::HALT,
+                offset: 0,
+            },
+        ];
+        let mut map = MemoryMap::new(0);
+        map.add_rom(0, rom_image(&program));
+        map.add_ram(0x8000_0000, 0x1000);
+        let mut cpu = CPU::with_memory(MemoryBus::from_map(map), 0x8000_1000);
+        assert_eq!(cpu.regs.pc(), 0);
+        assert_eq!(cpu.mem.ram_size(), 0x1000);
+        assert_eq!(cpu.mem.rom_size(), program.len() * 4);
+
+        while !cpu.halted {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.mem.read32(0x8000_0004).unwrap(), 42);
+        assert_eq!(cpu.mem.read32(0x8000_0FFC).unwrap(), 42);
+        assert_eq!(cpu.mem.write32(0, 1), Err(FaultKind::RomWrite { addr: 0 }));
+        assert_eq!(
+            cpu.mem.read32(0x1000),
+            Err(FaultKind::BusError { addr: 0x1000 })
+        );
+
+        // el estado guarda la RAM de todas las regiones
+        let state = cpu.save_state();
+        cpu.mem.write32(0x8000_0004, 0).unwrap();
+        cpu.load_state(&state).unwrap();
+        assert_eq!(cpu.mem.read32(0x8000_0004).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_memory_map_mirrors() {
+        // 2 KiB de RAM repetidos hasta 8 KiB y una ROM repetida dos veces
+        let mut map = MemoryMap::new(0);
+        map.add_ram(0, 0x800);
+        map.add_mirror(0x800, 0x1800, 0);
+        map.add_rom(0x4000, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        map.add_mirror(0x4008, 8, 0x4000);
+        let mut mem = MemoryBus::from_map(map);
+
+        mem.write32(0x10, 0xABCD).unwrap();
+        assert_eq!(mem.read32(0x810).unwrap(), 0xABCD);
+        assert_eq!(mem.read32(0x1810).unwrap(), 0xABCD);
+        mem.write16(0x1FFE, 0x55AA).unwrap();
+        assert_eq!(mem.read16(0x7FE).unwrap(), 0x55AA);
+        assert_eq!(mem.read32(0x400C).unwrap(), 0x0807_0605);
+        assert_eq!(
+            mem.write8(0x4008, 0),
+            Err(FaultKind::RomWrite { addr: 0x4008 })
+        );
+
+        // escribir por un alias invalida el código visto por el otro
+        let version = mem.code_version(0x810);
+        mem.write32(0x10, 0).unwrap();
+        assert_ne!(mem.code_version(0x810), version);
+        assert_eq!(mem.code_version(0x4008), 0);
+
+        // los accesos por el espejo cuentan como su región de destino
+        mem.take_accesses();
+        mem.read32(0x1000).unwrap();
+        mem.read32(0x400C).unwrap();
+        let accesses = mem.take_accesses();
+        assert_eq!((accesses.ram, accesses.rom), (1, 1));
+    }
+
+    #[test]
+    fn test_memory_map_unmapped_policies() {
+        let mut map = MemoryMap::new(0);
+        map.add_ram(0, 0x100);
+        map.add_unmapped(0x1000, 0x1000, UnmappedPolicy::Fault);
+        map.unmapped = UnmappedPolicy::OpenBus(0xFFFF_FFFF);
+        let mut mem = MemoryBus::from_map(map);
+
+        assert_eq!(mem.read32(0x200).unwrap(), 0xFFFF_FFFF);
+        assert_eq!(mem.read8(0x201).unwrap(), 0xFF);
+        assert_eq!(mem.write32(0x200, 5), Ok(()));
+        assert_eq!(mem.read32(0x200).unwrap(), 0xFFFF_FFFF);
+        assert_eq!(
+            mem.read32(0x1800),
+            Err(FaultKind::BusError { addr: 0x1800 })
+        );
+        // la alineación se comprueba igual
+        assert_eq!(
+            mem.read32(0x202),
+            Err(FaultKind::Misaligned { addr: 0x202 })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "regiones solapadas")]
+    fn test_memory_map_overlap_rejected() {
+        let mut map = MemoryMap::new(0);
+        map.add_ram(0, 0x1000);
+        map.add_rom(0xFFC, vec![0; 8]);
+    }
+
+    // Bus de pruebas: memoria dispersa en un HashMap que registra cada acceso
+    #[derive(Default)]
+    struct LoggingBus {
+        bytes: HashMap<u32, u8>,
+        log: Vec<(char, u32)>,
+        ports: Vec<(u16, u32)>,
+    }
+
+    impl LoggingBus {
+        fn load(&mut self, addr: u32, program: &[Instruction]) {
+            for (i, instr) in program.iter().enumerate() {
+                for (j, byte) in instr.encode().to_le_bytes().into_iter().enumerate() {
+                    self.bytes.insert(addr + (i * 4 + j) as u32, byte);
+                }
+            }
+        }
+
+        fn get(&self, addr: u32, size: u32) -> u32 {
+            (0..size).fold(0, |acc, i| {
+                acc | (*self.bytes.get(&(addr + i)).unwrap_or(&0) as u32) << (8 * i)
+            })
+        }
+
+        fn set(&mut self, addr: u32, size: u32, value: u32) {
+            for i in 0..size {
+                self.bytes.insert(addr + i, (value >> (8 * i)) as u8);
+            }
+        }
+    }
+
+    impl Bus for LoggingBus {
+        fn read8(&mut self, addr: u32) -> Result<u8, FaultKind> {
+            self.log.push(('r', addr));
+            Ok(self.get(addr, 1) as u8)
+        }
+
+        fn read16(&mut self, addr: u32) -> Result<u16, FaultKind> {
+            self.log.push(('r', addr));
+            Ok(self.get(addr, 2) as u16)
+        }
+
+        fn read32(&mut self, addr: u32) -> Result<u32, FaultKind> {
+            self.log.push(('r', addr));
+            Ok(self.get(addr, 4))
+        }
+
+        fn write8(&mut self, addr: u32, value: u8) -> Result<(), FaultKind> {
+            self.log.push(('w', addr));
+            self.set(addr, 1, value as u32);
+            Ok(())
+        }
+
+        fn write16(&mut self, addr: u32, value: u16) -> Result<(), FaultKind> {
+            self.log.push(('w', addr));
+            self.set(addr, 2, value as u32);
+            Ok(())
+        }
+
+        fn write32(&mut self, addr: u32, value: u32) -> Result<(), FaultKind> {
+            self.log.push(('w', addr));
+            self.set(addr, 4, value);
+            Ok(())
+        }
+
+        fn port_read(&mut self, port: u16) -> u32 {
+            port as u32
+        }
+
+        fn port_write(&mut self, port: u16, value: u32) {
+            self.ports.push((port, value));
+        }
+    }
+
+    #[test]
+    fn test_custom_bus_runs_program() {
+        let mut bus = LoggingBus::default();
+        bus.load(
+            0x8000_0000,
+            &[
+                ldw(1, 2),
+                stw(1, 3),
+                Instruction::IO {
+                    opcode: Opcode::OUT,
+                    rd: 1,
+                    port: 0x7000,
+                },
+                Instruction::IO {
+                    opcode: Opcode::IN,
+                    rd: 4,
+                    port: 0x7001,
+                },
+                Instruction::Sys {
+                    opcode: Opcode::HALT,
+                    rd: 0,
+                    imm: 0,
+                    rs: 0,
+                },
+            ],
+        );
+        bus.set(0x10, 4, 0xCAFE);
+        let mut cpu = CPU::with_bus(bus, 0xF000_0000, 0x8000_0000);
+        cpu.regs.set(2, 0x10);
+        cpu.regs.set(3, 0x20);
+
+        while !cpu.halted {
+            cpu.step().unwrap();
+        }
+
+        assert_eq!(cpu.bus.get(0x20, 4), 0xCAFE);
+        assert_eq!(cpu.regs.get(4), 0x7001);
+        assert_eq!(cpu.bus.ports, vec![(0x7000, 0xCAFE)]);
+        assert_eq!(
+            cpu.bus.log,
+            vec![
+                ('r', 0x8000_0000),
+                ('r', 0x10),
+                ('r', 0x8000_0004),
+                ('w', 0x20),
+                ('r', 0x8000_0008),
+                ('r', 0x8000_000C),
+                ('r', 0x8000_0010),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_custom_bus_keeps_on_chip_devices() {
+        let mut bus = LoggingBus::default();
+        bus.set(0x80 + 4, 4, 0x200);
+        let mut cpu = CPU::with_bus(bus, 0x1000, 0x100);
+
+        // los puertos del PIC no llegan al bus
+        cpu.port_write(PIC_VECTOR_BASE, 0x80);
+        cpu.port_write(PIC_MASK, 0b10);
+        assert_eq!(cpu.port_read(PIC_MASK), 0b10);
+        assert!(cpu.bus.ports.is_empty());
+
+        cpu.regs.set_flags(0x400);
+        cpu.irq_line(1).raise();
+        assert_eq!(cpu.step(), Ok(StepOutcome::Interrupt(1)));
+        assert_eq!(cpu.regs.pc(), 0x200);
+        assert_eq!(cpu.bus.get(0x1000 - 8, 4), 0x100);
+    }
 }
@@ -0,0 +1,111 @@
+//! Modelo de tiempo de la CPU.
+//!
+//! Cada instrucción cuesta un número base de ciclos según su opcode, más los
+//! estados de espera de cada acceso a memoria (incluida la búsqueda de la
+//! instrucción) según la región a la que va, más los de E/S por puerto.
+
+use crate::instruction::Opcode;
+use crate::isa::ISA;
+use crate::memory::AccessCounts;
+
+pub const DEFAULT_CLOCK_HZ: u64 = 10_000_000;
+
+/// Ciclos que cuesta entrar en un vector de interrupción o excepción, sin
+/// contar los accesos a memoria para leer el vector y apilar PC y FLAGS.
+pub const VECTOR_ENTRY_CYCLES: u32 = 2;
+
+/// Coste base por defecto de cada opcode.
+pub fn default_base_cycles(opcode: Opcode) -> u32 {
+    match opcode {
+        Opcode::MUL | Opcode::MULI => 3,
+        Opcode::MULH | Opcode::MULHU | Opcode::MULHSU => 3,
+        Opcode::DIV | Opcode::MOD | Opcode::DIVI | Opcode::MODI => 12,
+        Opcode::DIVU | Opcode::MODU | Opcode::DIVUI | Opcode::MODUI => 12,
+
+        Opcode::FADD | Opcode::FSUB | Opcode::FCMP => 2,
+        Opcode::FEQ | Opcode::FLT | Opcode::FGT => 2,
+        Opcode::FTOI | Opcode::ITOF | Opcode::FTOIU | Opcode::UTOF => 2,
+        Opcode::FMIN | Opcode::FMAX => 2,
+        Opcode::FMUL | Opcode::FMA => 4,
+        Opcode::FDIV | Opcode::FSQRT => 16,
+
+        Opcode::FADDD | Opcode::FSUBD | Opcode::FCMPD => 2,
+        Opcode::FEQD | Opcode::FLTD | Opcode::FGTD => 2,
+        Opcode::FMIND | Opcode::FMAXD => 2,
+        Opcode::DTOI | Opcode::DTOIU | Opcode::ITOD | Opcode::UTOD => 2,
+        Opcode::DTOF | Opcode::FTOD => 2,
+        Opcode::FMULD | Opcode::FMAD => 6,
+        Opcode::FDIVD | Opcode::FSQRTD => 30,
+
+        // Los saltos vacían la búsqueda de la siguiente instrucción
+        Opcode::CALL | Opcode::RET | Opcode::RETI | Opcode::SYSCALL => 2,
+        Opcode::JR | Opcode::CALLR => 2,
+        Opcode::ENTER | Opcode::LEAVE => 2,
+
+        _ => 1,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Timing {
+    /// Frecuencia del reloj emulado, en ciclos por segundo.
+    pub clock_hz: u64,
+    /// Estados de espera por acceso a RAM.
+    pub ram_wait: u32,
+    /// Estados de espera por acceso a ROM.
+    pub rom_wait: u32,
+    /// Estados de espera por cada IN/OUT y por cada acceso MMIO.
+    pub io_wait: u32,
+    /// Ciclos por byte de MEMCPY, MEMSET y MEMCMP, además del coste base.
+    pub block_byte_cycles: u32,
+    base: [u32; 256],
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self::new(DEFAULT_CLOCK_HZ)
+    }
+}
+
+impl Timing {
+    pub fn new(clock_hz: u64) -> Self {
+        let mut base = [1; 256];
+        for info in ISA {
+            base[info.opcode as usize] = default_base_cycles(info.opcode);
+        }
+
+        Self {
+            clock_hz,
+            ram_wait: 0,
+            rom_wait: 1,
+            io_wait: 1,
+            block_byte_cycles: 1,
+            base,
+        }
+    }
+
+    pub fn base_cycles(&self, opcode: Opcode) -> u32 {
+        self.base[opcode as usize]
+    }
+
+    pub fn set_base_cycles(&mut self, opcode: Opcode, cycles: u32) {
+        self.base[opcode as usize] = cycles;
+    }
+
+    /// Ciclos de espera que suman los accesos a memoria realizados.
+    pub fn memory_cycles(&self, accesses: AccessCounts) -> u64 {
+        accesses.ram * self.ram_wait as u64
+            + accesses.rom * self.rom_wait as u64
+            + accesses.mmio * self.io_wait as u64
+    }
+
+    /// Ciclos que caben en un fotograma a `fps` fotogramas por segundo.
+    pub fn cycles_per_frame(&self, fps: u32) -> u64 {
+        self.clock_hz / fps as u64
+    }
+
+    /// Tiempo emulado, en segundos, que representan `cycles` ciclos.
+    pub fn cycles_to_secs(&self, cycles: u64) -> f64 {
+        cycles as f64 / self.clock_hz as f64
+    }
+}
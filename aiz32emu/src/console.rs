@@ -1,5 +1,7 @@
 use aiz32core::peripheral::Peripheral;
+use aiz32core::snapshot::{SnapshotError, StateReader, StateWriter};
 
+#[derive(Default)]
 pub struct Console {
     last_value: u32,
 }
@@ -12,21 +14,28 @@ impl Console {
 
 impl Peripheral for Console {
     fn handles_port(&self, port: u16) -> bool {
-        matches!(port, 0x00 | 0x01 | 0x02 | 0x03)
+        matches!(port, 0x00..=0x03)
     }
 
     fn read(&self, port: u16) -> u32 {
-        let last_value = match port {
+        match port {
             0x02 => 0x100,
             0x03 => 0x101,
             _ => self.last_value,
-        };
-
-        last_value
+        }
     }
 
     fn write(&mut self, port: u16, value: u32) {
         self.last_value = value;
         println!("[Console] OUT a puerto 0x{:X}: {}", port, value);
     }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_u32(self.last_value);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.last_value = input.read_u32()?;
+        Ok(())
+    }
 }
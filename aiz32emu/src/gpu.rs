@@ -1,4 +1,8 @@
-use aiz32core::peripheral::Peripheral;
+use aiz32core::peripheral::{Mmio, Peripheral};
+use aiz32core::snapshot::{SnapshotError, StateReader, StateWriter};
+
+/// Dirección donde se mapea el back buffer, un píxel `0x00RRGGBB` por palabra.
+pub const GPU_FRAMEBUFFER_BASE: u32 = 0xA000_0000;
 
 pub struct GPU {
     pub width: usize,
@@ -42,6 +46,11 @@ impl GPU {
         }
     }
 
+    /// Bytes que ocupa el back buffer mapeado en memoria.
+    pub fn framebuffer_size(&self) -> u32 {
+        (self.back_buffer.len() * 4) as u32
+    }
+
     pub fn framebuffer(&self) -> &[u32] {
         &self.front_buffer
     }
@@ -249,4 +258,77 @@ impl Peripheral for GPU {
             _ => {}
         }
     }
+
+    // La ROM de tiles no se guarda: viene del archivo que se carga al arrancar
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_u32(self.width as u32);
+        out.write_u32(self.height as u32);
+        out.write_words(&self.front_buffer);
+        out.write_words(&self.back_buffer);
+        out.write_bool(self.frame_dirty);
+        for reg in [
+            self.command,
+            self.x,
+            self.y,
+            self.color,
+            self.color_end,
+            self.color_mid,
+            self.tile_index,
+            self.angle,
+            self.w,
+            self.h,
+        ] {
+            out.write_u32(reg);
+        }
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        if input.read_u32()? as usize != self.width || input.read_u32()? as usize != self.height {
+            return Err(SnapshotError::Mismatch("GPU resolution"));
+        }
+        input.read_words_into(&mut self.front_buffer, "GPU front buffer")?;
+        input.read_words_into(&mut self.back_buffer, "GPU back buffer")?;
+        self.frame_dirty = input.read_bool()?;
+        for reg in [
+            &mut self.command,
+            &mut self.x,
+            &mut self.y,
+            &mut self.color,
+            &mut self.color_end,
+            &mut self.color_mid,
+            &mut self.tile_index,
+            &mut self.angle,
+            &mut self.w,
+            &mut self.h,
+        ] {
+            *reg = input.read_u32()?;
+        }
+        Ok(())
+    }
+}
+
+// Acceso lineal al back buffer. Los accesos de 8 y 16 bits tocan solo sus
+// bytes del píxel.
+impl Mmio for GPU {
+    fn mmio_read(&self, offset: u32, size: u32) -> u32 {
+        let pixel = self.back_buffer[(offset / 4) as usize];
+        let shift = (offset % 4) * 8;
+        match size {
+            4 => pixel,
+            _ => (pixel >> shift) & ((1 << (size * 8)) - 1),
+        }
+    }
+
+    fn mmio_write(&mut self, offset: u32, size: u32, value: u32) {
+        let pixel = &mut self.back_buffer[(offset / 4) as usize];
+        *pixel = match size {
+            4 => value,
+            _ => {
+                let shift = (offset % 4) * 8;
+                let mask = ((1 << (size * 8)) - 1) << shift;
+                (*pixel & !mask) | ((value << shift) & mask)
+            }
+        };
+        self.frame_dirty = true;
+    }
 }
@@ -1,19 +1,31 @@
+use aiz32core::interrupt::IrqLine;
 use aiz32core::peripheral::Peripheral;
+use aiz32core::snapshot::{SnapshotError, StateReader, StateWriter};
 use std::collections::VecDeque;
 
+#[derive(Default)]
 pub struct Keyboard {
     buffer: VecDeque<u8>,
+    irq: Option<IrqLine>,
 }
 
 impl Keyboard {
     pub fn new() -> Self {
         Self {
             buffer: VecDeque::new(),
+            irq: None,
         }
     }
 
+    pub fn connect_irq(&mut self, line: IrqLine) {
+        self.irq = Some(line);
+    }
+
     pub fn key_down(&mut self, key: u8) {
         self.buffer.push_back(key);
+        if let Some(irq) = &self.irq {
+            irq.raise();
+        }
     }
 
     pub fn key_up(&mut self, _key: u8) {
@@ -42,13 +54,18 @@ impl Peripheral for Keyboard {
     }
 
     fn write(&mut self, port: u16, value: u32) {
-        match port {
-            0x3000 => {
-                if value == 0 {
-                    self.buffer.pop_front();
-                }
-            }
-            _ => {}
+        if port == 0x3000 && value == 0 {
+            self.buffer.pop_front();
         }
     }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        let keys: Vec<u8> = self.buffer.iter().copied().collect();
+        out.write_bytes(&keys);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.buffer = input.read_bytes()?.iter().copied().collect();
+        Ok(())
+    }
 }
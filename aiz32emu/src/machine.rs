@@ -0,0 +1,157 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use aiz32core::cpu::CPU;
+use aiz32core::fault::CpuFault;
+use aiz32core::interrupt::IrqLine;
+use aiz32core::snapshot::SnapshotError;
+
+use crate::console::Console;
+use crate::gpu::{GPU, GPU_FRAMEBUFFER_BASE};
+use crate::keyboard::Keyboard;
+use crate::movie::{InputEvent, Movie, Player};
+use crate::timer::Timer;
+
+pub const IRQ_TIMER: u8 = 0;
+pub const IRQ_KEYBOARD: u8 = 1;
+pub const IRQ_VBLANK: u8 = 2;
+
+pub const FPS: u32 = 60;
+
+/// CPU con sus periféricos, avanzando por fotogramas de tiempo emulado.
+///
+/// Toda la entrada externa pasa por `input`, que la graba si hay una
+/// grabación activa. Durante una reproducción la entrada en vivo se ignora y
+/// los eventos de la película se entregan en su ciclo exacto.
+pub struct Machine {
+    pub cpu: CPU,
+    pub gpu: Rc<RefCell<GPU>>,
+    pub keyboard: Rc<RefCell<Keyboard>>,
+    timer: Rc<RefCell<Timer>>,
+    vblank: IrqLine,
+    frame_deadline: u64,
+    recording: Option<Movie>,
+    replay: Option<Player>,
+}
+
+impl Machine {
+    pub fn new(mut cpu: CPU, gpu: GPU) -> Self {
+        let gpu = Rc::new(RefCell::new(gpu));
+        let console = Rc::new(RefCell::new(Console::new()));
+
+        let keyboard = Rc::new(RefCell::new(Keyboard::new()));
+        keyboard
+            .borrow_mut()
+            .connect_irq(cpu.irq_line(IRQ_KEYBOARD));
+
+        let timer = Rc::new(RefCell::new(Timer::new()));
+        timer.borrow_mut().connect_irq(cpu.irq_line(IRQ_TIMER));
+
+        let vblank = cpu.irq_line(IRQ_VBLANK);
+
+        cpu.io.register_peripheral(console);
+        cpu.io.register_peripheral(gpu.clone());
+        let framebuffer_size = gpu.borrow().framebuffer_size();
+        cpu.mem
+            .map_mmio(GPU_FRAMEBUFFER_BASE, framebuffer_size, gpu.clone());
+        cpu.io.register_peripheral(keyboard.clone());
+        cpu.io.register_peripheral(timer.clone());
+
+        let frame_deadline = cpu.cycle_count;
+        Self {
+            cpu,
+            gpu,
+            keyboard,
+            timer,
+            vblank,
+            frame_deadline,
+            recording: None,
+            replay: None,
+        }
+    }
+
+    pub fn start_recording(&mut self) {
+        self.recording = Some(Movie::new(self.cpu.timing.clock_hz));
+    }
+
+    /// Termina la grabación y devuelve la película.
+    pub fn finish_recording(&mut self) -> Option<Movie> {
+        let mut movie = self.recording.take()?;
+        movie.end_cycle = self.cpu.cycle_count;
+        Some(movie)
+    }
+
+    pub fn start_replay(&mut self, movie: Movie) {
+        self.cpu.timing.clock_hz = movie.clock_hz;
+        self.replay = Some(Player::new(movie));
+    }
+
+    pub fn replay_finished(&self) -> bool {
+        self.replay
+            .as_ref()
+            .is_some_and(|player| player.finished(self.cpu.cycle_count))
+    }
+
+    pub fn input(&mut self, event: InputEvent) {
+        if self.replay.is_some() {
+            return;
+        }
+        if let Some(movie) = &mut self.recording {
+            movie.record(self.cpu.cycle_count, event);
+        }
+        self.apply(event);
+    }
+
+    fn apply(&self, event: InputEvent) {
+        match event {
+            InputEvent::KeyDown(key) => self.keyboard.borrow_mut().key_down(key),
+            InputEvent::KeyUp(key) => self.keyboard.borrow_mut().key_up(key),
+        }
+    }
+
+    /// Entrega los eventos de la película cuyo ciclo ya se alcanzó.
+    pub fn feed_replay(&mut self) {
+        while let Some(event) = self
+            .replay
+            .as_mut()
+            .and_then(|player| player.due(self.cpu.cycle_count))
+        {
+            self.apply(event);
+        }
+    }
+
+    /// Ejecuta los ciclos de un fotograma; lo que se pasa se descuenta del
+    /// siguiente. Al final presenta el framebuffer y señala VBLANK.
+    pub fn run_frame(&mut self) -> Result<(), CpuFault> {
+        self.frame_deadline += self.cpu.timing.cycles_per_frame(FPS);
+        while self.cpu.cycle_count < self.frame_deadline && !self.cpu.halted {
+            self.feed_replay();
+
+            let cycles_before = self.cpu.cycle_count;
+            self.cpu.step()?;
+            self.timer
+                .borrow_mut()
+                .tick(self.cpu.cycle_count - cycles_before);
+        }
+
+        self.gpu.borrow_mut().present();
+        self.vblank.raise();
+        Ok(())
+    }
+
+    pub fn save_state_file(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        self.cpu.save_state_file(path)
+    }
+
+    /// Hay una grabación o reproducción en curso. Cargar un estado la rompería.
+    pub fn movie_active(&self) -> bool {
+        self.recording.is_some() || self.replay.is_some()
+    }
+
+    pub fn load_state_file(&mut self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        self.cpu.load_state_file(path)?;
+        self.frame_deadline = self.cpu.cycle_count;
+        Ok(())
+    }
+}
@@ -1,24 +1,46 @@
 pub mod console;
 pub mod gpu;
 pub mod keyboard;
+pub mod machine;
+pub mod movie;
+pub mod timer;
 
-use aiz32core::{alu::Flags, cpu::CPU};
+use aiz32core::memory::{MemoryBus, MemoryMap};
+use aiz32core::{alu::Flags, cpu::CPU, timing::DEFAULT_CLOCK_HZ};
 use sdl2::keyboard::Mod;
-use std::cell::RefCell;
 use std::env;
 use std::fs;
 use std::fs::File;
 use std::io::Read;
-use std::rc::Rc;
 use std::time::{Duration, Instant};
 
-use crate::console::Console;
 use crate::gpu::GPU;
-use crate::keyboard::Keyboard;
+use crate::machine::{FPS, Machine};
+use crate::movie::{InputEvent, Movie};
 
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::PixelFormatEnum;
 
+// F1..F8 cargan la ranura 1..8; con Shift guardan
+const SAVE_SLOT_KEYS: [Keycode; 8] = [
+    Keycode::F1,
+    Keycode::F2,
+    Keycode::F3,
+    Keycode::F4,
+    Keycode::F5,
+    Keycode::F6,
+    Keycode::F7,
+    Keycode::F8,
+];
+
+fn save_slot(key: Keycode) -> Option<usize> {
+    SAVE_SLOT_KEYS.iter().position(|&k| k == key).map(|i| i + 1)
+}
+
+fn state_path(program_path: &str, slot: usize) -> String {
+    format!("{}.state{}", program_path, slot)
+}
+
 fn load_gpu_rom(path: &str) -> Vec<u32> {
     let mut file = File::open(path).expect("No se pudo abrir el archivo ROM");
     let mut buf = Vec::new();
@@ -39,43 +61,143 @@ fn load_gpu_rom(path: &str) -> Vec<u32> {
         .collect()
 }
 
+// Dirección en decimal o en hexadecimal con prefijo 0x
+fn parse_addr(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// FNV-1a de 64 bits, para comparar reproducciones
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+fn print_usage(program: &str) {
+    eprintln!(
+        "Uso: {} [--record <pelicula> | --replay <pelicula> [--headless]] [--ram-base <dir>] [--rom-base <dir>] <binario> <ram_size> <sp_base> <debug> <gpu_width> <gpu_height> <gpu_rom> [clock_hz]",
+        program
+    );
+    eprintln!(
+        "Por defecto la RAM empieza en 0 y el binario se carga como ROM justo detrás; la CPU arranca al principio de la ROM."
+    );
+    eprintln!(
+        "Ejemplo: {} program.bin 65536 65535 0 640 480 tiles.rom",
+        program
+    );
+}
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 7 {
-        eprintln!(
-            "Uso: {} <binario> <ram_size> <sp_base> <debug> <gpu_width> <gpu_height> <gpu_rom>",
-            args[0]
-        );
-        eprintln!(
-            "Ejemplo: {} program.bin 65536 65535 0 640 480 tiles.rom",
-            args[0]
-        );
+    let mut args = env::args();
+    let program_name = args.next().unwrap_or_default();
+
+    let mut record_path = None;
+    let mut replay_path = None;
+    let mut headless = false;
+    let mut ram_base = None;
+    let mut rom_base = None;
+    let mut positional = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => record_path = args.next(),
+            "--replay" => replay_path = args.next(),
+            "--headless" => headless = true,
+            "--ram-base" => ram_base = args.next(),
+            "--rom-base" => rom_base = args.next(),
+            _ => positional.push(arg),
+        }
+    }
+
+    if positional.len() < 7 || (headless && replay_path.is_none()) {
+        print_usage(&program_name);
         return;
     }
 
-    let program_path = &args[1];
-    let ram_size: usize = args[2].parse().expect("RAM size inválido");
-    let sp_base: u32 = args[3].parse().expect("SP base inválida");
-    let debug: bool = args[4].parse::<u8>().unwrap_or(0) != 0;
+    let program_path = &positional[0];
+    let ram_size: usize = positional[1].parse().expect("RAM size inválido");
+    let sp_base: u32 = positional[2].parse().expect("SP base inválida");
+    let debug: bool = positional[3].parse::<u8>().unwrap_or(0) != 0;
+
+    let gpu_width: usize = positional[4].parse().expect("GPU width inválido");
+    let gpu_height: usize = positional[5].parse().expect("GPU height inválido");
+    let gpu_rom_path = &positional[6];
+    let clock_hz: u64 = positional
+        .get(7)
+        .map_or(DEFAULT_CLOCK_HZ, |s| s.parse().expect("clock_hz inválido"));
 
-    let gpu_width: usize = args[5].parse().expect("GPU width inválido");
-    let gpu_height: usize = args[6].parse().expect("GPU height inválido");
-    let gpu_rom_path = &args[7];
+    let ram_base = ram_base.map_or(0, |s| parse_addr(&s).expect("Base de RAM inválida"));
+    let rom_base = rom_base.map_or(ram_base.wrapping_add(ram_size as u32), |s| {
+        parse_addr(&s).expect("Base de ROM inválida")
+    });
 
     let program = fs::read(program_path).expect("No se pudo leer el archivo binario");
-    let pc_dir = ram_size as u32;
-    let mut cpu = CPU::new(ram_size, program.clone(), sp_base, pc_dir);
+    let mut map = MemoryMap::new(rom_base);
+    map.add_ram(ram_base, ram_size as u32);
+    map.add_rom(rom_base, program);
+    let mut cpu = CPU::with_memory(MemoryBus::from_map(map), sp_base);
+    cpu.timing.clock_hz = clock_hz;
 
     let gpu_rom = load_gpu_rom(gpu_rom_path);
-    let gpu = Rc::new(RefCell::new(GPU::new(gpu_width, gpu_height, gpu_rom)));
+    let gpu = GPU::new(gpu_width, gpu_height, gpu_rom);
+
+    let mut machine = Machine::new(cpu, gpu);
+
+    if let Some(path) = &replay_path {
+        let movie = Movie::load(path).expect("No se pudo leer la película");
+        machine.start_replay(movie);
+    } else if record_path.is_some() {
+        machine.start_recording();
+    }
 
-    let console = Rc::new(RefCell::new(Console::new()));
+    if headless {
+        run_headless(&mut machine);
+    } else {
+        run_window(&mut machine, program_path, gpu_width, gpu_height, debug);
+    }
 
-    let keyboard = Rc::new(RefCell::new(Keyboard::new()));
-    cpu.io.register_peripheral(console.clone());
-    cpu.io.register_peripheral(gpu.clone());
-    cpu.io.register_peripheral(keyboard.clone());
+    if let Some(path) = &record_path
+        && let Some(movie) = machine.finish_recording()
+    {
+        movie.save(path).expect("No se pudo escribir la película");
+        println!("Película guardada en {}", path);
+    }
+}
 
+// Reproduce la película sin ventana y resume el estado final
+fn run_headless(machine: &mut Machine) {
+    while !machine.replay_finished() && !machine.cpu.halted {
+        if let Err(fault) = machine.run_frame() {
+            eprintln!("Fallo de CPU: {}", fault);
+            break;
+        }
+    }
+    machine.feed_replay();
+
+    let cpu = &machine.cpu;
+    let framebuffer_hash = fnv1a(
+        machine
+            .gpu
+            .borrow()
+            .framebuffer()
+            .iter()
+            .flat_map(|px| px.to_le_bytes()),
+    );
+    println!("ciclos: {}", cpu.cycle_count);
+    println!("PC: 0x{:08X}   SP: 0x{:08X}", cpu.regs.pc(), cpu.regs.sp());
+    println!("framebuffer: {:016X}", framebuffer_hash);
+    println!("estado: {:016X}", fnv1a(cpu.save_state()));
+}
+
+fn run_window(
+    machine: &mut Machine,
+    program_path: &str,
+    gpu_width: usize,
+    gpu_height: usize,
+    debug: bool,
+) {
     // Inicialización SDL
     let sdl = sdl2::init().unwrap();
     let video_subsystem = sdl.video().unwrap();
@@ -109,20 +231,19 @@ fn main() {
         .unwrap();
 
     let mut event_pump = sdl.event_pump().unwrap();
-    let target_frame_duration = Duration::from_secs_f64(1.0 / 60.0);
+    let target_frame_duration = Duration::from_secs_f64(1.0 / FPS as f64);
     let mut last_frame_time = Instant::now();
 
     // ciclo principal
-    while !cpu.halted {
-        for _ in 0..1_000 {
-            if cpu.halted {
-                break;
-            }
-            cpu.step();
+    while !machine.cpu.halted {
+        if let Err(fault) = machine.run_frame() {
+            eprintln!("Fallo de CPU: {}", fault);
+            machine.cpu.halted = true;
+            break;
         }
 
         {
-            let mut gpu_borrow = gpu.borrow_mut();
+            let gpu_borrow = machine.gpu.borrow();
 
             let fb = gpu_borrow.framebuffer();
             texture
@@ -132,8 +253,6 @@ fn main() {
             canvas.clear();
             canvas.copy(&texture, None, None).unwrap();
             canvas.present();
-
-            gpu_borrow.present();
         }
 
         let now = Instant::now();
@@ -159,8 +278,25 @@ fn main() {
                     ..
                 } => {
                     let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    if let Some(slot) = save_slot(k) {
+                        let path = state_path(program_path, slot);
+                        if shift {
+                            match machine.save_state_file(&path) {
+                                Ok(()) => println!("Estado guardado en la ranura {}", slot),
+                                Err(err) => eprintln!("No se pudo guardar el estado: {}", err),
+                            }
+                        } else if machine.movie_active() {
+                            eprintln!("No se puede cargar un estado mientras se graba o reproduce");
+                        } else {
+                            match machine.load_state_file(&path) {
+                                Ok(()) => println!("Estado cargado de la ranura {}", slot),
+                                Err(err) => eprintln!("No se pudo cargar el estado: {}", err),
+                            }
+                        }
+                        continue;
+                    }
                     let key = map_keycode(k, shift);
-                    keyboard.borrow_mut().key_down(key);
+                    machine.input(InputEvent::KeyDown(key));
                 }
                 Event::KeyUp {
                     keycode: Some(k),
@@ -169,7 +305,7 @@ fn main() {
                 } => {
                     let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
                     let key = map_keycode(k, shift);
-                    keyboard.borrow_mut().key_up(key);
+                    machine.input(InputEvent::KeyUp(key));
                 }
 
                 _ => {}
@@ -177,6 +313,7 @@ fn main() {
         }
 
         if debug {
+            let cpu = &machine.cpu;
             let flags = Flags::from_u32(cpu.regs.flags());
 
             println!("================ CPU DUMP ================");
@@ -0,0 +1,160 @@
+//! Grabación y reproducción determinista de la entrada.
+//!
+//! Cada evento externo se marca con el `cycle_count` en el que se entregó a
+//! la máquina. Al reproducir se entrega justo antes del primer paso de CPU con
+//! ese ciclo, así que dos reproducciones de la misma película dejan la máquina
+//! en el mismo estado bit a bit.
+//!
+//! Formato de texto, una entrada por línea:
+//!
+//! ```text
+//! AIZ32MOVIE 1
+//! clock_hz 10000000
+//! 166666 down 97
+//! 333333 up 97
+//! 500000 end
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MOVIE_HEADER: &str = "AIZ32MOVIE 1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    KeyDown(u8),
+    KeyUp(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimedEvent {
+    pub cycle: u64,
+    pub event: InputEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    /// La planificación de fotogramas depende del reloj, así que se reproduce
+    /// con el mismo con el que se grabó.
+    pub clock_hz: u64,
+    pub events: Vec<TimedEvent>,
+    /// Ciclo en el que terminó la grabación.
+    pub end_cycle: u64,
+}
+
+impl Movie {
+    pub fn new(clock_hz: u64) -> Self {
+        Self {
+            clock_hz,
+            events: Vec::new(),
+            end_cycle: 0,
+        }
+    }
+
+    pub fn record(&mut self, cycle: u64, event: InputEvent) {
+        debug_assert!(
+            self.events.last().is_none_or(|last| last.cycle <= cycle),
+            "eventos fuera de orden"
+        );
+        self.events.push(TimedEvent { cycle, event });
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\nclock_hz {}\n", MOVIE_HEADER, self.clock_hz);
+        for ev in &self.events {
+            let line = match ev.event {
+                InputEvent::KeyDown(key) => format!("{} down {}\n", ev.cycle, key),
+                InputEvent::KeyUp(key) => format!("{} up {}\n", ev.cycle, key),
+            };
+            text.push_str(&line);
+        }
+        text.push_str(&format!("{} end\n", self.end_cycle));
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l.trim()));
+
+        match lines.next() {
+            Some((_, MOVIE_HEADER)) => {}
+            _ => return Err("cabecera de película inválida".to_string()),
+        }
+
+        let clock_hz = match lines.next() {
+            Some((n, line)) => line
+                .strip_prefix("clock_hz ")
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| format!("línea {}: se esperaba clock_hz", n))?,
+            None => return Err("falta clock_hz".to_string()),
+        };
+
+        let mut movie = Movie::new(clock_hz);
+        for (n, line) in lines {
+            if line.is_empty() {
+                continue;
+            }
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let cycle: u64 = parts[0]
+                .parse()
+                .map_err(|_| format!("línea {}: ciclo inválido", n))?;
+            if movie.events.last().is_some_and(|last| last.cycle > cycle) {
+                return Err(format!("línea {}: eventos fuera de orden", n));
+            }
+            let key = || -> Result<u8, String> {
+                parts
+                    .get(2)
+                    .and_then(|k| k.parse().ok())
+                    .ok_or_else(|| format!("línea {}: tecla inválida", n))
+            };
+            match parts.get(1) {
+                Some(&"down") => movie.record(cycle, InputEvent::KeyDown(key()?)),
+                Some(&"up") => movie.record(cycle, InputEvent::KeyUp(key()?)),
+                Some(&"end") => {
+                    movie.end_cycle = cycle;
+                    return Ok(movie);
+                }
+                _ => return Err(format!("línea {}: evento desconocido", n)),
+            }
+        }
+        Err("falta la línea end".to_string())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))
+    }
+}
+
+/// Entrega los eventos de una película según avanzan los ciclos.
+pub struct Player {
+    movie: Movie,
+    next: usize,
+}
+
+impl Player {
+    pub fn new(movie: Movie) -> Self {
+        Self { movie, next: 0 }
+    }
+
+    /// Siguiente evento pendiente cuyo ciclo ya se alcanzó.
+    pub fn due(&mut self, cycle: u64) -> Option<InputEvent> {
+        let ev = self.movie.events.get(self.next)?;
+        if ev.cycle <= cycle {
+            self.next += 1;
+            Some(ev.event)
+        } else {
+            None
+        }
+    }
+
+    /// Se alcanzó el ciclo en el que terminó la grabación. Los eventos de ese
+    /// mismo ciclo se entregan con `due` sin ejecutar más instrucciones.
+    pub fn finished(&self, cycle: u64) -> bool {
+        cycle >= self.movie.end_cycle
+    }
+}
@@ -0,0 +1,77 @@
+use aiz32core::interrupt::IrqLine;
+use aiz32core::peripheral::Peripheral;
+use aiz32core::snapshot::{SnapshotError, StateReader, StateWriter};
+
+/// Temporizador de ciclos: cuando está habilitado cuenta ciclos de CPU y
+/// solicita su IRQ cada `period` ciclos.
+#[derive(Default)]
+pub struct Timer {
+    period: u32,
+    counter: u32,
+    enabled: bool,
+    irq: Option<IrqLine>,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn connect_irq(&mut self, line: IrqLine) {
+        self.irq = Some(line);
+    }
+
+    pub fn tick(&mut self, cycles: u64) {
+        if !self.enabled || self.period == 0 {
+            return;
+        }
+
+        let total = self.counter as u64 + cycles;
+        if total >= self.period as u64
+            && let Some(irq) = &self.irq
+        {
+            irq.raise();
+        }
+        self.counter = (total % self.period as u64) as u32;
+    }
+}
+
+impl Peripheral for Timer {
+    fn handles_port(&self, port: u16) -> bool {
+        (0x4000..=0x4002).contains(&port)
+    }
+
+    fn read(&self, port: u16) -> u32 {
+        match port {
+            0x4000 => self.period,
+            0x4001 => self.enabled as u32,
+            0x4002 => self.counter,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, port: u16, value: u32) {
+        match port {
+            0x4000 => {
+                self.period = value;
+                self.counter = 0;
+            }
+            0x4001 => self.enabled = value & 1 != 0,
+            0x4002 => self.counter = value,
+            _ => {}
+        }
+    }
+
+    fn save_state(&self, out: &mut StateWriter) {
+        out.write_u32(self.period);
+        out.write_u32(self.counter);
+        out.write_bool(self.enabled);
+    }
+
+    fn load_state(&mut self, input: &mut StateReader) -> Result<(), SnapshotError> {
+        self.period = input.read_u32()?;
+        self.counter = input.read_u32()?;
+        self.enabled = input.read_bool()?;
+        Ok(())
+    }
+}